@@ -0,0 +1,24 @@
+//! Connect to a desk, print its height and disconnect
+//!
+//! ```bash
+//! cargo run --example connect_and_query -- <desk id or name>
+//! ```
+
+use std::env;
+
+use uplift_lib::desk::Desk;
+use uplift_lib::output;
+
+#[tokio::main]
+async fn main() -> Result<(), anyhow::Error> {
+    // without a filter we connect to the first desk we find
+    let filter = env::args().nth(1);
+    let desk = Desk::new(filter.as_deref()).await?;
+
+    let height = desk.query_height().await?;
+    println!("{}: {}", desk.id(), output::height(height));
+
+    desk.disconnect().await?;
+
+    Ok(())
+}
@@ -0,0 +1,229 @@
+//! A pretend desk built on [uplift_lib::protocol] and [DeskControl], which doesn't need Bluetooth.
+//! It parses the packets we'd write to a real desk and keeps track of where the desk would be.
+//! Anything written against [DeskControl] runs on it or a real [uplift_lib::desk::Desk], which
+//! makes it a good starting point for bridges or for trying out automation without a desk
+//!
+//! ```bash
+//! cargo run --example mock_desk
+//! ```
+
+use std::sync::Mutex;
+
+use anyhow::{anyhow, bail, ensure};
+
+use uplift_lib::desk::{
+    DecoderKind, DeskControl, ForcePolicy, MoveProfile, Nudge, Target, Tolerance, AVG_MID_HEIGHT,
+    AVG_SITTING_HEIGHT, AVG_STANDING_HEIGHT, MAX_PHYSICAL_HEIGHT, MIN_PHYSICAL_HEIGHT,
+};
+use uplift_lib::output;
+use uplift_lib::protocol::{self, DOWN, QUERY, SAVE_SIT, SAVE_STAND, SIT, STAND, STOP, UP};
+
+/// Where the desk is and the heights saved on its keypad
+struct MockDesk {
+    state: Mutex<MockState>,
+}
+
+struct MockState {
+    height: isize,
+    sit: isize,
+    stand: isize,
+}
+
+impl MockDesk {
+    fn new() -> Self {
+        MockDesk {
+            state: Mutex::new(MockState {
+                height: AVG_SITTING_HEIGHT,
+                sit: AVG_SITTING_HEIGHT,
+                stand: AVG_STANDING_HEIGHT,
+            }),
+        }
+    }
+
+    /// Handle one packet, returning the raw height bytes a real desk would notify us with
+    fn write(&self, packet: &[u8]) -> Result<(u8, u8), anyhow::Error> {
+        let frame = protocol::parse_frame(packet)
+            .ok_or_else(|| anyhow!("Malformed packet: {packet:x?}"))?;
+        ensure!(frame.has_valid_checksum(), "Bad checksum: {packet:x?}");
+
+        let mut state = self.state.lock().expect("Mock desk lock poisoned");
+        match frame.command {
+            UP => state.height += 1,
+            DOWN => state.height -= 1,
+            SIT => state.height = state.sit,
+            STAND => state.height = state.stand,
+            SAVE_SIT => state.sit = state.height,
+            SAVE_STAND => state.stand = state.height,
+            QUERY | STOP => {}
+            command => bail!("Unknown command {command:x}"),
+        }
+        state.height = state.height.clamp(MIN_PHYSICAL_HEIGHT, MAX_PHYSICAL_HEIGHT);
+
+        // the low byte is our offset from the bottom of the range
+        let low = (state.height - MIN_PHYSICAL_HEIGHT) as u8;
+        Ok((low, low))
+    }
+
+    /// Write a packet and decode the notification it caused, like a real desk's notifications
+    fn send(&self, packet: &[u8]) -> Result<isize, anyhow::Error> {
+        let last_height = self.height();
+        let raw = self.write(packet)?;
+
+        Ok(DecoderKind::default().decoder().decode(raw, last_height))
+    }
+}
+
+impl DeskControl for MockDesk {
+    fn id(&self) -> &str {
+        "mock"
+    }
+
+    fn height(&self) -> isize {
+        self.state.lock().expect("Mock desk lock poisoned").height
+    }
+
+    async fn query_height(&self) -> Result<isize, anyhow::Error> {
+        self.send(&protocol::QUERY_PACKET)
+    }
+
+    async fn nudge(&self, direction: Nudge) -> Result<isize, anyhow::Error> {
+        match direction {
+            Nudge::Up => self.send(&protocol::UP_PACKET),
+            Nudge::Down => self.send(&protocol::DOWN_PACKET),
+        }
+    }
+
+    async fn move_to_with(
+        &self,
+        target: isize,
+        _profile: MoveProfile,
+        tolerance: Tolerance,
+    ) -> Result<isize, anyhow::Error> {
+        let mut height = self.query_height().await?;
+        while !tolerance.reached(height, target) {
+            let direction = if height < target {
+                Nudge::Up
+            } else {
+                Nudge::Down
+            };
+            let next_height = self.nudge(direction).await?;
+            if next_height == height {
+                bail!("The desk stopped moving at {}", output::height(height));
+            }
+            height = next_height;
+        }
+
+        Ok(height)
+    }
+
+    async fn force(&self, target: Target, _policy: ForcePolicy) -> Result<isize, anyhow::Error> {
+        let stand = match target {
+            Target::Sit => false,
+            Target::Stand => true,
+            Target::Toggle => self.height() < AVG_MID_HEIGHT,
+        };
+        if stand {
+            self.send(&protocol::STAND_PACKET)
+        } else {
+            self.send(&protocol::SIT_PACKET)
+        }
+    }
+}
+
+/// Stand, move up an inch and sit back down, returning every height along the way. Nothing here
+/// knows it's talking to a mock.
+async fn raise_standing_height(desk: &impl DeskControl) -> Result<Vec<isize>, anyhow::Error> {
+    let mut heights = vec![desk.query_height().await?];
+    let standing = desk.force(Target::Stand, ForcePolicy::default()).await?;
+    heights.push(standing);
+    heights.push(
+        desk.move_to_with(standing + 10, MoveProfile::default(), Tolerance(0))
+            .await?,
+    );
+    heights.push(desk.force(Target::Sit, ForcePolicy::default()).await?);
+
+    Ok(heights)
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<(), anyhow::Error> {
+    let desk = MockDesk::new();
+    for height in raise_standing_height(&desk).await? {
+        println!("{}", output::height(height));
+    }
+
+    // save our new standing height on the keypad, the packets are the same as for a real desk
+    desk.write(&protocol::STAND_PACKET)?;
+    for _ in 0..10 {
+        desk.write(&protocol::UP_PACKET)?;
+    }
+    desk.write(&protocol::SAVE_STAND_PACKET)?;
+    desk.write(&protocol::SIT_PACKET)?;
+    let height = desk.force(Target::Toggle, ForcePolicy::default()).await?;
+    println!("{}", output::height(height));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn controls_the_mock_like_a_desk() {
+        let desk = MockDesk::new();
+
+        assert_eq!(
+            raise_standing_height(&desk).await.unwrap(),
+            [
+                AVG_SITTING_HEIGHT,
+                AVG_STANDING_HEIGHT,
+                AVG_STANDING_HEIGHT + 10,
+                AVG_SITTING_HEIGHT
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn saved_presets_are_where_we_sit_and_stand() {
+        let desk = MockDesk::new();
+        desk.move_to_with(300, MoveProfile::default(), Tolerance(0))
+            .await
+            .unwrap();
+        desk.write(&protocol::SAVE_SIT_PACKET).unwrap();
+        desk.force(Target::Stand, ForcePolicy::default())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            desk.force(Target::Toggle, ForcePolicy::default())
+                .await
+                .unwrap(),
+            300
+        );
+    }
+
+    #[tokio::test]
+    async fn stops_at_the_top_of_the_range() {
+        let desk = MockDesk::new();
+        let e = desk
+            .move_to_with(
+                MAX_PHYSICAL_HEIGHT + 10,
+                MoveProfile::default(),
+                Tolerance(0),
+            )
+            .await
+            .unwrap_err();
+
+        assert!(e.to_string().starts_with("The desk stopped moving"), "{e}");
+        assert_eq!(desk.height(), MAX_PHYSICAL_HEIGHT);
+    }
+
+    #[test]
+    fn rejects_bad_checksums() {
+        let mut packet = protocol::UP_PACKET;
+        packet[4] ^= 0xff;
+
+        assert!(MockDesk::new().write(&packet).is_err());
+    }
+}
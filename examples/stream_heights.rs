@@ -0,0 +1,42 @@
+//! Print every height the desk reports and every change in our connection until the desk
+//! disconnects or we've seen enough heights
+//!
+//! ```bash
+//! cargo run --example stream_heights -- 100
+//! ```
+
+use std::env;
+
+use futures::StreamExt;
+
+use uplift_lib::desk::{ConnectionState, Desk};
+
+#[tokio::main]
+async fn main() -> Result<(), anyhow::Error> {
+    let limit = env::args()
+        .nth(1)
+        .map(|limit| limit.parse())
+        .transpose()?
+        .unwrap_or(20);
+    let desk = Desk::new(None).await?;
+
+    let mut heights = desk.subscribe_height().take(limit);
+    let mut connection = desk.subscribe_connection();
+    loop {
+        tokio::select! {
+            Some(update) = heights.next() => {
+                let (low, high) = update.raw;
+                println!("height: {} ({low:x},{high:x})", update.height);
+            }
+            Some(state) = connection.next() => {
+                println!("connection: {state}");
+                if state == ConnectionState::Disconnected {
+                    break;
+                }
+            }
+            else => break,
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,21 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use uplift_lib::protocol;
+
+fuzz_target!(|data: &[u8]| {
+    // malformed notifications should be rejected, never panic
+    if let Some((low, high)) = protocol::decode_height(data) {
+        assert_eq!((low, high), (data[5], data[7]));
+    }
+
+    if let Some(frame) = protocol::parse_frame(data) {
+        assert_eq!(frame.payload.len(), data[3] as usize);
+    }
+
+    if let [command, ..] = data {
+        let packet = protocol::command_packet(*command);
+        assert_eq!(packet[4], protocol::checksum(&packet[2..4]));
+        assert!(protocol::parse_frame(&packet).is_some_and(|frame| frame.has_valid_checksum()));
+    }
+});
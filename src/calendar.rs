@@ -0,0 +1,469 @@
+//! Days of the week and times of day in a time zone, used to decide when the desk may move
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context};
+use jiff::civil::{Date, Time, Weekday};
+use jiff::tz::{AmbiguousOffset, TimeZone};
+use jiff::{Timestamp, Zoned};
+use serde::{Deserialize, Serialize};
+
+/// A daily window of local time
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TimeWindow {
+    pub start: Time,
+    pub end: Time,
+}
+
+impl TimeWindow {
+    /// Whether this window starts on one day and ends on the next
+    pub fn is_overnight(&self) -> bool {
+        self.end < self.start
+    }
+
+    /// Whether the local `time` is inside this window
+    pub fn contains(&self, time: Time) -> bool {
+        if self.is_overnight() {
+            time >= self.start || time < self.end
+        } else {
+            self.start <= time && time < self.end
+        }
+    }
+
+    /// How long until this window ends if `now` is inside it. This is real time, so a DST change
+    /// inside the window makes it an hour longer or shorter than the clock suggests.
+    pub fn remaining(&self, now: &Zoned) -> Option<Duration> {
+        if !self.contains(now.time()) {
+            return None;
+        }
+
+        let date = if self.is_overnight() && now.time() >= self.start {
+            now.date().tomorrow().ok()?
+        } else {
+            now.date()
+        };
+        // an end the clock passes twice is the second time, we're back in the window after the first
+        let end = instant(now.time_zone(), date, self.end, false)?;
+
+        Some(
+            end.timestamp()
+                .duration_since(now.timestamp())
+                .unsigned_abs(),
+        )
+    }
+}
+
+/// When the clock in `time_zone` first reads `time` on `date`. A time skipped by a DST gap is
+/// reached as the gap ends, and a time repeated by a DST fold is its `earliest` or its last instant.
+fn instant(time_zone: &TimeZone, date: Date, time: Time, earliest: bool) -> Option<Zoned> {
+    let datetime = date.to_datetime(time);
+    let ambiguous = time_zone.to_ambiguous_zoned(datetime);
+    match ambiguous.offset() {
+        AmbiguousOffset::Unambiguous { .. } => ambiguous.compatible().ok(),
+        AmbiguousOffset::Gap { after, .. } => {
+            // read with the offset after the gap our time is still before it, so the next
+            // transition is the end of the gap
+            let before_gap = after.to_timestamp(datetime).ok()?;
+            let transition = time_zone.following(before_gap).next()?;
+            Some(transition.timestamp().to_zoned(time_zone.clone()))
+        }
+        AmbiguousOffset::Fold { .. } if earliest => ambiguous.earlier().ok(),
+        AmbiguousOffset::Fold { .. } => ambiguous.later().ok(),
+    }
+}
+
+impl FromStr for TimeWindow {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s
+            .split_once('-')
+            .ok_or_else(|| anyhow!("Expected a window like `12:00-13:00` but got `{s}`"))?;
+        let parse = |time: &str| {
+            time.trim()
+                .parse::<Time>()
+                .with_context(|| format!("Invalid time `{time}` in `{s}`"))
+        };
+
+        Ok(TimeWindow {
+            start: parse(start)?,
+            end: parse(end)?,
+        })
+    }
+}
+
+impl TryFrom<String> for TimeWindow {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<TimeWindow> for String {
+    fn from(window: TimeWindow) -> Self {
+        window.to_string()
+    }
+}
+
+impl fmt::Display for TimeWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:02}:{:02}-{:02}:{:02}",
+            self.start.hour(),
+            self.start.minute(),
+            self.end.hour(),
+            self.end.minute()
+        )
+    }
+}
+
+const WEEKDAY_NAMES: [&str; 7] = [
+    "monday",
+    "tuesday",
+    "wednesday",
+    "thursday",
+    "friday",
+    "saturday",
+    "sunday",
+];
+
+/// A set of days of the week, parsed from `weekdays`, `weekends`, `daily`, `mon-fri` or `mon,wed,fri`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Weekdays([bool; 7]);
+
+impl Weekdays {
+    pub const ALL: Weekdays = Weekdays([true; 7]);
+
+    pub fn contains(&self, day: Weekday) -> bool {
+        self.0[day.to_monday_zero_offset() as usize]
+    }
+}
+
+impl Default for Weekdays {
+    fn default() -> Self {
+        Weekdays::ALL
+    }
+}
+
+fn parse_weekday(name: &str) -> Result<Weekday, anyhow::Error> {
+    let name = name.trim().to_lowercase();
+    WEEKDAY_NAMES
+        .iter()
+        // `mon`, `tues` and `monday` all work
+        .position(|day| name.len() >= 3 && day.starts_with(&name))
+        .map(|offset| Weekday::from_monday_zero_offset(offset as i8).expect("offset is 0..7"))
+        .ok_or_else(|| {
+            anyhow!(
+                "Unknown day `{name}`, expected one of {}",
+                WEEKDAY_NAMES.join(", ")
+            )
+        })
+}
+
+impl FromStr for Weekdays {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "daily" => return Ok(Weekdays::ALL),
+            "weekdays" => return "mon-fri".parse(),
+            "weekends" => return "sat,sun".parse(),
+            _ => {}
+        }
+
+        let mut days = [false; 7];
+        for part in s.split(',').filter(|part| !part.trim().is_empty()) {
+            let (mut day, last) = match part.split_once('-') {
+                // ranges can wrap around the weekend, ie: `fri-mon`
+                Some((first, last)) => (parse_weekday(first)?, parse_weekday(last)?),
+                None => (parse_weekday(part)?, parse_weekday(part)?),
+            };
+            days[day.to_monday_zero_offset() as usize] = true;
+            while day != last {
+                day = day.next();
+                days[day.to_monday_zero_offset() as usize] = true;
+            }
+        }
+        if !days.contains(&true) {
+            bail!("Expected at least one day but got `{s}`");
+        }
+
+        Ok(Weekdays(days))
+    }
+}
+
+/// When the desk is allowed to move: some days of the week, optionally only between some hours,
+/// in a time zone. The hours follow the local clock so they stay put across DST changes.
+#[derive(Clone, Debug)]
+pub struct ActiveHours {
+    pub days: Weekdays,
+    /// Windows that end before they start run overnight and belong to the day they start on
+    pub hours: Option<TimeWindow>,
+    pub time_zone: TimeZone,
+}
+
+impl ActiveHours {
+    pub fn contains(&self, now: &Zoned) -> bool {
+        let Some(hours) = self.hours else {
+            return self.days.contains(now.weekday());
+        };
+        if !hours.contains(now.time()) {
+            return false;
+        }
+
+        let day = if hours.is_overnight() && now.time() < hours.end {
+            now.weekday().previous()
+        } else {
+            now.weekday()
+        };
+        self.days.contains(day)
+    }
+
+    /// How long from `now` until we're next active, or `None` if we're active now
+    pub fn until_active(&self, now: Timestamp) -> Option<Duration> {
+        let now = now.to_zoned(self.time_zone.clone());
+        if self.contains(&now) {
+            return None;
+        }
+
+        let start = self.hours.map_or(Time::midnight(), |hours| hours.start);
+        let mut date = now.date();
+        // a week and a day always reaches one of our days, we never have none
+        for _ in 0..=7 {
+            if self.days.contains(date.weekday()) {
+                let begins = instant(&self.time_zone, date, start, true)?;
+                if begins > now {
+                    return Some(
+                        begins
+                            .timestamp()
+                            .duration_since(now.timestamp())
+                            .unsigned_abs(),
+                    );
+                }
+            }
+            date = date.tomorrow().ok()?;
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use jiff::civil::DateTime;
+    use jiff::SignedDuration;
+
+    use super::*;
+
+    fn new_york() -> TimeZone {
+        TimeZone::get("America/New_York").expect("The tz database has New York")
+    }
+
+    /// A local time in New York, `2026-03-08T01:00`
+    fn at(datetime: &str) -> Zoned {
+        datetime
+            .parse::<DateTime>()
+            .unwrap()
+            .to_zoned(new_york())
+            .unwrap()
+    }
+
+    fn hours(hours: f64) -> Duration {
+        Duration::from_secs_f64(hours * 60.0 * 60.0)
+    }
+
+    fn active(days: &str, hours: Option<&str>) -> ActiveHours {
+        ActiveHours {
+            days: days.parse().unwrap(),
+            hours: hours.map(|hours| hours.parse().unwrap()),
+            time_zone: new_york(),
+        }
+    }
+
+    #[test]
+    fn parses_days() {
+        assert_eq!("daily".parse::<Weekdays>().unwrap(), Weekdays::ALL);
+        assert_eq!(
+            "weekdays".parse::<Weekdays>().unwrap(),
+            "mon,tue,wed,thu,fri".parse().unwrap()
+        );
+        // ranges wrap around the weekend
+        assert_eq!(
+            "fri-mon".parse::<Weekdays>().unwrap(),
+            "fri,sat,sun,mon".parse().unwrap()
+        );
+        assert!("".parse::<Weekdays>().is_err());
+        assert!("mo".parse::<Weekdays>().is_err());
+    }
+
+    #[test]
+    fn contains_times() {
+        let day: TimeWindow = "09:00-17:00".parse().unwrap();
+        assert!(day.contains(Time::constant(9, 0, 0, 0)));
+        assert!(!day.contains(Time::constant(17, 0, 0, 0)));
+        assert!(!day.contains(Time::constant(3, 0, 0, 0)));
+
+        let night: TimeWindow = "22:00-06:00".parse().unwrap();
+        assert!(night.is_overnight());
+        assert!(night.contains(Time::constant(23, 0, 0, 0)));
+        assert!(night.contains(Time::constant(5, 59, 0, 0)));
+        assert!(!night.contains(Time::constant(12, 0, 0, 0)));
+    }
+
+    #[test]
+    fn remaining() {
+        let day: TimeWindow = "09:00-17:00".parse().unwrap();
+        assert_eq!(day.remaining(&at("2026-10-16T12:30")), Some(hours(4.5)));
+        assert_eq!(day.remaining(&at("2026-10-16T18:00")), None);
+
+        let night: TimeWindow = "22:00-07:00".parse().unwrap();
+        assert_eq!(night.remaining(&at("2026-10-16T23:00")), Some(hours(8.0)));
+        assert_eq!(night.remaining(&at("2026-10-17T01:00")), Some(hours(6.0)));
+    }
+
+    #[test]
+    fn remaining_across_dst() {
+        let night: TimeWindow = "22:00-07:00".parse().unwrap();
+        // spring forward skips 02:00-03:00 so the night is an hour shorter
+        assert_eq!(night.remaining(&at("2026-03-07T23:00")), Some(hours(7.0)));
+        // fall back repeats 01:00-02:00 so the night is an hour longer
+        assert_eq!(night.remaining(&at("2026-10-31T23:00")), Some(hours(9.0)));
+
+        // an end inside the gap happens as soon as the clock jumps past it
+        let gap: TimeWindow = "01:00-02:30".parse().unwrap();
+        assert_eq!(gap.remaining(&at("2026-03-08T01:30")), Some(hours(0.5)));
+    }
+
+    #[test]
+    fn until_active_over_the_weekend() {
+        let work = active("weekdays", Some("09:00-17:00"));
+
+        assert_eq!(work.until_active(at("2026-10-16T12:00").timestamp()), None);
+        // friday evening waits for monday morning
+        assert_eq!(
+            work.until_active(at("2026-10-16T18:00").timestamp()),
+            Some(hours(63.0))
+        );
+        assert_eq!(
+            work.until_active(at("2026-10-17T12:00").timestamp()),
+            Some(hours(45.0))
+        );
+        // monday before work
+        assert_eq!(
+            work.until_active(at("2026-10-19T08:00").timestamp()),
+            Some(hours(1.0))
+        );
+
+        let weekends = active("weekends", None);
+        assert_eq!(
+            weekends.until_active(at("2026-10-17T03:00").timestamp()),
+            None
+        );
+        assert_eq!(
+            weekends.until_active(at("2026-10-16T21:00").timestamp()),
+            Some(hours(3.0))
+        );
+    }
+
+    #[test]
+    fn until_active_overnight() {
+        let nights = active("weekdays", Some("22:00-06:00"));
+
+        assert_eq!(
+            nights.until_active(at("2026-10-16T23:00").timestamp()),
+            None
+        );
+        // early saturday is still friday night
+        assert_eq!(
+            nights.until_active(at("2026-10-17T02:00").timestamp()),
+            None
+        );
+        assert_eq!(
+            nights.until_active(at("2026-10-17T07:00").timestamp()),
+            Some(hours(63.0))
+        );
+        // early monday is sunday night, which isn't one of our days
+        assert_eq!(
+            nights.until_active(at("2026-10-19T05:00").timestamp()),
+            Some(hours(17.0))
+        );
+    }
+
+    #[test]
+    fn until_active_on_spring_forward() {
+        // saturday 18:00 EST to sunday 09:00 EDT is 15 hours on the clock but 14 real hours
+        let daily = active("daily", Some("09:00-17:00"));
+        assert_eq!(
+            daily.until_active(at("2026-03-07T18:00").timestamp()),
+            Some(hours(14.0))
+        );
+
+        // 02:30 doesn't exist, so the window opens when the clock jumps to 03:00
+        let gap = active("daily", Some("02:30-04:00"));
+        assert_eq!(
+            gap.until_active(at("2026-03-08T01:00").timestamp()),
+            Some(hours(1.0))
+        );
+        assert_eq!(gap.until_active(at("2026-03-08T03:00").timestamp()), None);
+    }
+
+    #[test]
+    fn until_active_on_fall_back() {
+        // saturday 18:00 EDT to sunday 09:00 EST is 15 hours on the clock but 16 real hours
+        let daily = active("daily", Some("09:00-17:00"));
+        assert_eq!(
+            daily.until_active(at("2026-10-31T18:00").timestamp()),
+            Some(hours(16.0))
+        );
+
+        // 01:30 happens twice, we start at the first
+        let fold = active("daily", Some("01:30-03:00"));
+        assert_eq!(
+            fold.until_active(at("2026-11-01T00:00").timestamp()),
+            Some(hours(1.5))
+        );
+    }
+
+    #[test]
+    fn active_when_until_active_says() {
+        let rules = [
+            active("weekdays", Some("09:00-17:00")),
+            active("daily", Some("02:30-04:00")),
+            active("daily", Some("01:30-03:00")),
+            active("mon,wed", Some("22:00-06:00")),
+            active("weekends", None),
+        ];
+        // every 10 minutes through the weeks around both DST changes
+        for start in ["2026-03-04T00:00", "2026-10-28T00:00"] {
+            let start = at(start).timestamp();
+            for step in 0..(7 * 24 * 6) {
+                let now = start + SignedDuration::from_mins(step * 10);
+                for rule in &rules {
+                    let Some(wait) = rule.until_active(now) else {
+                        assert!(
+                            rule.contains(&now.to_zoned(new_york())),
+                            "{rule:?} at {now}"
+                        );
+                        continue;
+                    };
+                    let begins = now + SignedDuration::try_from(wait).unwrap();
+                    let before = begins - SignedDuration::from_secs(1);
+
+                    assert!(
+                        rule.contains(&begins.to_zoned(new_york())),
+                        "{rule:?} at {now}"
+                    );
+                    assert!(
+                        !rule.contains(&before.to_zoned(new_york())),
+                        "{rule:?} at {now}"
+                    );
+                }
+            }
+        }
+    }
+}
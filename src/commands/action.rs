@@ -0,0 +1,66 @@
+use anyhow::Context;
+
+use uplift_lib::desk::{Desk, MoveProfile, Tolerance};
+use uplift_lib::output;
+
+use crate::commands::{movement, undo};
+use crate::config::{Action, Config};
+use crate::hooks::{self, HookContext, HookEvent};
+use crate::print::{progress, verbose};
+
+pub async fn run(name: &str, desk: &Desk) -> Result<(), anyhow::Error> {
+    let config = Config::load()?;
+    let action = config.action(name)?;
+    let tolerance = config.tolerance();
+    let previous_height = desk.query_height().await?;
+
+    if let Some(before) = &action.before {
+        verbose!("Running before hook");
+        // nothing has moved yet so there's nothing to roll back
+        let context = HookContext::new(HookEvent::Before, Some(desk.id()))
+            .height(previous_height)
+            .target(action.height.to_tenths());
+        hooks::run_hook(before, &context).await?;
+    }
+
+    if let Err(e) = move_and_finish(action, desk, tolerance).await {
+        progress!(
+            "{name} failed, moving back to {}",
+            output::height(previous_height)
+        );
+        desk.move_to_with(previous_height, MoveProfile::Constant, tolerance)
+            .await
+            .with_context(|| format!("Failed to roll back after: {e:?}"))?;
+
+        return Err(e.context(format!("Action {name} failed")));
+    }
+
+    // a failed action moved back, so only a finished one can be undone
+    undo::record(desk, previous_height);
+    progress!("Finished {name}");
+
+    Ok(())
+}
+
+async fn move_and_finish(
+    action: &Action,
+    desk: &Desk,
+    tolerance: Tolerance,
+) -> Result<(), anyhow::Error> {
+    let target = action.height.to_tenths();
+    let height = desk
+        .move_to_with(target, MoveProfile::Constant, tolerance)
+        .await?;
+    verbose!("Reached {}", output::height(height));
+    movement::fire_reached(desk, target, height).await;
+
+    if let Some(after) = &action.after {
+        verbose!("Running after hook");
+        let context = HookContext::new(HookEvent::After, Some(desk.id()))
+            .height(height)
+            .target(target);
+        hooks::run_hook(after, &context).await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,133 @@
+use std::time::Duration;
+
+use clap::Args;
+use futures::StreamExt;
+use tokio::time::{self, Instant};
+
+use uplift_lib::desk::Desk;
+use uplift_lib::output;
+
+use crate::hooks::{self, HookContext, HookEvent};
+use crate::print::{progress, verbose};
+use crate::units::Inches;
+
+#[derive(Args, Debug)]
+pub struct Alert {
+    /// Trigger when the desk rises above this height, inches by default or use a unit like `110cm`
+    #[clap(long, required_unless_present = "below", conflicts_with = "below")]
+    pub above: Option<Inches>,
+    /// Trigger when the desk falls below this height
+    #[clap(long)]
+    pub below: Option<Inches>,
+    /// The command to run, it gets the same `UPLIFT_*` variables and `{...}` placeholders as hooks
+    #[clap(long)]
+    pub cmd: String,
+    /// Only trigger once the desk has stayed past the threshold this long, ie: `30s`
+    #[clap(long, value_parser = humantime::parse_duration, default_value = "0s")]
+    pub debounce: Duration,
+    /// Exit after the first trigger instead of waiting for the desk to cross back and trigger again
+    #[clap(long)]
+    pub once: bool,
+}
+
+impl Alert {
+    /// Watch the height stream and run our command each time the desk crosses our threshold. We
+    /// only trigger on crossings, so a desk that's already past the threshold has to cross back
+    /// first.
+    pub async fn run(&self, desk: &Desk) -> Result<(), anyhow::Error> {
+        let threshold = self.threshold();
+        let mut updates = desk.subscribe_height();
+        let mut height = desk.height();
+        let mut armed = !self.past(height);
+        let mut deadline = None;
+
+        loop {
+            tokio::select! {
+                update = updates.next() => {
+                    let Some(update) = update else {
+                        return Ok(());
+                    };
+                    height = update.height;
+
+                    if !self.past(height) {
+                        armed = true;
+                        deadline = None;
+                    } else if armed && deadline.is_none() {
+                        verbose!("Past {}, waiting to trigger", output::height(threshold));
+                        deadline = Some(Instant::now() + self.debounce);
+                    }
+                }
+                _ = time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                    deadline = None;
+                    armed = false;
+                    progress!("Alert: {}", output::height(height));
+
+                    let context = HookContext::new(HookEvent::Alert, Some(desk.id()))
+                        .height(height)
+                        .target(threshold);
+                    if let Err(e) = hooks::run_hook(&self.cmd, &context).await {
+                        log::warn!("The alert command failed: {e:?}");
+                    }
+
+                    if self.once {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+
+    fn threshold(&self) -> isize {
+        self.above
+            .or(self.below)
+            .map_or(0, |threshold| threshold.to_tenths())
+    }
+
+    /// Is `height` on the alerting side of our threshold
+    fn past(&self, height: isize) -> bool {
+        if self.above.is_some() {
+            height > self.threshold()
+        } else {
+            height < self.threshold()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alert(above: Option<&str>, below: Option<&str>) -> Alert {
+        Alert {
+            above: above.map(|height| height.parse().unwrap()),
+            below: below.map(|height| height.parse().unwrap()),
+            cmd: "true".to_string(),
+            debounce: Duration::ZERO,
+            once: false,
+        }
+    }
+
+    #[test]
+    fn thresholds_are_in_tenths() {
+        assert_eq!(alert(Some("40"), None).threshold(), 400);
+        assert_eq!(alert(None, Some("76.2cm")).threshold(), 300);
+    }
+
+    #[test]
+    fn past_above() {
+        let alert = alert(Some("40"), None);
+
+        assert!(!alert.past(399));
+        assert!(!alert.past(400));
+        assert!(alert.past(401));
+    }
+
+    #[test]
+    fn past_below() {
+        let alert = alert(None, Some("30"));
+
+        assert!(alert.past(299));
+        assert!(!alert.past(300));
+        assert!(!alert.past(301));
+    }
+}
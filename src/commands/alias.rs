@@ -0,0 +1,48 @@
+use anyhow::{anyhow, Context};
+use clap::Parser;
+
+use crate::commands::{Commands, Session};
+use crate::config::Config;
+use crate::print::verbose;
+
+/// A single step of an alias, parsed like our normal arguments
+#[derive(Parser, Debug)]
+#[clap(no_binary_name = true)]
+struct Step {
+    #[clap(subcommand)]
+    command: Commands,
+}
+
+/// Run each `&&` separated step of an alias from the config, stopping at the first failure
+pub async fn run(args: &[String], session: &Session) -> Result<(), anyhow::Error> {
+    let (name, extra) = args
+        .split_first()
+        .ok_or_else(|| anyhow!("Missing a command"))?;
+    if !extra.is_empty() {
+        return Err(anyhow!(
+            "Aliases don't take arguments but `{name}` got: {extra:?}"
+        ));
+    }
+
+    let config = Config::load()?;
+    let alias = config.alias(name)?;
+
+    for step in alias.split("&&") {
+        let step = step.trim();
+        verbose!("Running `{step}`");
+
+        let Step { command } = Step::try_parse_from(step.split_whitespace())
+            .with_context(|| format!("Invalid step `{step}` in alias `{name}`"))?;
+        if let Commands::Alias(_) = command {
+            return Err(anyhow!(
+                "Aliases can't run other aliases: `{step}` in `{name}`"
+            ));
+        }
+
+        Box::pin(command.run(session))
+            .await
+            .with_context(|| format!("Failed to run `{step}` in alias `{name}`"))?;
+    }
+
+    Ok(())
+}
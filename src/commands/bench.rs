@@ -0,0 +1,120 @@
+use std::time::{Duration, Instant};
+
+use anyhow::anyhow;
+use clap::Args;
+use tokio::time;
+
+use uplift_lib::desk::Desk;
+use uplift_lib::timings;
+
+use crate::commands::Session;
+use crate::print::progress;
+
+// the percentiles we report for each phase
+const PERCENTILES: [f64; 3] = [50.0, 90.0, 99.0];
+
+#[derive(Args, Debug)]
+pub struct Bench {
+    /// How many times to connect and query the desk
+    #[clap(long, short = 'n', default_value_t = 10)]
+    pub cycles: usize,
+    /// How long each cycle can take before we count it as failed, ie: `20s`
+    #[clap(long, value_parser = humantime::parse_duration, default_value = "30s")]
+    pub cycle_timeout: Duration,
+}
+
+impl Bench {
+    /// Connect, query and disconnect `cycles` times, then print percentiles for every phase so
+    /// adapters and desk placements can be compared
+    pub async fn run(&self, session: &Session) -> Result<(), anyhow::Error> {
+        // forget anything recorded before we started
+        timings::take();
+
+        let mut phases: Vec<(&'static str, Vec<Duration>)> = Vec::new();
+        let mut failures = 0;
+        for cycle in 1..=self.cycles {
+            progress!("Cycle {cycle}/{}", self.cycles);
+
+            let result = time::timeout(self.cycle_timeout, cycle_once(session))
+                .await
+                .unwrap_or_else(|_| Err(anyhow!("Timed out")));
+            let recorded = timings::take();
+            match result {
+                Ok(()) => {
+                    for (name, elapsed) in recorded {
+                        match phases.iter_mut().find(|(phase, _)| *phase == name) {
+                            Some((_, samples)) => samples.push(elapsed),
+                            None => phases.push((name, vec![elapsed])),
+                        }
+                    }
+                }
+                Err(e) => {
+                    failures += 1;
+                    progress!("Cycle {cycle} failed: {e:#}");
+                }
+            }
+        }
+
+        print_percentiles(&mut phases);
+        println!("failed cycles: {failures}/{}", self.cycles);
+
+        Ok(())
+    }
+}
+
+async fn cycle_once(session: &Session) -> Result<(), anyhow::Error> {
+    let started = Instant::now();
+    let desk = Desk::with_options(session.desk_filter(), None, session.adapters()).await?;
+    timings::record("connected", started);
+
+    let query = Instant::now();
+    desk.query_height().await?;
+    timings::record("query round trip", query);
+
+    let disconnect = Instant::now();
+    desk.disconnect().await?;
+    timings::record("disconnect", disconnect);
+
+    Ok(())
+}
+
+fn print_percentiles(phases: &mut [(&'static str, Vec<Duration>)]) {
+    println!(
+        "{:<24} {:>6} {}",
+        "phase",
+        "count",
+        PERCENTILES
+            .iter()
+            .map(|percentile| format!("p{percentile}"))
+            .chain(["max".to_string()])
+            .map(|column| format!("{column:>9}"))
+            .collect::<Vec<_>>()
+            .join(" ")
+    );
+
+    for (name, samples) in phases.iter_mut() {
+        samples.sort();
+        let max = samples.last().copied().unwrap_or_default();
+        println!(
+            "{name:<24} {:>6} {}",
+            samples.len(),
+            PERCENTILES
+                .iter()
+                .map(|percentile| percentile_of(samples, *percentile))
+                .chain([max])
+                .map(|elapsed| format!("{:>8.3}s", elapsed.as_secs_f32()))
+                .collect::<Vec<_>>()
+                .join(" ")
+        );
+    }
+}
+
+/// The nearest rank percentile of our sorted samples
+fn percentile_of(sorted: &[Duration], percentile: f64) -> Duration {
+    let rank = (percentile / 100.0 * sorted.len() as f64).ceil() as usize;
+
+    sorted
+        .get(rank.saturating_sub(1))
+        .copied()
+        .unwrap_or_default()
+}
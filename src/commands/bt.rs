@@ -0,0 +1,76 @@
+use std::time::SystemTime;
+
+use btleplug::api::{Central, CentralEvent, Peripheral as _, ScanFilter};
+use btleplug::platform::{Adapter, Manager};
+use clap::Subcommand;
+use futures::StreamExt;
+
+use uplift_lib::desk::{self, DESK_SERVICE_UUID};
+use uplift_lib::redact;
+
+#[derive(Subcommand, Debug)]
+pub enum BtCommand {
+    /// Scan and print every Bluetooth event with a timestamp
+    Events {
+        /// Only scan for peripherals advertising the desk service
+        #[clap(long)]
+        desks: bool,
+    },
+}
+
+impl BtCommand {
+    pub async fn run(&self) -> Result<(), anyhow::Error> {
+        match self {
+            BtCommand::Events { desks } => events(*desks).await,
+        }
+    }
+}
+
+async fn events(desks: bool) -> Result<(), anyhow::Error> {
+    let manager = Manager::new().await?;
+    let central = desk::first_adapter(&manager).await?;
+    eprintln!(
+        "{}",
+        redact::scrub(format!("Using adapter: {}", central.adapter_info().await?))
+    );
+
+    let mut events = central.events().await?;
+    let services = if desks {
+        vec![DESK_SERVICE_UUID]
+    } else {
+        vec![]
+    };
+    central.start_scan(ScanFilter { services }).await?;
+
+    while let Some(event) = events.next().await {
+        println!(
+            "{} {}",
+            humantime::format_rfc3339_millis(SystemTime::now()),
+            redact::scrub(describe(&central, event).await)
+        );
+    }
+
+    central.stop_scan().await?;
+
+    Ok(())
+}
+
+/// Add the name and signal strength to discovery events so desks are easy to spot
+async fn describe(central: &Adapter, event: CentralEvent) -> String {
+    if let CentralEvent::DeviceDiscovered(id) = &event {
+        if let Ok(Some(properties)) =
+            async { central.peripheral(id).await?.properties().await }.await
+        {
+            return format!(
+                "{event:?} name: {}, rssi: {}, desk: {}",
+                properties.local_name.as_deref().unwrap_or("-"),
+                properties
+                    .rssi
+                    .map_or_else(|| "-".to_string(), |rssi| rssi.to_string()),
+                properties.services.contains(&DESK_SERVICE_UUID)
+            );
+        }
+    }
+
+    format!("{event:?}")
+}
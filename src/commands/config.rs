@@ -0,0 +1,148 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read};
+use std::path::PathBuf;
+
+use anyhow::Context;
+use clap::Subcommand;
+use serde::{Deserialize, Serialize};
+use toml::Value;
+
+use crate::config::Config;
+use crate::print::progress;
+use uplift_lib::storage::{self, Calibration};
+
+/// Everything `config export` carries to another machine
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+struct Bundle {
+    /// Our config without `[desks]`, those ids only mean something on this machine
+    config: Config,
+    /// What we've learned about each named desk, keyed by its `[desks]` name
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    calibration: BTreeMap<String, Calibration>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Print a setting, ie: `positions.drawing`
+    Get { key: String },
+    /// Change a setting, heights can use units like `87.6cm`
+    Set { key: String, value: String },
+    /// Remove a setting
+    Unset { key: String },
+    /// Print every setting
+    List,
+    /// Write our config and what we've learned about each named desk to one TOML file for another
+    /// machine, desk ids are left out since they only work on this one
+    Export {
+        /// Where to write the bundle, defaults to stdout
+        file: Option<PathBuf>,
+    },
+    /// Replace our config with an exported bundle, keeping our `[desks]`
+    Import {
+        /// The bundle from `config export`, or `-` to read stdin
+        file: PathBuf,
+    },
+}
+
+impl ConfigCommand {
+    pub fn run(&self) -> Result<(), anyhow::Error> {
+        match self {
+            ConfigCommand::Get { key } => println!("{}", display(&Config::load()?.get(key)?)),
+            ConfigCommand::Set { key, value } => {
+                Config::edit(|contents| Config::set(contents, key, value))?;
+                progress!("Set {key}");
+            }
+            ConfigCommand::Unset { key } => {
+                Config::edit(|contents| Config::unset(contents, key))?;
+                progress!("Unset {key}");
+            }
+            ConfigCommand::List => {
+                for (key, value) in Config::load()?.entries()? {
+                    println!("{key} = {value}");
+                }
+            }
+            ConfigCommand::Export { file } => export(file.as_ref())?,
+            ConfigCommand::Import { file } => import(file)?,
+        }
+
+        Ok(())
+    }
+}
+
+fn export(file: Option<&PathBuf>) -> Result<(), anyhow::Error> {
+    let mut config = Config::load()?;
+    let desks = std::mem::take(&mut config.desks);
+
+    let mut calibration = BTreeMap::new();
+    for (name, id) in desks {
+        let learned = storage::calibration(&id)?;
+        if !learned.is_empty() {
+            calibration.insert(name, learned);
+        }
+    }
+
+    let bundle = toml::to_string_pretty(&Bundle {
+        config,
+        calibration,
+    })
+    .context("Failed to serialize our bundle")?;
+    match file {
+        Some(file) => {
+            fs::write(file, bundle)
+                .with_context(|| format!("Failed to write {}", file.display()))?;
+            progress!("Exported to {}", file.display());
+        }
+        None => print!("{bundle}"),
+    }
+
+    Ok(())
+}
+
+fn import(file: &PathBuf) -> Result<(), anyhow::Error> {
+    let contents = if file.as_os_str() == "-" {
+        let mut contents = String::new();
+        io::stdin()
+            .read_to_string(&mut contents)
+            .context("Failed to read stdin")?;
+        contents
+    } else {
+        fs::read_to_string(file).with_context(|| format!("Failed to read {}", file.display()))?
+    };
+    let bundle: Bundle = toml::from_str(&contents).context("Failed to parse our bundle")?;
+
+    let mut desks = BTreeMap::new();
+    Config::update(|config| {
+        desks = config.desks;
+        Ok(Config {
+            desks: desks.clone(),
+            ..bundle.config
+        })
+    })?;
+    progress!("Imported our config");
+
+    for (name, calibration) in bundle.calibration {
+        match desks.get(&name) {
+            Some(id) => {
+                storage::save_calibration(id, calibration)?;
+                progress!("Calibrated {name}");
+            }
+            None => {
+                // wait until `desks resolve --save` tells us which desk this is
+                storage::save_pending_calibration(&name, calibration)?;
+                progress!("Pair {name} with `uplift desks resolve <address> --save {name}`");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Print strings without their TOML quotes
+fn display(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
@@ -0,0 +1,295 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use clap::Subcommand;
+use futures::StreamExt;
+use serde::Serialize;
+use uuid::Uuid;
+
+use uplift_lib::desk::{self, Desk, ScanOptions};
+use uplift_lib::output::{self, Height};
+use uplift_lib::redact;
+use uplift_lib::storage::{self, DeskSighting};
+
+use crate::commands::Session;
+use crate::config::Config;
+use crate::print::progress;
+
+/// A desk from `desks status --json`
+#[derive(Serialize)]
+struct DeskStatus<'a> {
+    id: &'a str,
+    address: &'a str,
+    name: Option<&'a str>,
+    reachable: bool,
+    height_in: Option<f64>,
+    error: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DesksCommand {
+    /// Scan for nearby desks without connecting to them
+    List {
+        /// How long to scan for, ie: `10s`
+        #[clap(long, value_parser = humantime::parse_duration, default_value = "5s")]
+        duration: Duration,
+        /// Stop once we've found this many desks
+        #[clap(long)]
+        max: Option<usize>,
+    },
+    /// List every desk we've ever seen, most recent first
+    History,
+    /// Connect to every desk we've seen and report its height
+    Status {
+        /// How many desks to connect to at once
+        #[clap(long, default_value_t = 2)]
+        concurrency: usize,
+        /// How long to look for each desk, and then to connect to it, before calling it unreachable,
+        /// ie: `20s`
+        #[clap(long, value_parser = humantime::parse_duration, default_value = "30s")]
+        desk_timeout: Duration,
+        /// Print a JSON array instead of a table
+        #[clap(long)]
+        json: bool,
+    },
+    /// Connect to a desk by the address or UUID another tool printed and show the id we use for it
+    Resolve {
+        /// A Bluetooth address like `E8:5F:...`, a UUID like the ones macOS uses or one of our ids
+        id: String,
+        /// Save the id under this name in `[desks]` so `--desk <name>` connects to it
+        #[clap(long)]
+        save: Option<String>,
+    },
+}
+
+impl DesksCommand {
+    pub async fn run(&self, session: &Session) -> Result<(), anyhow::Error> {
+        match self {
+            DesksCommand::List { duration, max } => list(*duration, *max, session).await,
+            DesksCommand::History => history(),
+            DesksCommand::Status {
+                concurrency,
+                desk_timeout,
+                json,
+            } => status(*concurrency, *desk_timeout, *json, session).await,
+            DesksCommand::Resolve { id, save } => resolve(id, save.as_deref(), session).await,
+        }
+    }
+}
+
+async fn list(
+    duration: Duration,
+    max_desks: Option<usize>,
+    session: &Session,
+) -> Result<(), anyhow::Error> {
+    let desks = desk::scan_with(&ScanOptions {
+        duration,
+        max_desks,
+        adapters: session.adapters().clone(),
+        ..ScanOptions::default()
+    })
+    .await?;
+
+    for desk in desks {
+        print_desk(&desk);
+    }
+
+    Ok(())
+}
+
+fn history() -> Result<(), anyhow::Error> {
+    for desk in storage::desk_history()? {
+        print_desk(&desk);
+    }
+
+    Ok(())
+}
+
+async fn status(
+    concurrency: usize,
+    desk_timeout: Duration,
+    json: bool,
+    session: &Session,
+) -> Result<(), anyhow::Error> {
+    let desks = storage::desk_history()?;
+    let ids = desks.iter().map(|desk| desk.id.clone()).collect();
+    // one scan for every desk, their own scans would stop each other on a shared adapter
+    let mut heights = desk::connect_each(ids, session.adapters(), desk_timeout, concurrency)
+        .await?
+        .map(|(id, connected)| async move {
+            let height = async {
+                let connected = connected?;
+                let height = connected.query_height().await?;
+                if let Err(e) = connected.disconnect().await {
+                    log::warn!("Failed to disconnect from {id}: {e:?}");
+                }
+
+                Ok(height)
+            }
+            .await;
+
+            (id, height)
+        })
+        .buffer_unordered(desks.len().max(1))
+        .collect::<HashMap<_, _>>()
+        .await;
+    let statuses = desks
+        .into_iter()
+        .map(|desk| {
+            let height = heights
+                .remove(&desk.id)
+                .unwrap_or_else(|| Err(anyhow!("Lost track of it while connecting")));
+            (desk, height)
+        })
+        .collect::<Vec<_>>();
+
+    if json {
+        let statuses = statuses
+            .iter()
+            .map(|(desk, height)| DeskStatus {
+                id: &desk.id,
+                address: &desk.address,
+                name: desk.name.as_deref(),
+                reachable: height.is_ok(),
+                height_in: height
+                    .as_ref()
+                    .ok()
+                    .map(|height| Height::from_tenths(*height).height_in),
+                error: height.as_ref().err().map(|e| format!("{e:#}")),
+            })
+            .collect::<Vec<_>>();
+        println!(
+            "{}",
+            redact::scrub(serde_json::to_string_pretty(&statuses)?)
+        );
+    } else {
+        for (desk, height) in statuses {
+            let status = format!(
+                "{}\t{}\t{}",
+                desk.id,
+                desk.name.as_deref().unwrap_or("-"),
+                match height {
+                    Ok(height) => output::height(height),
+                    Err(e) => format!("unreachable: {e:#}"),
+                }
+            );
+            println!("{}", redact::scrub(status));
+        }
+    }
+
+    Ok(())
+}
+
+async fn resolve(id: &str, save: Option<&str>, session: &Session) -> Result<(), anyhow::Error> {
+    let filter = normalize_id(id)?;
+    let desk = Desk::with_options(Some(&filter), None, session.adapters()).await?;
+    println!("{}", desk.id());
+
+    if let Some(name) = save {
+        Config::update(|mut config| {
+            config.desks.insert(name.to_string(), desk.id().to_string());
+            Ok(config)
+        })?;
+        progress!("Saved as {name}, connect with `uplift --desk {name}`");
+
+        if let Some(calibration) = storage::take_pending_calibration(name)? {
+            storage::save_calibration(desk.id(), calibration)?;
+            progress!("Applied the calibration we imported for {name}");
+        }
+    }
+
+    Ok(())
+}
+
+/// Turn the ids other tools print into something `--desk` matches. Addresses can use `:`, `-` or
+/// `_` separators (bluetoothctl, nRF Connect and BlueZ paths) and UUIDs can use any case.
+fn normalize_id(id: &str) -> Result<String, anyhow::Error> {
+    let id = id.trim();
+    if let Ok(uuid) = Uuid::parse_str(id) {
+        return Ok(uuid.hyphenated().to_string());
+    }
+
+    let address = id
+        .rsplit('/')
+        .next()
+        .unwrap_or(id)
+        .trim_start_matches("dev_")
+        .replace([':', '-', '_'], "");
+    if address.len() == 12 && address.chars().all(|c| c.is_ascii_hexdigit()) {
+        let address = address.to_ascii_uppercase();
+        let octets = (0..address.len())
+            .step_by(2)
+            .map(|start| &address[start..start + 2])
+            .collect::<Vec<_>>();
+        return Ok(octets.join(":"));
+    }
+
+    bail!("`{id}` isn't a Bluetooth address, UUID or desk id")
+}
+
+fn print_desk(desk: &DeskSighting) {
+    let line = format!(
+        "{}\t{}\t{}\tadapter: {}\trssi: {}\tlast seen: {}",
+        desk.id,
+        desk.address,
+        desk.name.as_deref().unwrap_or("-"),
+        desk.adapter,
+        desk.rssi
+            .map_or_else(|| "-".to_string(), |rssi| rssi.to_string()),
+        humantime::format_rfc3339_seconds(desk.last_seen)
+    );
+    println!("{}", redact::scrub(line));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_addresses_with_any_separator() {
+        for id in [
+            "e8:5f:12:34:56:78",
+            "E8-5F-12-34-56-78",
+            "e8_5f_12_34_56_78",
+            "E8:5f-12_34:56-78",
+            "e85f12345678",
+            "  E8:5F:12:34:56:78 ",
+        ] {
+            assert_eq!(normalize_id(id).unwrap(), "E8:5F:12:34:56:78", "{id}");
+        }
+    }
+
+    #[test]
+    fn normalizes_bluez_paths() {
+        for id in [
+            "dev_E8_5F_12_34_56_78",
+            "/org/bluez/hci0/dev_E8_5F_12_34_56_78",
+            "/org/bluez/hci1/dev_e8_5f_12_34_56_78",
+        ] {
+            assert_eq!(normalize_id(id).unwrap(), "E8:5F:12:34:56:78", "{id}");
+        }
+    }
+
+    #[test]
+    fn normalizes_uuids() {
+        assert_eq!(
+            normalize_id("1B2C3D4E-5F60-7182-93A4-B5C6D7E8F901").unwrap(),
+            "1b2c3d4e-5f60-7182-93a4-b5c6d7e8f901"
+        );
+    }
+
+    #[test]
+    fn rejects_other_ids() {
+        for id in [
+            "",
+            "my desk",
+            "E8:5F:12:34:56",
+            "E8:5F:12:34:56:78:9A",
+            "G8:5F:12:34:56:78",
+            "/org/bluez/hci0",
+        ] {
+            assert!(normalize_id(id).is_err(), "{id}");
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use std::io::{self, IsTerminal, Write};
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::terminal;
+use tokio::time;
+
+use uplift_lib::desk::{CommandBatch, Desk, Nudge};
+use uplift_lib::output;
+use uplift_lib::protocol::STOP;
+
+// how often we check for keys while the desk is idle
+const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(20);
+// holding an arrow key can't queue up more than this many pulses
+const MAX_PENDING_PULSES: isize = 20;
+
+const HELP: &str = "↑/↓ move, space stop, s sit, t stand, q quit";
+
+/// Puts the terminal in raw mode until it's dropped, so keys reach us without waiting for enter
+struct RawMode;
+
+impl RawMode {
+    fn enable() -> Result<RawMode, anyhow::Error> {
+        terminal::enable_raw_mode().context("Failed to put the terminal in raw mode")?;
+
+        Ok(RawMode)
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        if let Err(e) = terminal::disable_raw_mode() {
+            log::warn!("Failed to restore the terminal: {e:?}");
+        }
+    }
+}
+
+/// What a key asks us to do
+enum Key {
+    Pulse(Nudge),
+    Stop,
+    Sit,
+    Stand,
+    Quit,
+}
+
+/// Drive the desk from the keyboard. Each arrow press queues a single pulse so holding an arrow
+/// keeps the desk moving. Space drops whatever pulses are still queued and stops the desk, which
+/// interrupts a sit or stand.
+pub async fn run(desk: &Desk) -> Result<(), anyhow::Error> {
+    if !io::stdin().is_terminal() {
+        bail!("uplift drive needs a terminal to read keys from");
+    }
+
+    eprintln!("{HELP}");
+    let _raw_mode = RawMode::enable()?;
+
+    // positive pulses move up and negative pulses move down
+    let mut pending: isize = 0;
+    let mut shown = None;
+    loop {
+        while event::poll(Duration::ZERO)? {
+            match to_key(event::read()?) {
+                Some(Key::Pulse(Nudge::Up)) => {
+                    pending = (pending.max(0) + 1).min(MAX_PENDING_PULSES)
+                }
+                Some(Key::Pulse(Nudge::Down)) => {
+                    pending = (pending.min(0) - 1).max(-MAX_PENDING_PULSES)
+                }
+                Some(Key::Stop) => {
+                    pending = 0;
+                    // ask for the height in the same batch so we show where it stopped
+                    let outcome = desk
+                        .send_batch(&CommandBatch::new().command(STOP).verify())
+                        .await?;
+                    let height = outcome.height.unwrap_or_else(|| desk.height());
+                    eprint!("\r{} stopped\x1b[K", output::height(height));
+                    io::stderr().flush()?;
+                    shown = Some(height);
+                }
+                Some(Key::Sit) => {
+                    pending = 0;
+                    desk.sit().await?;
+                }
+                Some(Key::Stand) => {
+                    pending = 0;
+                    desk.stand().await?;
+                }
+                Some(Key::Quit) => {
+                    eprint!("\r\n");
+                    return Ok(());
+                }
+                None => {}
+            }
+        }
+
+        if pending > 0 {
+            desk.nudge(Nudge::Up).await?;
+            pending -= 1;
+        } else if pending < 0 {
+            desk.nudge(Nudge::Down).await?;
+            pending += 1;
+        } else {
+            time::sleep(IDLE_POLL_INTERVAL).await;
+        }
+
+        let height = desk.height();
+        if shown != Some(height) {
+            // raw mode doesn't return the cursor on newlines so we stay on one line
+            eprint!("\r{}\x1b[K", output::height(height));
+            io::stderr().flush()?;
+            shown = Some(height);
+        }
+    }
+}
+
+fn to_key(event: Event) -> Option<Key> {
+    let Event::Key(KeyEvent {
+        code,
+        modifiers,
+        kind: KeyEventKind::Press | KeyEventKind::Repeat,
+        ..
+    }) = event
+    else {
+        return None;
+    };
+
+    match code {
+        KeyCode::Up => Some(Key::Pulse(Nudge::Up)),
+        KeyCode::Down => Some(Key::Pulse(Nudge::Down)),
+        KeyCode::Char(' ') => Some(Key::Stop),
+        KeyCode::Char('s') => Some(Key::Sit),
+        KeyCode::Char('t') => Some(Key::Stand),
+        KeyCode::Char('q') | KeyCode::Esc => Some(Key::Quit),
+        // raw mode swallows our interrupt signal
+        KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => Some(Key::Quit),
+        _ => None,
+    }
+}
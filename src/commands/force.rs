@@ -0,0 +1,44 @@
+use uplift_lib::desk::{Desk, ForcePolicy, ForceProgress, Target, AVG_MID_HEIGHT};
+use uplift_lib::output;
+
+use crate::commands::undo;
+use crate::config::Config;
+use crate::hooks::{self, HookContext, HookEvent};
+use crate::print::{progress, verbose};
+
+pub async fn force(desk: &Desk, target: Target) -> Result<(), anyhow::Error> {
+    let policy = ForcePolicy {
+        tolerance: Config::load()?.tolerance(),
+        ..ForcePolicy::default()
+    };
+    let previous_height = desk.query_height().await?;
+    let height = desk
+        .force(target, policy, |update| match update {
+            ForceProgress::Attempt { attempt, attempts } => {
+                verbose!("Attempt {attempt} of {attempts}")
+            }
+            ForceProgress::Moved { to, .. } => verbose!("Moving: {}", output::height(to)),
+            ForceProgress::Reached {
+                height,
+                preset: Some(preset),
+            } if preset != height => progress!(
+                "Reached {} (preset {})",
+                output::height(height),
+                output::height(preset)
+            ),
+            ForceProgress::Reached { height, .. } => {
+                progress!("Reached {}", output::height(height))
+            }
+        })
+        .await?;
+    undo::record(desk, previous_height);
+
+    let event = if height > AVG_MID_HEIGHT {
+        HookEvent::Stand
+    } else {
+        HookEvent::Sit
+    };
+    hooks::fire(HookContext::new(event, Some(desk.id())).height(height)).await;
+
+    Ok(())
+}
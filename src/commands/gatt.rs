@@ -0,0 +1,119 @@
+use btleplug::api::{CharPropFlags, Characteristic, Peripheral as _};
+use btleplug::platform::Peripheral;
+use clap::Subcommand;
+
+use uplift_lib::desk;
+
+use crate::commands::Session;
+use crate::print::progress;
+
+const PROPERTY_NAMES: [(CharPropFlags, &str); 8] = [
+    (CharPropFlags::BROADCAST, "broadcast"),
+    (CharPropFlags::READ, "read"),
+    (
+        CharPropFlags::WRITE_WITHOUT_RESPONSE,
+        "write-without-response",
+    ),
+    (CharPropFlags::WRITE, "write"),
+    (CharPropFlags::NOTIFY, "notify"),
+    (CharPropFlags::INDICATE, "indicate"),
+    (
+        CharPropFlags::AUTHENTICATED_SIGNED_WRITES,
+        "authenticated-signed-writes",
+    ),
+    (CharPropFlags::EXTENDED_PROPERTIES, "extended-properties"),
+];
+
+#[derive(Subcommand, Debug)]
+pub enum GattCommand {
+    /// Print every service, characteristic and descriptor on the desk
+    Dump {
+        /// Also read every readable characteristic and descriptor
+        #[clap(long)]
+        read: bool,
+    },
+}
+
+impl GattCommand {
+    pub async fn run(&self, session: &Session) -> Result<(), anyhow::Error> {
+        match self {
+            GattCommand::Dump { read } => {
+                // we don't go through Desk so this works even if the desk characteristics are missing
+                let (_manager, _, peripheral) =
+                    desk::connect(session.desk_filter(), session.adapters()).await?;
+                progress!("Connected to {}", peripheral.address());
+
+                let result = dump(&peripheral, *read).await;
+                peripheral.disconnect().await?;
+
+                result
+            }
+        }
+    }
+}
+
+async fn dump(peripheral: &Peripheral, read: bool) -> Result<(), anyhow::Error> {
+    peripheral.discover_services().await?;
+
+    for service in peripheral.services() {
+        println!(
+            "Service {}{}",
+            service.uuid,
+            if service.primary { " (primary)" } else { "" }
+        );
+
+        for characteristic in &service.characteristics {
+            print!(
+                "  Characteristic {} [{}]",
+                characteristic.uuid,
+                properties(characteristic)
+            );
+            if read && characteristic.properties.contains(CharPropFlags::READ) {
+                print!(" = {}", describe(peripheral.read(characteristic).await));
+            }
+            println!();
+
+            for descriptor in &characteristic.descriptors {
+                print!("    Descriptor {}", descriptor.uuid);
+                if read {
+                    print!(
+                        " = {}",
+                        describe(peripheral.read_descriptor(descriptor).await)
+                    );
+                }
+                println!();
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn properties(characteristic: &Characteristic) -> String {
+    PROPERTY_NAMES
+        .iter()
+        .filter(|(flag, _)| characteristic.properties.contains(*flag))
+        .map(|(_, name)| *name)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Show values as hex and as text when they're printable, reads failing shouldn't stop the dump
+fn describe(value: btleplug::Result<Vec<u8>>) -> String {
+    match value {
+        Ok(value) => {
+            let hex = value
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect::<Vec<_>>()
+                .join(" ");
+            match std::str::from_utf8(&value) {
+                Ok(text) if !text.is_empty() && !text.chars().any(char::is_control) => {
+                    format!("{hex} ({text:?})")
+                }
+                _ => hex,
+            }
+        }
+        Err(e) => format!("<read failed: {e}>"),
+    }
+}
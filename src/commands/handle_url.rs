@@ -0,0 +1,167 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Context};
+use serde_json::json;
+use url::Url;
+
+use crate::commands::movement::{Move, Sit, Stand};
+use crate::commands::query::Query;
+use crate::commands::{Commands, Session};
+use crate::run_id;
+use crate::units::Inches;
+use uplift_lib::output::Height;
+
+const SCHEME: &str = "uplift";
+
+/// Run a command from an `uplift://<command>?height=<inches>&desk=<address or name>` url and print
+/// the result as JSON so Shortcuts/Raycast can use it directly. The url's desk has already been
+/// picked up by [Commands::desk] when the session was made, everything else comes from our flags.
+pub async fn run(url: &str, session: &Session) -> Result<(), anyhow::Error> {
+    let (name, command, _) = parse_url(url)?;
+
+    if !matches!(command, Commands::Query(_)) {
+        Box::pin(command.run(session)).await?;
+    }
+    let height = session.desk().await?.query_height().await?;
+
+    println!(
+        "{}",
+        json!({
+            "command": name,
+            "height": Height::from_tenths(height).height_in,
+            "run_id": run_id::get(),
+        })
+    );
+
+    Ok(())
+}
+
+/// The desk named by a url, if it parses
+pub fn desk(url: &str) -> Option<String> {
+    parse_url(url).ok().and_then(|(_, _, desk)| desk)
+}
+
+fn parse_url(url: &str) -> Result<(String, Commands, Option<String>), anyhow::Error> {
+    let url = Url::parse(url).with_context(|| format!("Invalid url: {url}"))?;
+    if url.scheme() != SCHEME {
+        return Err(anyhow!(
+            "Expected an {SCHEME}:// url but got {}://",
+            url.scheme()
+        ));
+    }
+
+    // support both `uplift://stand` and `uplift:stand`
+    let name = url
+        .host_str()
+        .filter(|host| !host.is_empty())
+        .unwrap_or_else(|| url.path().trim_matches('/'))
+        .to_lowercase();
+
+    let mut height = None;
+    let mut desk = None;
+    for (key, value) in url.query_pairs() {
+        match key.as_ref() {
+            "height" => {
+                height = Some(
+                    value
+                        .parse::<Inches>()
+                        .with_context(|| format!("Invalid height: {value}"))?,
+                )
+            }
+            "desk" => desk = Some(value.into_owned()),
+            key => return Err(anyhow!("Unknown url parameter: {key}")),
+        }
+    }
+
+    let command = match name.as_str() {
+        "sit" => Commands::Sit(Sit {
+            save: None,
+            duration: None,
+        }),
+        "stand" => Commands::Stand(Stand {
+            save: None,
+            duration: None,
+        }),
+        "toggle" => Commands::Toggle,
+        "query" => Commands::Query(Query {
+            watch: false,
+            changes_only: false,
+            interval: Duration::from_secs(1),
+            format: None,
+        }),
+        "force-sit" => Commands::ForceSit,
+        "force-stand" => Commands::ForceStand,
+        "force-toggle" => Commands::ForceToggle,
+        "move" => Commands::Move(Move {
+            height: height.ok_or_else(|| anyhow!("move requires a height parameter"))?,
+            ramp: false,
+        }),
+        name => return Err(anyhow!("Unknown url command: {name}")),
+    };
+
+    Ok((name, command, desk))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn move_height(url: &str) -> f64 {
+        match parse_url(url).unwrap() {
+            (name, Commands::Move(move_to), None) if name == "move" => move_to.height.0,
+            other => panic!("Expected a move from {url} but got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn parses_both_url_forms() {
+        for url in [
+            "uplift://stand",
+            "uplift:stand",
+            "uplift:///stand",
+            "UPLIFT://Stand",
+        ] {
+            let (name, command, desk) = parse_url(url).unwrap();
+            assert_eq!(name, "stand", "{url}");
+            assert!(matches!(command, Commands::Stand(_)), "{url}");
+            assert_eq!(desk, None, "{url}");
+        }
+    }
+
+    #[test]
+    fn parses_move_heights_in_every_unit() {
+        assert_eq!(move_height("uplift://move?height=30"), 30.0);
+        assert_eq!(move_height("uplift://move?height=30.5in"), 30.5);
+        assert_eq!(move_height("uplift://move?height=30%22"), 30.0);
+        assert_eq!(move_height("uplift://move?height=76.2cm"), 30.0);
+        assert_eq!(move_height("uplift://move?height=762mm"), 30.0);
+        assert_eq!(move_height("uplift:move?height=30,5"), 30.5);
+    }
+
+    #[test]
+    fn parses_the_desk() {
+        let url = "uplift://sit?desk=Office%20Desk";
+        let (_, command, desk) = parse_url(url).unwrap();
+
+        assert!(matches!(command, Commands::Sit(_)));
+        assert_eq!(desk.as_deref(), Some("Office Desk"));
+        assert_eq!(super::desk(url).as_deref(), Some("Office Desk"));
+        assert_eq!(super::desk("uplift://sit"), None);
+    }
+
+    #[test]
+    fn rejects_bad_urls() {
+        for url in [
+            "https://stand",
+            "file:///stand",
+            "stand",
+            "uplift://dance",
+            "uplift://move",
+            "uplift://move?height=tall",
+            "uplift://move?height=30ft",
+            "uplift://stand?speed=fast",
+        ] {
+            assert!(parse_url(url).is_err(), "{url}");
+        }
+    }
+}
@@ -0,0 +1,20 @@
+use uplift_lib::desk::Desk;
+use uplift_lib::{output, redact};
+
+pub async fn run(desk: &Desk) -> Result<(), anyhow::Error> {
+    let height = desk.query_height().await?;
+    let presets = desk.presets();
+
+    println!("{}", redact::scrub(format!("id: {}", desk.id())));
+    println!("{}", redact::scrub(format!("address: {}", desk.address())));
+    println!("{}", redact::scrub(format!("name: {}", desk.name().await?)));
+    println!("height: {}", output::height(height));
+    println!("sit preset: {}", display_preset(presets.sit));
+    println!("stand preset: {}", display_preset(presets.stand));
+
+    Ok(())
+}
+
+fn display_preset(preset: Option<isize>) -> String {
+    preset.map_or_else(|| "unknown".to_string(), output::height)
+}
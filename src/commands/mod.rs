@@ -0,0 +1,333 @@
+use std::time::{Duration, Instant};
+
+use clap::{Subcommand, ValueEnum};
+use tokio::sync::OnceCell;
+use tokio::time;
+
+use uplift_lib::desk::{AdapterFilter, DecoderKind, Desk, HeightRange, Target};
+use uplift_lib::output::ProgressBar;
+use uplift_lib::quiet_hours::QuietHours;
+
+use crate::config::Config;
+use crate::print::progress;
+
+mod action;
+#[cfg(feature = "streaming")]
+mod alert;
+mod alias;
+mod bench;
+mod bt;
+mod config;
+mod desks;
+mod drive;
+mod force;
+mod gatt;
+#[cfg(feature = "url-handler")]
+mod handle_url;
+mod info;
+mod movement;
+mod permissions;
+mod positions;
+mod query;
+mod redact;
+mod reset;
+#[cfg(feature = "schedule")]
+mod schedule;
+mod soak;
+mod stop;
+#[cfg(feature = "streaming")]
+mod stream;
+mod temporary;
+mod undo;
+
+/// Every command we support, each command lives in its own module and is only dispatched from here
+#[derive(Subcommand, Debug)]
+pub enum Commands {
+    /// Sit or use `save` to store the current height
+    Sit(movement::Sit),
+    /// Retry the Sit operation 5 times if the desk doesn't complete it
+    ForceSit,
+    /// Stand or use `save` to store the current height
+    Stand(movement::Stand),
+    /// Retry the Stand operation 5 times if the desk doesn't complete it
+    ForceStand,
+    /// Get the estimated desk height, use `--watch` to keep querying
+    Query(query::Query),
+    /// Sit -> Stand or Stand -> Sit
+    Toggle,
+    /// Retry the Toggle operation 5 times if the desk doesn't complete it
+    ForceToggle,
+    /// Print what we know about the desk, including the sit and stand heights we've learned
+    Info,
+    /// Listen for height changes
+    Listen(query::Listen),
+    /// Stream every height update to stdout for other programs
+    #[cfg(feature = "streaming")]
+    Stream(stream::Stream),
+    /// Run a command when the desk rises above or falls below a height
+    #[cfg(feature = "streaming")]
+    Alert(alert::Alert),
+    /// Move the desk to a height
+    Move(movement::Move),
+    /// Drive the desk with the arrow keys
+    Drive,
+    /// Lower the desk all the way and hold down to reset the controller, ie: after an E code
+    ResetProcedure(reset::ResetProcedure),
+    /// Stop a sit or stand that's still moving, `--all` stops every desk we've seen
+    Stop(stop::Stop),
+    /// Move the desk to a named position from the config
+    Goto(movement::Goto),
+    /// List the named positions from the config
+    Positions,
+    /// Run a named action from the config, moving back if any of its hooks fail
+    Action { name: String },
+    /// Read or change the config
+    Config {
+        #[clap(subcommand)]
+        command: config::ConfigCommand,
+    },
+    /// Find nearby desks or list the desks we've seen
+    Desks {
+        #[clap(subcommand)]
+        command: desks::DesksCommand,
+    },
+    /// Alternate between sitting and standing
+    #[cfg(feature = "schedule")]
+    Schedule(schedule::ScheduleArgs),
+    /// Cancel returning to the previous height after `--for`
+    Cancel,
+    /// Take over returning to the previous height after `--for` if uplift was stopped while waiting
+    Resume,
+    /// Return to the height before the last move
+    Undo,
+    /// Check that we're allowed to use Bluetooth, this shows the macOS permission prompt
+    Permissions(permissions::Permissions),
+    /// Connect to and query the desk repeatedly, printing percentiles for each phase
+    Bench(bench::Bench),
+    /// Stay connected for hours, querying and sending stops, and report every disconnect and error
+    Soak(soak::Soak),
+    /// Replace desk addresses, ids and names in a log or export with the pseudonyms from `--redact`
+    Redact(redact::Redact),
+    /// Debug the Bluetooth stack
+    Bt {
+        #[clap(subcommand)]
+        command: bt::BtCommand,
+    },
+    /// Explore the raw GATT services on a desk
+    Gatt {
+        #[clap(subcommand)]
+        command: gatt::GattCommand,
+    },
+    /// Run an alias from the config, ie: `focus = "goto drawing && action dnd-on"`
+    #[clap(external_subcommand)]
+    Alias(Vec<String>),
+    /// Run a command from an `uplift://` url like `uplift://move?height=30&desk=Uplift`
+    #[cfg(feature = "url-handler")]
+    HandleUrl { url: String },
+}
+
+impl Commands {
+    /// Temporary moves wait around to move back while watching and streaming run until they're
+    /// stopped, so none of them can be limited by `--timeout`
+    pub fn ignores_timeout(&self) -> bool {
+        match self {
+            Commands::Sit(sit) => sit.duration.is_some(),
+            Commands::Stand(stand) => stand.duration.is_some(),
+            Commands::Goto(goto) => goto.duration.is_some(),
+            Commands::Resume | Commands::Drive => true,
+            // each cycle has its own timeout
+            Commands::Bench(_) => true,
+            Commands::Soak(_) => true,
+            Commands::Query(query) => query.watch,
+            #[cfg(feature = "streaming")]
+            Commands::Stream(_) | Commands::Alert(_) => true,
+            // each desk has its own timeout
+            Commands::Desks {
+                command: desks::DesksCommand::Status { .. },
+            } => true,
+            Commands::Stop(stop) => stop.all,
+            _ => false,
+        }
+    }
+
+    /// The desk named by the command itself, which takes over from `--desk`
+    pub fn desk(&self) -> Option<String> {
+        match self {
+            #[cfg(feature = "url-handler")]
+            Commands::HandleUrl { url } => handle_url::desk(url),
+            _ => None,
+        }
+    }
+
+    /// Commands that move the desk, these are refused during quiet hours
+    fn moves_desk(&self) -> bool {
+        match self {
+            Commands::Sit(sit) => sit.save.is_none(),
+            Commands::Stand(stand) => stand.save.is_none(),
+            Commands::ForceSit
+            | Commands::ForceStand
+            | Commands::Toggle
+            | Commands::ForceToggle
+            | Commands::Move(_)
+            | Commands::Goto(_)
+            | Commands::Drive
+            | Commands::ResetProcedure(_)
+            | Commands::Action { .. }
+            | Commands::Resume
+            | Commands::Undo => true,
+            _ => false,
+        }
+    }
+
+    pub async fn run(&self, session: &Session) -> Result<(), anyhow::Error> {
+        if self.moves_desk() && !session.override_quiet_hours {
+            Config::load()?.quiet_hours.check()?;
+        }
+
+        match self {
+            Commands::Sit(sit) => sit.run(session).await,
+            Commands::ForceSit => force::force(session.desk().await?, Target::Sit).await,
+            Commands::Stand(stand) => stand.run(session).await,
+            Commands::ForceStand => force::force(session.desk().await?, Target::Stand).await,
+            Commands::Query(query) => query.run(session.desk().await?).await,
+            Commands::Toggle => movement::toggle(session.desk().await?, session.wait).await,
+            Commands::ForceToggle => force::force(session.desk().await?, Target::Toggle).await,
+            Commands::Info => info::run(session.desk().await?).await,
+            Commands::Listen(listen) => listen.run(session.desk().await?).await,
+            #[cfg(feature = "streaming")]
+            Commands::Stream(stream) => stream.run(session.desk().await?).await,
+            #[cfg(feature = "streaming")]
+            Commands::Alert(alert) => alert.run(session.desk().await?).await,
+            Commands::Move(move_to) => move_to.run(session.desk().await?).await,
+            Commands::Goto(goto) => goto.run(session).await,
+            Commands::Drive => drive::run(session.desk().await?).await,
+            Commands::ResetProcedure(reset) => reset.run(session.desk().await?).await,
+            Commands::Stop(stop) => stop.run(session).await,
+            Commands::Positions => positions::run(),
+            Commands::Action { name } => action::run(name, session.desk().await?).await,
+            Commands::Config { command } => command.run(),
+            Commands::Desks { command } => command.run(session).await,
+            Commands::Permissions(permissions) => permissions.run().await,
+            Commands::Bench(bench) => bench.run(session).await,
+            Commands::Soak(soak) => soak.run(session.desk().await?).await,
+            Commands::Redact(redact) => redact.run(),
+            Commands::Bt { command } => command.run().await,
+            Commands::Gatt { command } => command.run(session).await,
+            Commands::Cancel => temporary::cancel(),
+            Commands::Resume => {
+                temporary::resume(session.desk().await?, &session.quiet_hours()?).await
+            }
+            Commands::Undo => undo::run(session.desk().await?).await,
+            Commands::Alias(args) => alias::run(args, session).await,
+            #[cfg(feature = "schedule")]
+            Commands::Schedule(schedule) => schedule.run(session.desk().await?).await,
+            #[cfg(feature = "url-handler")]
+            Commands::HandleUrl { url } => handle_url::run(url, session).await,
+        }
+    }
+}
+
+/// How we turn the desk's height notifications into heights
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum HeightDecoder {
+    /// Use the low byte, falling back to the high byte near the top of the range
+    LowHigh,
+    /// Only use the low byte, how older versions decoded heights
+    LowByte,
+}
+
+/// Everything a command needs to run, we only connect to the desk if a command asks for it
+pub struct Session {
+    desk_filter: Option<String>,
+    decoder: Option<HeightDecoder>,
+    adapters: AdapterFilter,
+    write_spacing: Option<Duration>,
+    height_range: HeightRange,
+    override_quiet_hours: bool,
+    /// Whether sit, stand and toggle wait for the desk to stop
+    wait: bool,
+    desk: OnceCell<Desk>,
+}
+
+impl Session {
+    pub fn new(
+        desk_filter: Option<String>,
+        decoder: Option<HeightDecoder>,
+        adapters: AdapterFilter,
+        write_spacing: Option<Duration>,
+        height_range: HeightRange,
+        override_quiet_hours: bool,
+        wait: bool,
+    ) -> Session {
+        Session {
+            desk_filter,
+            decoder,
+            adapters,
+            write_spacing,
+            height_range,
+            override_quiet_hours,
+            wait,
+            desk: OnceCell::new(),
+        }
+    }
+
+    /// The `--desk` filter, for commands that need to connect without going through [Desk]
+    pub fn desk_filter(&self) -> Option<&str> {
+        self.desk_filter.as_deref()
+    }
+
+    /// The id of the desk we connected to, if we've connected
+    pub fn desk_id(&self) -> Option<&str> {
+        self.desk.get().map(Desk::id)
+    }
+
+    /// The quiet hours our automated moves respect, none with `--override-quiet-hours`
+    pub fn quiet_hours(&self) -> Result<QuietHours, anyhow::Error> {
+        if self.override_quiet_hours {
+            Ok(QuietHours::default())
+        } else {
+            Ok(Config::load()?.quiet_hours)
+        }
+    }
+
+    pub fn adapters(&self) -> &AdapterFilter {
+        &self.adapters
+    }
+
+    pub async fn desk(&self) -> Result<&Desk, anyhow::Error> {
+        self.desk
+            .get_or_try_init(|| async {
+                let desk_filter = self.desk_filter.as_deref();
+                let decoder = self.decoder.map(|decoder| match decoder {
+                    HeightDecoder::LowHigh => DecoderKind::LowHigh,
+                    HeightDecoder::LowByte => DecoderKind::LowByte,
+                });
+                let connect = Desk::with_options(desk_filter, decoder, &self.adapters);
+                tokio::pin!(connect);
+
+                // draw how long we've been scanning and connecting
+                let bar = ProgressBar::new("Connecting");
+                let started = Instant::now();
+                let mut ticks = time::interval(Duration::from_millis(100));
+                let desk = loop {
+                    tokio::select! {
+                        desk = &mut connect => break desk?,
+                        _ = ticks.tick() => bar.update(
+                            None,
+                            &format!("{:.1}s", started.elapsed().as_secs_f64()),
+                        ),
+                    }
+                };
+                drop(bar);
+                progress!("Connected to desk");
+
+                if let Some(write_spacing) = self.write_spacing {
+                    desk.set_write_spacing(write_spacing);
+                }
+                desk.set_range(self.height_range);
+
+                Ok(desk)
+            })
+            .await
+    }
+}
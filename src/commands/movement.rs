@@ -0,0 +1,240 @@
+use std::time::Duration;
+
+use clap::{Args, Subcommand};
+
+use crate::config::Config;
+use crate::units::Inches;
+use uplift_lib::desk::{
+    CommandOutcome, Desk, ForcePolicy, MoveProfile, Preset, Tolerance, AVG_MID_HEIGHT,
+};
+use uplift_lib::output::{self, ProgressBar};
+
+use crate::commands::{temporary, undo, Session};
+use crate::hooks::{self, HookContext, HookEvent};
+use crate::print::progress;
+
+#[derive(Args, Debug)]
+pub struct Sit {
+    #[clap(subcommand)]
+    pub save: Option<SaveCommand>,
+    /// Return to the previous height after this long, ie: `30m`
+    #[clap(long = "for", value_parser = humantime::parse_duration)]
+    pub duration: Option<Duration>,
+}
+
+#[derive(Args, Debug)]
+pub struct Stand {
+    #[clap(subcommand)]
+    pub save: Option<SaveCommand>,
+    /// Return to the previous height after this long, ie: `30m`
+    #[clap(long = "for", value_parser = humantime::parse_duration)]
+    pub duration: Option<Duration>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SaveCommand {
+    Save,
+}
+
+#[derive(Args, Debug)]
+pub struct Move {
+    /// Inches by default or use a unit like `87.6cm`
+    pub height: Inches,
+    /// Ramp the desk up to speed and back down, see `[ramp]` in the config
+    #[clap(long)]
+    pub ramp: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct Goto {
+    pub name: String,
+    /// Ramp the desk up to speed and back down, see `[ramp]` in the config
+    #[clap(long)]
+    pub ramp: bool,
+    /// Return to the previous height after this long, ie: `30m`
+    #[clap(long = "for", value_parser = humantime::parse_duration)]
+    pub duration: Option<Duration>,
+}
+
+impl Sit {
+    /// Sit, and with `--wait` block until the desk stops at our sit preset
+    pub async fn run(&self, session: &Session) -> Result<(), anyhow::Error> {
+        let desk = session.desk().await?;
+        if self.save.is_some() {
+            desk.save_sit().await?;
+            progress!("Saved sitting height");
+        } else {
+            let previous_height = desk.query_height().await?;
+            report(desk.sit().await?);
+            undo::record(desk, previous_height);
+            progress!("Sitting");
+            if session.wait {
+                wait_for(desk, Preset::Sit).await?;
+            }
+            fire_moved(desk, HookEvent::Sit).await?;
+
+            if let Some(duration) = self.duration {
+                let quiet_hours = session.quiet_hours()?;
+                return temporary::hold(desk, previous_height, duration, &quiet_hours).await;
+            }
+            return Ok(());
+        }
+
+        // let the packet actually send
+        desk.query_height().await?;
+
+        Ok(())
+    }
+}
+
+impl Stand {
+    /// Stand, and with `--wait` block until the desk stops at our stand preset
+    pub async fn run(&self, session: &Session) -> Result<(), anyhow::Error> {
+        let desk = session.desk().await?;
+        if self.save.is_some() {
+            desk.save_stand().await?;
+            progress!("Saved standing height");
+        } else {
+            let previous_height = desk.query_height().await?;
+            report(desk.stand().await?);
+            undo::record(desk, previous_height);
+            progress!("Standing");
+            if session.wait {
+                wait_for(desk, Preset::Stand).await?;
+            }
+            fire_moved(desk, HookEvent::Stand).await?;
+
+            if let Some(duration) = self.duration {
+                let quiet_hours = session.quiet_hours()?;
+                return temporary::hold(desk, previous_height, duration, &quiet_hours).await;
+            }
+            return Ok(());
+        }
+
+        // let the packet actually send
+        desk.query_height().await?;
+
+        Ok(())
+    }
+}
+
+fn move_profile(ramp: bool) -> Result<MoveProfile, anyhow::Error> {
+    Ok(if ramp {
+        MoveProfile::Ramp(Config::load()?.ramp())
+    } else {
+        MoveProfile::Constant
+    })
+}
+
+/// Move to `target`, drawing how far we've come on a terminal
+async fn move_with_bar(
+    desk: &Desk,
+    target: isize,
+    profile: MoveProfile,
+    tolerance: Tolerance,
+) -> Result<isize, anyhow::Error> {
+    let start = desk.height();
+    let distance = (target - start).abs().max(1);
+    let bar = ProgressBar::new("Moving");
+
+    desk.move_to_with_progress(target, profile, tolerance, |height| {
+        bar.update(
+            Some(1.0 - (target - height).abs() as f64 / distance as f64),
+            &format!("{} / {}", output::height(height), output::height(target)),
+        );
+    })
+    .await
+}
+
+/// Warn if the desk didn't respond, it's likely to have ignored us
+fn report(outcome: CommandOutcome) {
+    if outcome == CommandOutcome::Unacknowledged {
+        progress!("The desk didn't acknowledge the command");
+    }
+}
+
+pub async fn toggle(desk: &Desk, wait: bool) -> Result<(), anyhow::Error> {
+    let height = desk.query_height().await?;
+    let (preset, event) = if height > AVG_MID_HEIGHT {
+        report(desk.sit().await?);
+        progress!("Sitting");
+        (Preset::Sit, HookEvent::Sit)
+    } else {
+        report(desk.stand().await?);
+        progress!("Standing");
+        (Preset::Stand, HookEvent::Stand)
+    };
+    undo::record(desk, height);
+    if wait {
+        wait_for(desk, preset).await?;
+    }
+
+    fire_moved(desk, event).await
+}
+
+/// Block until the desk stops, failing if it didn't stop at `preset`
+async fn wait_for(desk: &Desk, preset: Preset) -> Result<(), anyhow::Error> {
+    let policy = ForcePolicy {
+        tolerance: Config::load()?.tolerance(),
+        ..ForcePolicy::default()
+    };
+    let height = desk.wait_for_preset(preset, policy).await?;
+    progress!("Reached {}", output::height(height));
+
+    Ok(())
+}
+
+/// Run the `on_sit` or `on_stand` hook once the desk has heard our command
+async fn fire_moved(desk: &Desk, event: HookEvent) -> Result<(), anyhow::Error> {
+    // this also lets the packet actually send
+    let height = desk.query_height().await?;
+    hooks::fire(HookContext::new(event, Some(desk.id())).height(height)).await;
+
+    Ok(())
+}
+
+/// Run the `on_target` hook after reaching `target`
+pub async fn fire_reached(desk: &Desk, target: isize, height: isize) {
+    hooks::fire(
+        HookContext::new(HookEvent::Target, Some(desk.id()))
+            .height(height)
+            .target(target),
+    )
+    .await;
+}
+
+impl Move {
+    pub async fn run(&self, desk: &Desk) -> Result<(), anyhow::Error> {
+        let profile = move_profile(self.ramp)?;
+        let tolerance = Config::load()?.tolerance();
+        let previous_height = desk.query_height().await?;
+        let height = move_with_bar(desk, self.height.to_tenths(), profile, tolerance).await?;
+        undo::record(desk, previous_height);
+        progress!("Reached {}", output::height(height));
+        fire_reached(desk, self.height.to_tenths(), height).await;
+
+        Ok(())
+    }
+}
+
+impl Goto {
+    pub async fn run(&self, session: &Session) -> Result<(), anyhow::Error> {
+        let desk = session.desk().await?;
+        let config = Config::load()?;
+        let target = config.position(&self.name)?;
+        let profile = move_profile(self.ramp)?;
+        let previous_height = desk.query_height().await?;
+        let height = move_with_bar(desk, target.to_tenths(), profile, config.tolerance()).await?;
+        undo::record(desk, previous_height);
+        progress!("Reached {} at {}", self.name, output::height(height));
+        fire_reached(desk, target.to_tenths(), height).await;
+
+        match self.duration {
+            Some(duration) => {
+                let quiet_hours = session.quiet_hours()?;
+                temporary::hold(desk, previous_height, duration, &quiet_hours).await
+            }
+            None => Ok(()),
+        }
+    }
+}
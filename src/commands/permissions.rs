@@ -0,0 +1,79 @@
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use btleplug::api::{Central, CentralState, Manager as _};
+use clap::Args;
+use tokio::time;
+
+use uplift_lib::desk::{self, ConnectError, PERMISSION_HELP};
+
+use crate::print::progress;
+
+// the Bluetooth page of the macOS privacy settings
+const MACOS_BLUETOOTH_SETTINGS: &str =
+    "x-apple.systempreferences:com.apple.preference.security?Privacy_Bluetooth";
+// how long the OS has to tell us if Bluetooth is on, macOS asks for permission in the meantime
+const STATE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Args, Debug)]
+pub struct Permissions {
+    /// Open the Bluetooth privacy settings (macOS)
+    #[clap(long)]
+    pub open: bool,
+}
+
+impl Permissions {
+    /// Ask the OS for Bluetooth and wait until it tells us if our adapters are on. On macOS this is
+    /// what shows the permission prompt.
+    pub async fn run(&self) -> Result<(), anyhow::Error> {
+        if self.open {
+            open_settings().await?;
+        }
+
+        let manager = desk::manager().await?;
+        let adapters = manager.adapters().await.map_err(|e| match e {
+            btleplug::Error::PermissionDenied => anyhow::Error::new(e).context(PERMISSION_HELP),
+            e => e.into(),
+        })?;
+        if adapters.is_empty() {
+            return Err(ConnectError::NoAdapter.into());
+        }
+
+        for adapter in adapters {
+            let info = adapter.adapter_info().await?;
+            let started = Instant::now();
+            let state = loop {
+                let state = adapter.adapter_state().await?;
+                if state != CentralState::Unknown || started.elapsed() >= STATE_TIMEOUT {
+                    break state;
+                }
+                time::sleep(Duration::from_millis(100)).await;
+            };
+
+            match state {
+                CentralState::PoweredOn => println!("{info}: ready"),
+                CentralState::PoweredOff => println!("{info}: Bluetooth is off"),
+                CentralState::Unknown => {
+                    println!("{info}: unknown");
+                    progress!("{PERMISSION_HELP}");
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+async fn open_settings() -> Result<(), anyhow::Error> {
+    if cfg!(target_os = "macos") {
+        tokio::process::Command::new("open")
+            .arg(MACOS_BLUETOOTH_SETTINGS)
+            .status()
+            .await
+            .context("Failed to open the Bluetooth settings")?;
+    } else {
+        progress!("Opening the Bluetooth settings is only supported on macOS");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,10 @@
+use crate::config::Config;
+use uplift_lib::output;
+
+pub fn run() -> Result<(), anyhow::Error> {
+    for (name, height) in Config::load()?.positions {
+        println!("{name}: {}", output::height(height.to_tenths()));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,192 @@
+use std::time::Duration;
+#[cfg(feature = "streaming")]
+use std::{future, time::Instant};
+
+use clap::Args;
+#[cfg(feature = "streaming")]
+use futures::StreamExt;
+use tokio::time;
+
+use uplift_lib::desk::Desk;
+use uplift_lib::output;
+
+#[cfg(feature = "streaming")]
+use crate::print::progress;
+
+// how often `listen --high-rate` asks the desk for its height
+#[cfg(feature = "streaming")]
+const HIGH_RATE_INTERVAL: Duration = Duration::from_millis(20);
+
+#[derive(Args, Debug)]
+pub struct Query {
+    /// Keep querying the height
+    #[clap(long)]
+    pub watch: bool,
+    /// Only print heights that changed while watching
+    #[clap(long, requires = "watch")]
+    pub changes_only: bool,
+    /// How often to query while watching
+    #[clap(long, value_parser = humantime::parse_duration, default_value = "1s", requires = "watch")]
+    pub interval: Duration,
+    /// Print with a template, ie: `{height_in}`, `{height_cm}` or `{height}` in our display format
+    #[clap(long)]
+    pub format: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct Listen {
+    /// Print posture changes (sitting, standing, moving) instead of heights
+    #[cfg(feature = "streaming")]
+    #[clap(long)]
+    pub posture: bool,
+    /// Print every notification with a microsecond timestamp
+    #[cfg(feature = "streaming")]
+    #[clap(long, conflicts_with = "posture")]
+    pub raw: bool,
+    /// Keep asking the desk for its height so it notifies us as often as it can
+    #[cfg(feature = "streaming")]
+    #[clap(long, requires = "raw")]
+    pub high_rate: bool,
+    /// Stop after this many notifications and print how evenly they arrived
+    #[cfg(feature = "streaming")]
+    #[clap(long, requires = "raw")]
+    pub samples: Option<usize>,
+}
+
+impl Query {
+    pub async fn run(&self, desk: &Desk) -> Result<(), anyhow::Error> {
+        let mut last_height = None;
+        loop {
+            let height = desk.query_height().await?;
+            if !self.changes_only || last_height != Some(height) {
+                println!("{}", self.format_height(height));
+            }
+            last_height = Some(height);
+
+            if !self.watch {
+                return Ok(());
+            }
+            time::sleep(self.interval).await;
+        }
+    }
+
+    fn format_height(&self, height: isize) -> String {
+        match &self.format {
+            Some(format) => format
+                .replace("{height_in}", &(height as f32 / 10.0).to_string())
+                .replace(
+                    "{height_cm}",
+                    &((height as f32 * 2.54).round() / 10.0).to_string(),
+                )
+                .replace("{height}", &output::height(height)),
+            None => output::height(height),
+        }
+    }
+}
+
+impl Listen {
+    pub async fn run(&self, desk: &Desk) -> Result<(), anyhow::Error> {
+        #[cfg(feature = "streaming")]
+        if self.posture {
+            let mut postures = desk.subscribe_posture();
+            while let Some(posture) = postures.next().await {
+                println!("{posture}");
+            }
+
+            return Ok(());
+        }
+        #[cfg(feature = "streaming")]
+        if self.raw {
+            return self.run_raw(desk).await;
+        }
+
+        let mut height = 0;
+        loop {
+            let next_height = desk.height();
+            if height != next_height {
+                let (low, high) = desk.raw_height();
+                println!("height: ({low:x},{high:x}) -> {next_height}");
+            }
+            height = next_height;
+
+            time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    #[cfg(feature = "streaming")]
+    async fn run_raw(&self, desk: &Desk) -> Result<(), anyhow::Error> {
+        let start = Instant::now();
+        let mut updates = desk.subscribe_height();
+        let mut intervals = Vec::new();
+
+        let capture = async {
+            let mut last_received = None;
+            while let Some(update) = updates.next().await {
+                let (low, high) = update.raw;
+                println!(
+                    "{}us: ({low:x},{high:x}) -> {}",
+                    update.received.saturating_duration_since(start).as_micros(),
+                    update.height
+                );
+
+                if let Some(last_received) = last_received {
+                    intervals.push(update.received.saturating_duration_since(last_received));
+                }
+                last_received = Some(update.received);
+
+                if self
+                    .samples
+                    .is_some_and(|samples| intervals.len() + 1 >= samples)
+                {
+                    break;
+                }
+            }
+        };
+        let requests = async {
+            if !self.high_rate {
+                return future::pending().await;
+            }
+            loop {
+                desk.request_height().await?;
+                time::sleep(HIGH_RATE_INTERVAL).await;
+            }
+        };
+
+        tokio::select! {
+            _ = capture => {},
+            result = requests => return result,
+        }
+
+        print_jitter(&intervals);
+
+        Ok(())
+    }
+}
+
+/// Summarize the time between notifications, a steady desk has a small standard deviation
+#[cfg(feature = "streaming")]
+fn print_jitter(intervals: &[Duration]) {
+    if intervals.is_empty() {
+        progress!("Not enough notifications to measure jitter");
+        return;
+    }
+
+    let micros = intervals
+        .iter()
+        .map(|interval| interval.as_micros() as f64)
+        .collect::<Vec<_>>();
+    let mean = micros.iter().sum::<f64>() / micros.len() as f64;
+    let variance = micros
+        .iter()
+        .map(|interval| (interval - mean).powi(2))
+        .sum::<f64>()
+        / micros.len() as f64;
+    let min = micros.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = micros.iter().copied().fold(0.0, f64::max);
+
+    progress!(
+        "{} intervals: mean {mean:.0}us, std dev {:.0}us, min {min:.0}us, max {max:.0}us",
+        micros.len(),
+        variance.sqrt()
+    );
+}
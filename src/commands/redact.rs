@@ -0,0 +1,33 @@
+use std::fs;
+use std::io::{self, Read};
+use std::path::PathBuf;
+
+use anyhow::Context;
+use clap::Args;
+
+use uplift_lib::redact;
+
+#[derive(Args, Debug)]
+pub struct Redact {
+    /// The log or export to scrub, or `-` to read stdin
+    pub file: PathBuf,
+}
+
+impl Redact {
+    /// Print `file` with every desk address, id and name replaced by the pseudonyms `--redact` uses
+    pub fn run(&self) -> Result<(), anyhow::Error> {
+        let text = if self.file.as_os_str() == "-" {
+            let mut text = String::new();
+            io::stdin()
+                .read_to_string(&mut text)
+                .context("Failed to read stdin")?;
+            text
+        } else {
+            fs::read_to_string(&self.file)
+                .with_context(|| format!("Failed to read {}", self.file.display()))?
+        };
+        print!("{}", redact::redact(&text));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,82 @@
+use std::time::{Duration, Instant};
+
+use clap::Args;
+
+use uplift_lib::desk::{Desk, Nudge, MIN_PHYSICAL_HEIGHT};
+use uplift_lib::output::{self, ProgressBar};
+
+use crate::print::{progress, verbose};
+
+// once the height hasn't changed for this long we're at the bottom
+const BOTTOM_SETTLE: Duration = Duration::from_secs(2);
+
+#[derive(Args, Debug)]
+pub struct ResetProcedure {
+    /// How long to keep holding down once the desk reaches the bottom, ie: `10s`
+    #[clap(long, value_parser = humantime::parse_duration, default_value = "10s")]
+    pub hold: Duration,
+}
+
+impl ResetProcedure {
+    /// The keypad reset is to lower the desk all the way and keep holding down until the controller
+    /// relearns its range. We can't press the keypad but we can keep sending down pulses, which is
+    /// what holding the button does.
+    pub async fn run(&self, desk: &Desk) -> Result<(), anyhow::Error> {
+        let start = desk.query_height().await?;
+        progress!(
+            "Lowering from {} to the bottom, keep clear under the desk",
+            output::height(start)
+        );
+
+        let bottom = self.lower(desk, start).await?;
+        progress!(
+            "Reached the bottom at {}, holding down for {}",
+            output::height(bottom),
+            humantime::format_duration(self.hold)
+        );
+
+        let deadline = Instant::now() + self.hold;
+        let bar = ProgressBar::new("Holding");
+        while Instant::now() < deadline {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            bar.update(
+                Some(1.0 - remaining.as_secs_f64() / self.hold.as_secs_f64()),
+                &format!("{}s left", remaining.as_secs()),
+            );
+            desk.nudge(Nudge::Down).await?;
+        }
+        drop(bar);
+
+        let height = desk.query_height().await?;
+        progress!(
+            "Finished at {}. If the keypad still shows a reset code like ASR or an E code, hold ▼ on \
+            the keypad until the desk beeps and moves slightly",
+            output::height(height)
+        );
+
+        Ok(())
+    }
+
+    /// Keep pulsing down until the height stops changing, returns the height we stopped at
+    async fn lower(&self, desk: &Desk, start: isize) -> Result<isize, anyhow::Error> {
+        let bar = ProgressBar::new("Lowering");
+        let distance = (start - MIN_PHYSICAL_HEIGHT).max(1) as f64;
+        let mut last_height = start;
+        let mut last_moved = Instant::now();
+
+        while last_moved.elapsed() < BOTTOM_SETTLE {
+            let height = desk.nudge(Nudge::Down).await?;
+            if height != last_height {
+                verbose!("Lowering: {}", output::height(height));
+                last_height = height;
+                last_moved = Instant::now();
+            }
+            bar.update(
+                Some((start - height) as f64 / distance),
+                &output::height(height),
+            );
+        }
+
+        Ok(last_height)
+    }
+}
@@ -0,0 +1,70 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::Args;
+use jiff::tz::TimeZone;
+
+use uplift_lib::calendar::{ActiveHours, TimeWindow, Weekdays};
+use uplift_lib::desk::Desk;
+use uplift_lib::focus;
+use uplift_lib::schedule::{Schedule, SystemClock};
+
+use crate::config::Config;
+
+#[derive(Args, Debug)]
+pub struct ScheduleArgs {
+    /// How long to sit, ie: `45m`
+    #[clap(long, value_parser = humantime::parse_duration, default_value = "45m")]
+    pub sit: Duration,
+    /// How long to stand, ie: `15m`
+    #[clap(long, value_parser = humantime::parse_duration, default_value = "15m")]
+    pub stand: Duration,
+    /// Skip moving while Do Not Disturb / Focus mode is on
+    #[clap(long)]
+    pub respect_focus: bool,
+    /// After someone moves the desk by hand, wait this long before the next move, ie: `10m`
+    #[clap(long, value_parser = humantime::parse_duration)]
+    pub manual_grace: Option<Duration>,
+    /// While standing, shift the desk about 0.2" this often to encourage moving, ie: `5m`
+    #[clap(long, value_parser = humantime::parse_duration)]
+    pub micro_adjust: Option<Duration>,
+    /// Only move on these days, ie: `weekdays`, `mon-thu` or `mon,wed,fri`
+    #[clap(long)]
+    pub days: Option<Weekdays>,
+    /// Only move between these local times, ie: `09:00-17:00`
+    #[clap(long)]
+    pub hours: Option<TimeWindow>,
+    /// The time zone for `--days` and `--hours` instead of the system one, ie: `Europe/Berlin`
+    #[clap(long, value_parser = TimeZone::get)]
+    pub timezone: Option<TimeZone>,
+}
+
+impl ScheduleArgs {
+    pub async fn run(&self, desk: &Desk) -> Result<(), anyhow::Error> {
+        Schedule {
+            sit: self.sit,
+            stand: self.stand,
+            respect_focus: self.respect_focus,
+            manual_grace: self.manual_grace,
+            micro_adjust: self.micro_adjust,
+            quiet_hours: Config::load()?.quiet_hours,
+            active_hours: self.active_hours(),
+            clock: Arc::new(SystemClock),
+            focus: focus::is_focus_active,
+        }
+        .run(desk)
+        .await
+    }
+
+    fn active_hours(&self) -> Option<ActiveHours> {
+        if self.days.is_none() && self.hours.is_none() {
+            return None;
+        }
+
+        Some(ActiveHours {
+            days: self.days.unwrap_or_default(),
+            hours: self.hours,
+            time_zone: self.timezone.clone().unwrap_or_else(TimeZone::system),
+        })
+    }
+}
@@ -0,0 +1,169 @@
+use std::time::{Duration, Instant};
+
+use clap::Args;
+use jiff::Timestamp;
+use tokio::time;
+
+use uplift_lib::desk::{CommandOutcome, ConnectionState, Desk};
+use uplift_lib::output;
+use uplift_lib::redact;
+
+use crate::print::{progress, verbose};
+
+// how often we look at our connection state between queries
+const CONNECTION_CHECK: Duration = Duration::from_secs(1);
+
+#[derive(Args, Debug)]
+pub struct Soak {
+    /// How many hours to stay connected for
+    #[clap(long, default_value_t = 8.0)]
+    pub hours: f64,
+    /// How often to query the height, ie: `30s`
+    #[clap(long, value_parser = humantime::parse_duration, default_value = "30s")]
+    pub query_interval: Duration,
+    /// How often to send a stop, which the desk acknowledges without moving, ie: `5m`
+    #[clap(long, value_parser = humantime::parse_duration, default_value = "5m")]
+    pub command_interval: Duration,
+}
+
+/// Something that went wrong, or recovered, while we were connected
+enum SoakEvent {
+    Connection(ConnectionState),
+    QueryFailed(String),
+    CommandUnacknowledged,
+    CommandFailed(String),
+}
+
+#[derive(Default)]
+struct SoakReport {
+    events: Vec<(Timestamp, SoakEvent)>,
+    queries: usize,
+    failed_queries: usize,
+    commands: usize,
+    unacknowledged_commands: usize,
+    failed_commands: usize,
+    disconnects: usize,
+    reconnects: usize,
+    longest_outage: Duration,
+}
+
+impl Soak {
+    /// Keep one connection open for `hours`, querying and sending harmless commands, and record
+    /// everything that goes wrong so flaky adapters can be compared
+    pub async fn run(&self, desk: &Desk) -> Result<(), anyhow::Error> {
+        let length = Duration::from_secs_f64(self.hours.max(0.0) * 60.0 * 60.0);
+        progress!(
+            "Soaking for {}, querying every {} and sending a stop every {}",
+            humantime::format_duration(length),
+            humantime::format_duration(self.query_interval),
+            humantime::format_duration(self.command_interval)
+        );
+
+        let mut report = SoakReport::default();
+        let started = Instant::now();
+        let mut next_query = started;
+        let mut next_command = started + self.command_interval;
+        let mut state = desk.connection_state();
+        let mut outage_started: Option<Instant> = None;
+        while started.elapsed() < length {
+            let next_state = desk.connection_state();
+            if next_state != state {
+                match next_state {
+                    ConnectionState::Connected => {
+                        if let Some(outage_started) = outage_started.take() {
+                            report.longest_outage =
+                                report.longest_outage.max(outage_started.elapsed());
+                            report.reconnects += 1;
+                        }
+                    }
+                    _ if state == ConnectionState::Connected => {
+                        outage_started = Some(Instant::now());
+                        report.disconnects += 1;
+                    }
+                    _ => {}
+                }
+                report.record(SoakEvent::Connection(next_state));
+                state = next_state;
+            }
+
+            let now = Instant::now();
+            if now >= next_query {
+                next_query = now + self.query_interval;
+                report.queries += 1;
+                match desk.query_height().await {
+                    Ok(height) => verbose!("Height {}", output::height(height)),
+                    Err(e) => {
+                        report.failed_queries += 1;
+                        report.record(SoakEvent::QueryFailed(format!("{e:#}")));
+                    }
+                }
+            }
+            if now >= next_command {
+                next_command = now + self.command_interval;
+                report.commands += 1;
+                match desk.stop().await {
+                    Ok(CommandOutcome::Acknowledged) => {}
+                    Ok(CommandOutcome::Unacknowledged) => {
+                        report.unacknowledged_commands += 1;
+                        report.record(SoakEvent::CommandUnacknowledged);
+                    }
+                    Err(e) => {
+                        report.failed_commands += 1;
+                        report.record(SoakEvent::CommandFailed(format!("{e:#}")));
+                    }
+                }
+            }
+
+            time::sleep(CONNECTION_CHECK).await;
+        }
+
+        // we might have finished while still waiting to reconnect
+        if let Some(outage_started) = outage_started {
+            report.longest_outage = report.longest_outage.max(outage_started.elapsed());
+        }
+        report.print(started.elapsed());
+
+        Ok(())
+    }
+}
+
+impl SoakReport {
+    fn record(&mut self, event: SoakEvent) {
+        let now = Timestamp::now();
+        progress!("{now} {}", event.describe());
+        self.events.push((now, event));
+    }
+
+    fn print(&self, elapsed: Duration) {
+        let elapsed = Duration::from_secs(elapsed.as_secs());
+        println!("duration: {}", humantime::format_duration(elapsed));
+        println!("queries: {} ({} failed)", self.queries, self.failed_queries);
+        println!(
+            "commands: {} ({} unacknowledged, {} failed)",
+            self.commands, self.unacknowledged_commands, self.failed_commands
+        );
+        println!(
+            "disconnects: {}, reconnects: {}",
+            self.disconnects, self.reconnects
+        );
+        println!("longest outage: {:.1}s", self.longest_outage.as_secs_f32());
+
+        if !self.events.is_empty() {
+            println!("events:");
+            for (timestamp, event) in &self.events {
+                println!("  {timestamp} {}", redact::scrub(event.describe()));
+            }
+        }
+    }
+}
+
+impl SoakEvent {
+    fn describe(&self) -> String {
+        match self {
+            SoakEvent::Connection(state) => format!("connection {state}"),
+            SoakEvent::QueryFailed(e) => format!("query failed: {e}"),
+            SoakEvent::CommandUnacknowledged => "stop wasn't acknowledged".to_string(),
+            SoakEvent::CommandFailed(e) => format!("stop failed: {e}"),
+        }
+    }
+}
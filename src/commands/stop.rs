@@ -0,0 +1,104 @@
+use std::time::Duration;
+
+use anyhow::bail;
+use clap::Args;
+use futures::{future, StreamExt};
+
+use uplift_lib::desk::{self, BatchOutcome, CommandBatch, CommandOutcome, Desk};
+use uplift_lib::output;
+use uplift_lib::protocol::STOP;
+use uplift_lib::redact;
+use uplift_lib::storage;
+
+use crate::commands::Session;
+use crate::print::progress;
+
+#[derive(Args, Debug)]
+pub struct Stop {
+    /// Stop every desk we've seen at once instead of just ours, for when automation misfires
+    #[clap(long)]
+    pub all: bool,
+    /// How long to look for each desk with `--all`, and then to connect to it, before giving up on
+    /// it, ie: `10s`
+    #[clap(long, value_parser = humantime::parse_duration, default_value = "10s", requires = "all")]
+    pub desk_timeout: Duration,
+}
+
+impl Stop {
+    pub async fn run(&self, session: &Session) -> Result<(), anyhow::Error> {
+        if !self.all {
+            let outcome = stop(session.desk().await?).await?;
+            println!("{}", describe(outcome));
+            return Ok(());
+        }
+
+        let desks = storage::desk_history()?;
+        if desks.is_empty() {
+            bail!("We haven't seen any desks yet, `uplift desks list` finds nearby ones");
+        }
+        progress!("Stopping {} desks", desks.len());
+
+        // one scan for every desk and a stop as soon as each one connects, waiting on a slow desk
+        // shouldn't keep the others moving
+        let ids = desks.iter().map(|desk| desk.id.clone()).collect();
+        let mut stopped = 0;
+        desk::connect_each(ids, session.adapters(), self.desk_timeout, desks.len())
+            .await?
+            .map(|(id, connected)| async move {
+                let outcome = async {
+                    let connected = connected?;
+                    let outcome = stop(&connected).await?;
+                    if let Err(e) = connected.disconnect().await {
+                        log::warn!("Failed to disconnect from {id}: {e:?}");
+                    }
+
+                    Ok::<_, anyhow::Error>(outcome)
+                }
+                .await;
+
+                (id, outcome)
+            })
+            .buffer_unordered(desks.len())
+            .for_each(|(id, outcome)| {
+                let status = match outcome {
+                    Ok(outcome) => {
+                        stopped += 1;
+                        describe(outcome)
+                    }
+                    Err(e) => format!("unreachable: {e:#}"),
+                };
+                let name = desks
+                    .iter()
+                    .find(|desk| desk.id == id)
+                    .and_then(|desk| desk.name.as_deref());
+                let line = format!("{id}\t{}\t{status}", name.unwrap_or("-"));
+                println!("{}", redact::scrub(line));
+
+                future::ready(())
+            })
+            .await;
+
+        if stopped == 0 {
+            bail!("Couldn't reach any of our {} desks", desks.len());
+        }
+
+        Ok(())
+    }
+}
+
+/// Stop and ask for the height in one batch so we can say where the desk stopped
+async fn stop(desk: &Desk) -> Result<BatchOutcome, anyhow::Error> {
+    desk.send_batch(&CommandBatch::new().command(STOP).verify())
+        .await
+}
+
+fn describe(outcome: BatchOutcome) -> String {
+    let acknowledged = match outcome.outcome {
+        CommandOutcome::Acknowledged => "acknowledged",
+        CommandOutcome::Unacknowledged => "unacknowledged",
+    };
+    match outcome.height {
+        Some(height) => format!("{acknowledged} at {}", output::height(height)),
+        None => acknowledged.to_string(),
+    }
+}
@@ -0,0 +1,55 @@
+use std::io::{self, Write};
+use std::time::Instant;
+
+use clap::{Args, ValueEnum};
+use futures::StreamExt;
+use serde::Serialize;
+
+use uplift_lib::desk::Desk;
+use uplift_lib::output::Height;
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+pub enum StreamFormat {
+    /// One JSON object per line
+    #[default]
+    Jsonl,
+}
+
+#[derive(Args, Debug)]
+pub struct Stream {
+    #[clap(long, value_enum, default_value_t)]
+    pub format: StreamFormat,
+}
+
+/// One line of `--format jsonl`
+#[derive(Serialize)]
+struct StreamLine {
+    elapsed_ms: u64,
+    #[serde(flatten)]
+    height: Height,
+    raw: (u8, u8),
+}
+
+impl Stream {
+    /// Print every height notification as soon as we get it, see the Readme for the schema
+    pub async fn run(&self, desk: &Desk) -> Result<(), anyhow::Error> {
+        let start = Instant::now();
+        let mut updates = desk.subscribe_height();
+
+        while let Some(update) = updates.next().await {
+            let line = match self.format {
+                StreamFormat::Jsonl => serde_json::to_string(&StreamLine {
+                    elapsed_ms: update.received.saturating_duration_since(start).as_millis() as u64,
+                    height: Height::from_tenths(update.height),
+                    raw: update.raw,
+                })?,
+            };
+
+            let mut stdout = io::stdout().lock();
+            writeln!(stdout, "{line}")?;
+            stdout.flush()?;
+        }
+
+        Ok(())
+    }
+}
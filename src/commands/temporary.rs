@@ -0,0 +1,375 @@
+use std::process;
+use std::time::{Duration, SystemTime};
+
+use anyhow::bail;
+use tokio::time::{self, Instant};
+
+use uplift_lib::desk::{DeskControl, MoveProfile};
+use uplift_lib::output;
+use uplift_lib::quiet_hours::{QuietHours, QuietMode};
+use uplift_lib::storage::{self, TemporaryPosition};
+
+use crate::config::Config;
+use crate::print::progress;
+
+// how often we check if we've been cancelled
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Wait for `duration` then move back to `return_to`, unless `uplift cancel` clears our state first.
+/// Moving back is automated so it waits out or skips `quiet_hours` like the schedule does.
+pub async fn hold(
+    desk: &impl DeskControl,
+    return_to: isize,
+    duration: Duration,
+    quiet_hours: &QuietHours,
+) -> Result<(), anyhow::Error> {
+    let temporary = TemporaryPosition {
+        desk: desk.id().to_string(),
+        return_to,
+        until: SystemTime::now() + duration,
+        pid: process::id(),
+    };
+    storage::save_temporary_position(Some(&temporary))?;
+    progress!(
+        "Returning to {} in {}",
+        output::height(return_to),
+        humantime::format_duration(duration)
+    );
+
+    // `until` is for other processes, we wait on tokio's clock
+    if wait_unless_cancelled(duration).await? {
+        return Ok(());
+    }
+
+    if let Some((window, remaining)) = quiet_hours.active() {
+        match quiet_hours.mode {
+            QuietMode::Defer => {
+                progress!(
+                    "Quiet hours ({window}), waiting {} before returning",
+                    humantime::format_duration(remaining)
+                );
+                if wait_unless_cancelled(remaining).await? {
+                    return Ok(());
+                }
+            }
+            QuietMode::Refuse => {
+                log::info!("Skipped returning because of quiet hours {window}");
+                progress!(
+                    "Skipped returning to {}, quiet hours ({window})",
+                    output::height(return_to)
+                );
+                return storage::save_temporary_position(None);
+            }
+        }
+    }
+
+    let height = desk
+        .move_to_with(
+            return_to,
+            MoveProfile::Constant,
+            Config::load()?.tolerance(),
+        )
+        .await?;
+    progress!("Returned to {}", output::height(height));
+
+    storage::save_temporary_position(None)
+}
+
+/// Take over moving back after `--for` from a process that stopped, moving back now if we're late
+pub async fn resume(
+    desk: &impl DeskControl,
+    quiet_hours: &QuietHours,
+) -> Result<(), anyhow::Error> {
+    let Some(temporary) = storage::temporary_position()? else {
+        progress!("Nothing to resume");
+        return Ok(());
+    };
+    if temporary.desk != desk.id() {
+        bail!(
+            "We're waiting to move {} back, use `--desk {}` to resume it",
+            temporary.desk,
+            temporary.desk
+        );
+    }
+
+    // if the original process is still waiting it sees our pid and stops
+    let remaining = temporary
+        .until
+        .duration_since(SystemTime::now())
+        .unwrap_or_default();
+    hold(desk, temporary.return_to, remaining, quiet_hours).await
+}
+
+pub fn cancel() -> Result<(), anyhow::Error> {
+    match storage::temporary_position()? {
+        Some(temporary) => {
+            storage::save_temporary_position(None)?;
+            progress!(
+                "Cancelled returning to {}",
+                output::height(temporary.return_to)
+            );
+        }
+        None => progress!("Nothing to cancel"),
+    }
+
+    Ok(())
+}
+
+/// Sleep for `duration`, returning true if we were cancelled first
+async fn wait_unless_cancelled(duration: Duration) -> Result<bool, anyhow::Error> {
+    let deadline = Instant::now() + duration;
+    while Instant::now() < deadline {
+        time::sleep_until(deadline.min(Instant::now() + CANCEL_POLL_INTERVAL)).await;
+
+        if !is_ours(storage::temporary_position()?.as_ref()) {
+            progress!("Cancelled");
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+fn is_ours(temporary: Option<&TemporaryPosition>) -> bool {
+    temporary.is_some_and(|temporary| temporary.pid == process::id())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::sync::atomic::{AtomicIsize, Ordering};
+    use std::sync::Once;
+
+    use tokio::sync::{Mutex, MutexGuard};
+    use uplift_lib::desk::{ForcePolicy, Nudge, Target, Tolerance};
+
+    use crate::config;
+
+    use super::*;
+
+    const RAISED: isize = 300;
+    const RETURN_TO: isize = 260;
+
+    /// Only moves back to where it was
+    struct FakeDesk {
+        id: &'static str,
+        height: AtomicIsize,
+    }
+
+    impl FakeDesk {
+        fn raised(id: &'static str) -> Self {
+            FakeDesk {
+                id,
+                height: AtomicIsize::new(RAISED),
+            }
+        }
+    }
+
+    impl DeskControl for FakeDesk {
+        fn id(&self) -> &str {
+            self.id
+        }
+
+        fn height(&self) -> isize {
+            self.height.load(Ordering::Relaxed)
+        }
+
+        async fn query_height(&self) -> Result<isize, anyhow::Error> {
+            Ok(self.height())
+        }
+
+        async fn nudge(&self, _direction: Nudge) -> Result<isize, anyhow::Error> {
+            unreachable!("We only move back")
+        }
+
+        async fn move_to_with(
+            &self,
+            target: isize,
+            _profile: MoveProfile,
+            _tolerance: Tolerance,
+        ) -> Result<isize, anyhow::Error> {
+            self.height.store(target, Ordering::Relaxed);
+            Ok(target)
+        }
+
+        async fn force(
+            &self,
+            _target: Target,
+            _policy: ForcePolicy,
+        ) -> Result<isize, anyhow::Error> {
+            unreachable!("We only move back")
+        }
+    }
+
+    /// There's one temporary position for every desk so our tests take turns, away from our real
+    /// state and config
+    async fn isolated() -> MutexGuard<'static, ()> {
+        static STORAGE: Mutex<()> = Mutex::const_new(());
+        static DATA_DIR: Once = Once::new();
+        DATA_DIR.call_once(|| {
+            let dir = env::temp_dir().join(format!("uplift-temporary-{}", process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            config::set_path(dir.join("config.toml"));
+            env::set_var("UPLIFT_DATA_DIR", dir);
+        });
+
+        let guard = STORAGE.lock().await;
+        storage::save_temporary_position(None).unwrap();
+        guard
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn returns_after_the_duration() {
+        let _storage = isolated().await;
+        let desk = FakeDesk::raised("returns");
+        let started = Instant::now();
+        hold(
+            &desk,
+            RETURN_TO,
+            Duration::from_secs(30 * 60),
+            &QuietHours::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(started.elapsed(), Duration::from_secs(30 * 60));
+        assert_eq!(desk.height(), RETURN_TO);
+        assert!(storage::temporary_position().unwrap().is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn cancelling_stops_the_return() {
+        let _storage = isolated().await;
+        let desk = FakeDesk::raised("cancelled");
+        let started = Instant::now();
+        let cancelling = async {
+            // between two of our checks so we see it at 10 minutes
+            time::sleep(Duration::from_secs(10 * 60) - Duration::from_millis(500)).await;
+            cancel().unwrap();
+        };
+        let quiet_hours = QuietHours::default();
+        let (held, ()) = tokio::join!(
+            hold(&desk, RETURN_TO, Duration::from_secs(30 * 60), &quiet_hours),
+            cancelling
+        );
+        held.unwrap();
+
+        assert_eq!(started.elapsed(), Duration::from_secs(10 * 60));
+        assert_eq!(desk.height(), RAISED);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn resume_waits_out_the_rest() {
+        let _storage = isolated().await;
+        let desk = FakeDesk::raised("waits");
+        let temporary = TemporaryPosition {
+            desk: desk.id.to_string(),
+            return_to: RETURN_TO,
+            until: SystemTime::now() + Duration::from_secs(20 * 60),
+            pid: 0,
+        };
+        storage::save_temporary_position(Some(&temporary)).unwrap();
+        let started = Instant::now();
+        resume(&desk, &QuietHours::default()).await.unwrap();
+
+        // `until` is on the system clock which keeps running while tokio's is paused
+        let elapsed = started.elapsed();
+        assert!(
+            elapsed > Duration::from_secs(19 * 60) && elapsed <= Duration::from_secs(20 * 60),
+            "{elapsed:?}"
+        );
+        assert_eq!(desk.height(), RETURN_TO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn resume_moves_back_now_when_late() {
+        let _storage = isolated().await;
+        let desk = FakeDesk::raised("late");
+        let temporary = TemporaryPosition {
+            desk: desk.id.to_string(),
+            return_to: RETURN_TO,
+            until: SystemTime::now() - Duration::from_secs(5 * 60),
+            pid: 0,
+        };
+        storage::save_temporary_position(Some(&temporary)).unwrap();
+        let started = Instant::now();
+        resume(&desk, &QuietHours::default()).await.unwrap();
+
+        assert_eq!(started.elapsed(), Duration::ZERO);
+        assert_eq!(desk.height(), RETURN_TO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn resume_leaves_other_desks_alone() {
+        let _storage = isolated().await;
+        let temporary = TemporaryPosition {
+            desk: "other".to_string(),
+            return_to: RETURN_TO,
+            until: SystemTime::now(),
+            pid: 0,
+        };
+        storage::save_temporary_position(Some(&temporary)).unwrap();
+        let desk = FakeDesk::raised("ours");
+        let e = resume(&desk, &QuietHours::default()).await.unwrap_err();
+
+        assert!(e.to_string().contains("--desk other"), "{e}");
+        assert_eq!(desk.height(), RAISED);
+    }
+
+    /// Quiet hours from an hour ago to an hour from now on the system clock, which is what
+    /// [QuietHours::active] reads
+    fn quiet_now(mode: QuietMode) -> QuietHours {
+        let now = jiff::Zoned::now();
+        let window = format!(
+            "{}-{}",
+            (&now - jiff::Span::new().hours(1)).strftime("%H:%M"),
+            (&now + jiff::Span::new().hours(1)).strftime("%H:%M")
+        );
+
+        QuietHours {
+            windows: vec![window.parse().unwrap()],
+            mode,
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn waits_for_quiet_hours_to_end_before_returning() {
+        let _storage = isolated().await;
+        let desk = FakeDesk::raised("deferred");
+        let started = Instant::now();
+        hold(
+            &desk,
+            RETURN_TO,
+            Duration::from_secs(10 * 60),
+            &quiet_now(QuietMode::Defer),
+        )
+        .await
+        .unwrap();
+
+        // the rest of the window is read from the system clock, so it's about an hour
+        let elapsed = started.elapsed();
+        assert!(
+            elapsed > Duration::from_secs(60 * 60) && elapsed <= Duration::from_secs(70 * 60),
+            "{elapsed:?}"
+        );
+        assert_eq!(desk.height(), RETURN_TO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn skips_returning_in_refused_quiet_hours() {
+        let _storage = isolated().await;
+        let desk = FakeDesk::raised("refused");
+        hold(
+            &desk,
+            RETURN_TO,
+            Duration::from_secs(10 * 60),
+            &quiet_now(QuietMode::Refuse),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(desk.height(), RAISED);
+        assert!(storage::temporary_position().unwrap().is_none());
+    }
+}
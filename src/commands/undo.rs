@@ -0,0 +1,34 @@
+use uplift_lib::desk::{Desk, MoveProfile};
+use uplift_lib::output;
+use uplift_lib::storage;
+
+use crate::commands::movement;
+use crate::config::Config;
+use crate::print::progress;
+
+/// Remember where the desk was before a move so `uplift undo` can return to it
+pub fn record(desk: &Desk, height: isize) {
+    if let Err(e) = storage::push_history(desk.id(), height) {
+        log::warn!("{} - Failed to save our history: {e:?}", desk.id());
+    }
+}
+
+/// Return to the height before our last move
+pub async fn run(desk: &Desk) -> Result<(), anyhow::Error> {
+    match storage::pop_history(desk.id())? {
+        Some(previous_height) => {
+            let height = desk
+                .move_to_with(
+                    previous_height,
+                    MoveProfile::Constant,
+                    Config::load()?.tolerance(),
+                )
+                .await?;
+            progress!("Returned to {}", output::height(height));
+            movement::fire_reached(desk, previous_height, height).await;
+        }
+        None => progress!("Nothing to undo"),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,638 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+use toml::Value;
+use toml_edit::{DocumentMut, Item, TableLike};
+
+use crate::hooks::HookEvent;
+use crate::units::{Inches, Seconds};
+use uplift_lib::desk::{AdapterFilter, ConnectRetry, HeightRange, Ramp, ScanRetry, Tolerance};
+use uplift_lib::output::{HeightFormat, HeightUnit};
+use uplift_lib::quiet_hours::QuietHours;
+use uplift_lib::storage;
+
+const CONFIG_FILE: &str = "config.toml";
+/// Use a different config file, overridden by `--config`
+pub const CONFIG_ENV: &str = "UPLIFT_CONFIG";
+/// The timeout for every command, overridden by `[timeouts]` and `--timeout`
+const TIMEOUT_ENV: &str = "UPLIFT_TIMEOUT";
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+static PATH_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Load and save our config at `path` instead of the config directory
+pub fn set_path(path: PathBuf) {
+    if PATH_OVERRIDE.set(path).is_err() {
+        log::warn!("Our config path was already set");
+    }
+}
+
+/// User configuration stored in `<config dir>/uplift/config.toml` or the path from `--config`
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Named desk heights, ie: `drawing = 34.5` or `drawing = "87.6cm"`
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub positions: BTreeMap<String, Inches>,
+    /// Named actions that move the desk and run hooks around it
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub actions: BTreeMap<String, Action>,
+    /// How heights are displayed, defaults to our locale
+    #[serde(skip_serializing_if = "Display::is_empty")]
+    pub display: Display,
+    /// Names for desk ids to use with `--desk`, ie: `office = "hci0/dev_E8_5F_..."`
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub desks: BTreeMap<String, String>,
+    /// Shortcuts for `&&` separated commands, ie: `focus = "goto drawing && action dnd-on"`
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub aliases: BTreeMap<String, String>,
+    /// Default `--timeout` for each command, ie: `force-sit = "5m"`
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub timeouts: BTreeMap<String, Seconds>,
+    /// The lowest and highest heights we'll move to, ie: `min = 26`
+    #[serde(skip_serializing_if = "Limits::is_empty")]
+    pub limits: Limits,
+    /// How close moves need to get to their target, defaults to 0.3"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tolerance: Option<Inches>,
+    /// Whether sit, stand and toggle wait for the desk to stop, like `--wait`
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub wait: bool,
+    /// The least time between writes to the desk, defaults to 50ms
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub write_spacing: Option<Seconds>,
+    /// How `--ramp` moves speed up and slow down
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ramp: Option<RampConfig>,
+    /// How many times we try to connect to a desk, ie: `attempts = 5`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connect_retry: Option<ConnectRetryConfig>,
+    /// How we rescan when we can't find a desk, ie: `windows = 6`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scan_retry: Option<ScanRetryConfig>,
+    /// Which Bluetooth adapters we use, ie: `deny = ["hci0"]`
+    #[serde(skip_serializing_if = "AdapterFilter::is_empty")]
+    pub adapters: AdapterFilter,
+    /// Shell commands to run when the desk does something, ie: `on_stand = "say {height_in}"`
+    #[serde(skip_serializing_if = "Hooks::is_empty")]
+    pub hooks: Hooks,
+    /// When automated moves wait and manual moves need `--override-quiet-hours`
+    #[serde(skip_serializing_if = "QuietHours::is_empty")]
+    pub quiet_hours: QuietHours,
+}
+
+/// Move to `height`, running the `before` and `after` shell hooks around the move. If a hook fails
+/// we move back to where we started.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Action {
+    pub height: Inches,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub before: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min: Option<Inches>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max: Option<Inches>,
+}
+
+impl Limits {
+    pub fn is_empty(&self) -> bool {
+        self.min.is_none() && self.max.is_none()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConnectRetryConfig {
+    pub attempts: usize,
+    /// How long to wait after the first failure, this doubles after each failure
+    pub backoff: Seconds,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScanRetryConfig {
+    /// How many times we scan before giving up
+    pub windows: usize,
+    /// How long each scan lasts
+    pub window: Seconds,
+    /// How long we wait between scans
+    pub pause: Seconds,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RampConfig {
+    /// The most packets we send in a single pulse
+    pub max_packets: usize,
+    /// Start slowing down within this distance of the target
+    pub slow_distance: Inches,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Display {
+    /// `in` or `cm`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub units: Option<HeightUnit>,
+    /// `.` or `,`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub decimal_separator: Option<char>,
+    /// Round heights to a multiple of this in our unit, ie: `0.5`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub precision: Option<f64>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Hooks {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_sit: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_stand: Option<String>,
+    /// After `move`, `goto`, `action` or `undo` reach their height
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_target: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_error: Option<String>,
+    /// Speak every event with the OS text to speech, like `--announce`
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub announce: bool,
+    /// The text to speech voice for announcements, ie: `Anna` on macOS, defaults to the system voice
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub voice: Option<String>,
+}
+
+impl Hooks {
+    fn is_empty(&self) -> bool {
+        self.on_sit.is_none()
+            && self.on_stand.is_none()
+            && self.on_target.is_none()
+            && self.on_error.is_none()
+            && !self.announce
+            && self.voice.is_none()
+    }
+
+    pub fn get(&self, event: HookEvent) -> Option<&str> {
+        match event {
+            HookEvent::Sit => self.on_sit.as_deref(),
+            HookEvent::Stand => self.on_stand.as_deref(),
+            HookEvent::Target => self.on_target.as_deref(),
+            HookEvent::Error => self.on_error.as_deref(),
+            // these belong to actions and alerts
+            HookEvent::Before | HookEvent::After => None,
+            #[cfg(feature = "streaming")]
+            HookEvent::Alert => None,
+        }
+    }
+}
+
+impl Display {
+    fn is_empty(&self) -> bool {
+        self.units.is_none() && self.decimal_separator.is_none() && self.precision.is_none()
+    }
+}
+
+impl Config {
+    pub fn path() -> Result<PathBuf, anyhow::Error> {
+        if let Some(path) = PATH_OVERRIDE.get() {
+            return Ok(path.clone());
+        }
+
+        dirs::config_dir()
+            .map(|dir| dir.join("uplift").join(CONFIG_FILE))
+            .ok_or_else(|| anyhow!("Couldn't find a config directory"))
+    }
+
+    /// Load our config, falling back to the default if it doesn't exist yet
+    pub fn load() -> Result<Config, anyhow::Error> {
+        let path = Config::path()?;
+
+        match fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents)
+                .with_context(|| format!("Failed to parse config {}", path.display())),
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                log::debug!("No config at {}, using the default", path.display());
+                Ok(Config::default())
+            }
+            Err(e) => Err(e).with_context(|| format!("Failed to read config {}", path.display())),
+        }
+    }
+
+    /// Atomically replace our config file so concurrent readers never see a partial write
+    pub fn save(&self) -> Result<(), anyhow::Error> {
+        let contents = toml::to_string_pretty(self).context("Failed to serialize config")?;
+        storage::write_atomic(&Config::path()?, contents.as_bytes())
+    }
+
+    /// Change the text of our config file while holding its lock, for edits that keep its comments
+    /// and layout. Unlike [Config::update] the file only needs to be valid TOML, so this can fix a
+    /// config that doesn't load.
+    pub fn edit(
+        change: impl FnOnce(&str) -> Result<String, anyhow::Error>,
+    ) -> Result<(), anyhow::Error> {
+        let path = Config::path()?;
+        let _lock = storage::lock(&path)?;
+
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read config {}", path.display()))
+            }
+        };
+        storage::write_atomic(&path, change(&contents)?.as_bytes())
+    }
+
+    /// Change the config while holding its lock, re-reading it first so we don't lose changes
+    /// another uplift made since we loaded it
+    pub fn update(
+        change: impl FnOnce(Config) -> Result<Config, anyhow::Error>,
+    ) -> Result<(), anyhow::Error> {
+        let _lock = storage::lock(&Config::path()?)?;
+
+        change(Config::load()?)?.save()
+    }
+
+    /// Find the height of a named position
+    pub fn position(&self, name: &str) -> Result<Inches, anyhow::Error> {
+        self.positions.get(name).copied().ok_or_else(|| {
+            anyhow!(
+                "Unknown position `{name}`, known positions: {}",
+                self.positions
+                    .keys()
+                    .map(String::as_str)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        })
+    }
+
+    pub fn alias(&self, name: &str) -> Result<&str, anyhow::Error> {
+        self.aliases.get(name).map(String::as_str).ok_or_else(|| {
+            anyhow!(
+                "Unknown command `{name}`, known aliases: {}",
+                self.aliases
+                    .keys()
+                    .map(String::as_str)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        })
+    }
+
+    /// The desk id saved under `desk` in `[desks]`, or `desk` itself if it isn't one of ours
+    pub fn desk_filter(&self, desk: Option<String>) -> Option<String> {
+        desk.map(|desk| self.desks.get(&desk).cloned().unwrap_or(desk))
+    }
+
+    /// Resolve the timeout for `command`, `--timeout` beats `[timeouts]` which beats
+    /// `UPLIFT_TIMEOUT`
+    pub fn command_timeout(
+        &self,
+        command: Option<&str>,
+        flag: Option<Seconds>,
+    ) -> Result<Duration, anyhow::Error> {
+        let configured = command.and_then(|command| self.timeouts.get(command).copied());
+        let timeout = match flag.or(configured) {
+            Some(timeout) => Some(timeout),
+            None => match env::var(TIMEOUT_ENV) {
+                Ok(value) => Some(
+                    value
+                        .parse::<Seconds>()
+                        .with_context(|| format!("Invalid {TIMEOUT_ENV}"))?,
+                ),
+                Err(_) => None,
+            },
+        };
+
+        Ok(timeout.map_or(DEFAULT_TIMEOUT, |timeout| timeout.0))
+    }
+
+    pub fn tolerance(&self) -> Tolerance {
+        self.tolerance.map_or_else(Tolerance::default, |tolerance| {
+            Tolerance(tolerance.to_tenths())
+        })
+    }
+
+    /// The heights we'll move to, the desk's whole range unless `[limits]` narrows it
+    pub fn height_range(&self) -> HeightRange {
+        let physical = HeightRange::default();
+
+        HeightRange {
+            min: self.limits.min.map_or(physical.min, Inches::to_tenths),
+            max: self.limits.max.map_or(physical.max, Inches::to_tenths),
+        }
+    }
+
+    pub fn connect_retry(&self) -> ConnectRetry {
+        self.connect_retry
+            .map_or_else(ConnectRetry::default, |retry| ConnectRetry {
+                attempts: retry.attempts,
+                backoff: retry.backoff.0,
+            })
+    }
+
+    pub fn scan_retry(&self) -> ScanRetry {
+        self.scan_retry
+            .map_or_else(ScanRetry::default, |retry| ScanRetry {
+                windows: retry.windows,
+                window: retry.window.0,
+                pause: retry.pause.0,
+            })
+    }
+
+    pub fn ramp(&self) -> Ramp {
+        self.ramp.map_or_else(Ramp::default, |ramp| Ramp {
+            max_packets: ramp.max_packets,
+            slow_distance: ramp.slow_distance.to_tenths(),
+        })
+    }
+
+    /// Our locale's height format with any overrides from `[display]`
+    pub fn height_format(&self) -> HeightFormat {
+        let locale = HeightFormat::from_locale();
+
+        HeightFormat {
+            unit: self.display.units.unwrap_or(locale.unit),
+            decimal_separator: self
+                .display
+                .decimal_separator
+                .unwrap_or(locale.decimal_separator),
+            step: self
+                .display
+                .precision
+                .map_or(locale.step, |precision| (precision * 10.0).round() as isize),
+        }
+    }
+
+    pub fn action(&self, name: &str) -> Result<&Action, anyhow::Error> {
+        self.actions.get(name).ok_or_else(|| {
+            anyhow!(
+                "Unknown action `{name}`, known actions: {}",
+                self.actions
+                    .keys()
+                    .map(String::as_str)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        })
+    }
+
+    /// Every setting as a dotted key, ie: `positions.drawing`
+    pub fn entries(&self) -> Result<Vec<(String, Value)>, anyhow::Error> {
+        let mut entries = Vec::new();
+        flatten(String::new(), Value::try_from(self)?, &mut entries);
+
+        Ok(entries)
+    }
+
+    pub fn get(&self, key: &str) -> Result<Value, anyhow::Error> {
+        let mut value = Value::try_from(self)?;
+        for part in parse_key(key)? {
+            value = match value {
+                Value::Table(mut table) => table.remove(part),
+                _ => None,
+            }
+            .ok_or_else(|| anyhow!("`{key}` isn't set"))?;
+        }
+
+        Ok(value)
+    }
+
+    /// Set `key` to `value` in the config file `contents`, leaving the rest of the file as it was.
+    /// The result is validated against our schema.
+    pub fn set(contents: &str, key: &str, value: &str) -> Result<String, anyhow::Error> {
+        let mut document = parse_document(contents)?;
+        let (last, parents) = split_key(key)?;
+
+        let mut table = document.as_table_mut() as &mut dyn TableLike;
+        for part in parents {
+            table = table
+                .entry(part)
+                .or_insert_with(toml_edit::table)
+                .as_table_like_mut()
+                .ok_or_else(|| anyhow!("`{part}` in `{key}` isn't a table"))?;
+        }
+        let mut new_value = parse_value(value);
+        match table.get_mut(last).and_then(Item::as_value_mut) {
+            // keep the comments around the value we're replacing
+            Some(old_value) => {
+                *new_value.decor_mut() = old_value.decor().clone();
+                *old_value = new_value;
+            }
+            None => {
+                table.insert(last, Item::Value(new_value));
+            }
+        }
+
+        let contents = document.to_string();
+        validate(&contents).with_context(|| format!("Invalid value `{value}` for `{key}`"))?;
+
+        Ok(contents)
+    }
+
+    /// Remove `key` from the config file `contents` so it falls back to its default
+    pub fn unset(contents: &str, key: &str) -> Result<String, anyhow::Error> {
+        let mut document = parse_document(contents)?;
+        let (last, parents) = split_key(key)?;
+
+        let mut table = document.as_table_mut() as &mut dyn TableLike;
+        for part in parents {
+            table = table
+                .get_mut(part)
+                .and_then(Item::as_table_like_mut)
+                .ok_or_else(|| anyhow!("`{key}` isn't set"))?;
+        }
+        table
+            .remove(last)
+            .ok_or_else(|| anyhow!("`{key}` isn't set"))?;
+
+        let contents = document.to_string();
+        validate(&contents).with_context(|| format!("Failed to unset `{key}`"))?;
+
+        Ok(contents)
+    }
+}
+
+fn parse_document(contents: &str) -> Result<DocumentMut, anyhow::Error> {
+    contents
+        .parse::<DocumentMut>()
+        .context("Failed to parse our config")
+}
+
+fn validate(contents: &str) -> Result<(), anyhow::Error> {
+    toml::from_str::<Config>(contents)?;
+
+    Ok(())
+}
+
+fn parse_key(key: &str) -> Result<Vec<&str>, anyhow::Error> {
+    let parts = key.split('.').collect::<Vec<_>>();
+    if parts.iter().any(|part| part.is_empty()) {
+        Err(anyhow!("Invalid config key `{key}`"))
+    } else {
+        Ok(parts)
+    }
+}
+
+fn split_key(key: &str) -> Result<(&str, Vec<&str>), anyhow::Error> {
+    let mut parts = parse_key(key)?;
+    let last = parts.pop().expect("split always returns at least one part");
+
+    Ok((last, parts))
+}
+
+/// Values are parsed as TOML so numbers and booleans keep their types, anything else is a string
+fn parse_value(value: &str) -> toml_edit::Value {
+    value
+        .parse::<toml_edit::Value>()
+        .unwrap_or_else(|_| value.into())
+}
+
+fn flatten(prefix: String, value: Value, entries: &mut Vec<(String, Value)>) {
+    match value {
+        Value::Table(table) => {
+            for (key, value) in table {
+                let key = if prefix.is_empty() {
+                    key
+                } else {
+                    format!("{prefix}.{key}")
+                };
+                flatten(key, value, entries);
+            }
+        }
+        value => entries.push((prefix, value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"# my desk
+wait = true # always
+
+[positions]
+# for drawing
+drawing = "87.6cm"
+"#;
+
+    #[test]
+    fn set_keeps_the_rest_of_the_file() {
+        let contents = Config::set(CONFIG, "positions.typing", "29.5").unwrap();
+
+        assert_eq!(
+            contents,
+            r#"# my desk
+wait = true # always
+
+[positions]
+# for drawing
+drawing = "87.6cm"
+typing = 29.5
+"#
+        );
+    }
+
+    #[test]
+    fn set_replaces_values_in_place() {
+        let contents = Config::set(CONFIG, "wait", "false").unwrap();
+        let contents = Config::set(&contents, "positions.drawing", "90cm").unwrap();
+
+        assert_eq!(
+            contents,
+            r#"# my desk
+wait = false # always
+
+[positions]
+# for drawing
+drawing = "90cm"
+"#
+        );
+    }
+
+    #[test]
+    fn set_creates_nested_tables() {
+        let contents = Config::set("", "quiet_hours.mode", "refuse").unwrap();
+        assert_eq!(contents, "[quiet_hours]\nmode = \"refuse\"\n");
+
+        let contents = Config::set(CONFIG, "actions.draw.height", "34").unwrap();
+        let config = toml::from_str::<Config>(&contents).unwrap();
+        assert_eq!(config.action("draw").unwrap().height, Inches(34.0));
+    }
+
+    #[test]
+    fn set_rejects_what_we_cant_load() {
+        for (key, value) in [
+            ("unknown", "1"),
+            ("positions.drawing", "30ft"),
+            ("wait", "sometimes"),
+            ("quiet_hours.unknown", "1"),
+            ("wait.nested", "1"),
+            ("positions..drawing", "30"),
+        ] {
+            assert!(Config::set(CONFIG, key, value).is_err(), "{key} = {value}");
+        }
+    }
+
+    #[test]
+    fn set_can_fix_a_config_that_doesnt_load() {
+        let broken = "wait = \"yes\"\n";
+        assert!(validate(broken).is_err());
+
+        assert_eq!(
+            Config::set(broken, "wait", "true").unwrap(),
+            "wait = true\n"
+        );
+    }
+
+    #[test]
+    fn unset_removes_one_key() {
+        let contents = Config::unset(CONFIG, "wait").unwrap();
+        assert!(!contents.contains("wait"), "{contents}");
+        assert!(contents.contains("drawing = \"87.6cm\""), "{contents}");
+
+        let contents = Config::unset(CONFIG, "positions.drawing").unwrap();
+        assert!(!contents.contains("drawing ="), "{contents}");
+        assert!(contents.contains("wait = true # always"), "{contents}");
+    }
+
+    #[test]
+    fn unset_fails_for_missing_keys() {
+        for key in ["tolerance", "positions.typing", "limits.min", "wait.nested"] {
+            assert!(Config::unset(CONFIG, key).is_err(), "{key}");
+        }
+    }
+
+    #[test]
+    fn parses_values_as_toml_or_strings() {
+        assert_eq!(parse_value("true").as_bool(), Some(true));
+        assert_eq!(parse_value("30").as_integer(), Some(30));
+        assert_eq!(parse_value("29.5").as_float(), Some(29.5));
+        assert_eq!(parse_value("87.6cm").as_str(), Some("87.6cm"));
+        assert_eq!(parse_value("\"quoted\"").as_str(), Some("quoted"));
+        assert_eq!(parse_value("say hi").as_str(), Some("say hi"));
+        assert_eq!(
+            parse_value(r#"["hci0", "hci1"]"#)
+                .as_array()
+                .map(|array| array.len()),
+            Some(2)
+        );
+    }
+}
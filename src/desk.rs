@@ -1,81 +1,715 @@
-use std::collections::BTreeSet;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::future::Future;
+#[cfg(feature = "streaming")]
+use std::pin::Pin;
+use std::sync::atomic::AtomicBool;
 use std::sync::atomic::AtomicIsize;
 use std::sync::atomic::AtomicU8;
+use std::sync::atomic::AtomicUsize;
 use std::sync::atomic::Ordering;
-use std::sync::Arc;
-use std::time::Duration;
+use std::sync::{Arc, Mutex, RwLock};
+#[cfg(feature = "streaming")]
+use std::task::{self, Poll};
+use std::time::{Duration, Instant, SystemTime};
 
+use crate::protocol::{
+    self, DESK_DATA_IN_UUID, DESK_DATA_OUT_UUID, DESK_NAME_UUID, DOWN_PACKET, QUERY_PACKET,
+    SAVE_SIT_PACKET, SAVE_STAND_PACKET, SIT_PACKET, STAND_PACKET, STOP_PACKET, UP_PACKET,
+};
 use anyhow::{anyhow, Context};
-use btleplug::api::CentralEvent::{DeviceConnected, DeviceDiscovered, DeviceUpdated};
+use btleplug::api::CentralEvent::{
+    self, DeviceConnected, DeviceDisconnected, DeviceDiscovered, DeviceUpdated,
+};
 use btleplug::api::{
-    bleuuid, Central, Characteristic, Manager as _, Peripheral as _, ScanFilter, ValueNotification,
-    WriteType,
+    Central, CharPropFlags, Characteristic, Manager as _, Peripheral as _, PeripheralProperties,
+    ScanFilter, ValueNotification, WriteType,
 };
-use btleplug::platform::{Manager, Peripheral};
+use btleplug::platform::{Adapter, Manager, Peripheral, PeripheralId};
+#[cfg(feature = "streaming")]
+use futures::ready;
+use futures::stream::{BoxStream, FuturesUnordered};
+use futures::Stream;
 use futures::{executor, StreamExt};
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "streaming")]
+use tokio::sync::broadcast;
+use tokio::sync::{mpsc, watch, Semaphore};
 use tokio::time;
-use uuid::Uuid;
+#[cfg(feature = "streaming")]
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+#[cfg(feature = "streaming")]
+use tokio_stream::wrappers::{BroadcastStream, WatchStream};
+
+use crate::output::{self, chatter, verbose};
+#[cfg(feature = "streaming")]
+use crate::posture::{self, Posture};
+use crate::storage::{self, CachedCharacteristic, CachedCharacteristics, DeskSighting, Presets};
+use crate::timings;
+use crate::wsl;
+
+pub use crate::protocol::DESK_SERVICE_UUID;
+
+// how often we nudge the desk while moving
+const MOVE_PULSE_INTERVAL: Duration = Duration::from_millis(150);
+// how long we wait between packets within a single ramped pulse
+const RAMP_PACKET_INTERVAL: Duration = Duration::from_millis(50);
+// how many pulses in a row can leave the height unchanged before we give up
+const MAX_STALLED_PULSES: usize = 20;
+// how many times we query a freshly connected desk before giving up on it
+const WAKE_ATTEMPTS: usize = 3;
+// how long we wait for a notification after each wake query
+const WAKE_TIMEOUT: Duration = Duration::from_secs(2);
+// how long [Desk::query_height] waits for the desk to tell us its height
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+// how long we wait for the desk to respond to a command before calling it unacknowledged
+const ACKNOWLEDGEMENT_TIMEOUT: Duration = Duration::from_millis(500);
+// how long we look for a remembered desk id before matching it by name instead
+const REDISCOVERY_DELAY: Duration = Duration::from_secs(5);
+/// How long we wait between writes by default, the desk ignores some writes that arrive back to back
+pub const DEFAULT_WRITE_SPACING: Duration = Duration::from_millis(50);
+// how many times [Desk::wait_for_preset] sees the desk still before deciding it won't move
+const STILL_CHECKS: usize = 3;
+// how long [Desk::disconnect] waits for the adapter to report that we disconnected
+const DISCONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+// how many connection attempts we race while scanning
+const MAX_CONNECT_ATTEMPTS: usize = 3;
+// how long a connection attempt can hang before we race another one against the same desk
+const CONNECT_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// How many times we try to connect to a desk we found before giving up on it. btleplug's first
+/// connect often fails on Linux even though the desk is right there.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConnectRetry {
+    pub attempts: usize,
+    /// How long we wait after the first failure, this doubles after each failure
+    pub backoff: Duration,
+}
+
+impl Default for ConnectRetry {
+    fn default() -> Self {
+        ConnectRetry {
+            attempts: 3,
+            backoff: Duration::from_millis(500),
+        }
+    }
+}
+
+static CONNECT_RETRY: RwLock<Option<ConnectRetry>> = RwLock::new(None);
+
+/// Change how every connect retries, by default we try 3 times starting with a 500ms backoff
+pub fn set_connect_retry(retry: ConnectRetry) {
+    if let Ok(mut connect_retry) = CONNECT_RETRY.write() {
+        *connect_retry = Some(retry);
+    }
+}
+
+fn connect_retry() -> ConnectRetry {
+    CONNECT_RETRY
+        .read()
+        .ok()
+        .and_then(|retry| *retry)
+        .unwrap_or_default()
+}
+
+/// How we keep scanning when we can't find a desk. We scan for `window`, then pause for about
+/// `pause` before scanning again, and give up with [ConnectError::NoDeskFound] after `windows`
+/// scans. The defaults fit within our 60s default timeout.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ScanRetry {
+    pub windows: usize,
+    pub window: Duration,
+    /// Up to a fifth of this is added at random so several uplifts don't scan in lockstep
+    pub pause: Duration,
+}
+
+impl Default for ScanRetry {
+    fn default() -> Self {
+        ScanRetry {
+            windows: 4,
+            window: Duration::from_secs(10),
+            pause: Duration::from_secs(5),
+        }
+    }
+}
+
+static SCAN_RETRY: RwLock<Option<ScanRetry>> = RwLock::new(None);
+
+/// Change how every connect rescans, by default we scan 4 times for 10s with 5s pauses
+pub fn set_scan_retry(retry: ScanRetry) {
+    if let Ok(mut scan_retry) = SCAN_RETRY.write() {
+        *scan_retry = Some(retry);
+    }
+}
+
+fn scan_retry() -> ScanRetry {
+    SCAN_RETRY
+        .read()
+        .ok()
+        .and_then(|retry| *retry)
+        .unwrap_or_default()
+}
+
+impl ScanRetry {
+    /// Our pause with some jitter, the clock's nanoseconds are random enough for this
+    fn jittered_pause(&self) -> Duration {
+        let nanos = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |now| now.subsec_nanos());
+        self.pause + self.pause.mul_f64(0.2 * f64::from(nanos % 1000) / 1000.0)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Preset {
+    Sit,
+    Stand,
+}
+
+impl Preset {
+    /// Whether `height` is past the midpoint towards this preset, for when we haven't learned it
+    fn is_past_midpoint(&self, height: isize) -> bool {
+        match self {
+            Preset::Sit => height < (AVG_MID_HEIGHT + AVG_SITTING_HEIGHT) / 2,
+            Preset::Stand => height > (AVG_MID_HEIGHT + AVG_STANDING_HEIGHT) / 2,
+        }
+    }
+}
+
+/// Where [Desk::force] should move the desk to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Target {
+    Sit,
+    Stand,
+    /// Sit if we're standing or stand if we're sitting
+    Toggle,
+}
+
+/// Which way [Desk::nudge] moves the desk
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Nudge {
+    Up,
+    Down,
+}
+
+/// The desk never sent us a notification after [Desk::wake], it's probably not a desk we know how
+/// to talk to
+#[derive(Debug)]
+pub struct WakeError {
+    pub address: String,
+    pub attempts: usize,
+}
+
+impl fmt::Display for WakeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} - The desk didn't respond after {} wake attempts",
+            self.address, self.attempts
+        )
+    }
+}
+
+impl std::error::Error for WakeError {}
+
+/// The desk didn't respond to a height query in time
+#[derive(Debug)]
+pub struct QueryTimeout {
+    pub timeout: Duration,
+}
+
+impl fmt::Display for QueryTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The desk didn't report its height within {}",
+            humantime::format_duration(self.timeout)
+        )
+    }
+}
+
+impl std::error::Error for QueryTimeout {}
+
+/// We were asked to move outside of the heights the desk can, or is allowed to, reach
+#[derive(Debug)]
+pub struct OutOfRange {
+    pub target: isize,
+    pub min: isize,
+    pub max: isize,
+}
+
+impl fmt::Display for OutOfRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} is outside of the desk range {}-{}, the closest we can go is {}",
+            output::height(self.target),
+            output::height(self.min),
+            output::height(self.max),
+            output::height(self.target.clamp(self.min, self.max))
+        )
+    }
+}
+
+impl std::error::Error for OutOfRange {}
+
+/// The heights we'll move to, in tenths of an inch. This defaults to everything the desk can
+/// report.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HeightRange {
+    pub min: isize,
+    pub max: isize,
+}
+
+impl Default for HeightRange {
+    fn default() -> Self {
+        HeightRange {
+            min: MIN_PHYSICAL_HEIGHT,
+            max: MAX_PHYSICAL_HEIGHT,
+        }
+    }
+}
+
+impl HeightRange {
+    /// Fail with [OutOfRange] if we shouldn't move to `target`
+    pub fn check(&self, target: isize) -> Result<(), OutOfRange> {
+        if (self.min..=self.max).contains(&target) {
+            Ok(())
+        } else {
+            Err(OutOfRange {
+                target,
+                min: self.min,
+                max: self.max,
+            })
+        }
+    }
+}
+
+/// Why we couldn't find or connect to a desk
+#[derive(Debug)]
+pub enum ConnectError {
+    /// There aren't any Bluetooth adapters we're allowed to use
+    NoAdapter,
+    /// None of our adapters match the one in our config
+    AdapterNotFound {
+        adapter: String,
+        available: Vec<String>,
+    },
+    /// The adapter stopped sending us scan events before we found a desk
+    ScanEnded { adapter: String },
+    /// We scanned every window of our [ScanRetry] without finding a desk
+    NoDeskFound { adapter: String, windows: usize },
+    /// The desk doesn't have one of the characteristics we need to control it
+    MissingCharacteristic(&'static str),
+}
+
+impl fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectError::NoAdapter if wsl::is_wsl() => {
+                write!(f, "Couldn't find an adapter. {}", wsl::NO_BLUETOOTH_HELP)
+            }
+            ConnectError::NoAdapter => write!(f, "Couldn't find an adapter"),
+            ConnectError::AdapterNotFound { adapter, available } if available.is_empty() => {
+                write!(
+                    f,
+                    "Couldn't find adapter `{adapter}`, we don't have any adapters"
+                )
+            }
+            ConnectError::AdapterNotFound { adapter, available } => write!(
+                f,
+                "Couldn't find adapter `{adapter}`, available adapters: {}",
+                available.join(", ")
+            ),
+            ConnectError::ScanEnded { adapter } => {
+                write!(f, "{adapter} - Stopped scanning before we found a desk")
+            }
+            ConnectError::NoDeskFound { adapter, windows } => write!(
+                f,
+                "{adapter} - Didn't find a desk after scanning {windows} times, check that it's \
+                powered on and in range"
+            ),
+            ConnectError::MissingCharacteristic(name) => {
+                write!(f, "Couldn't find the {name} characteristic")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConnectError {}
+
+/// A height notification from the desk
+#[cfg(feature = "streaming")]
+#[derive(Clone, Copy, Debug)]
+pub struct HeightUpdate {
+    pub height: isize,
+    pub raw: (u8, u8),
+    /// When we received the notification
+    pub received: Instant,
+}
+
+// how many height updates a slow subscriber can fall behind before it misses some
+#[cfg(feature = "streaming")]
+const HEIGHT_UPDATE_CAPACITY: usize = 64;
+
+/// Every [HeightUpdate] from [Desk::subscribe_height]. If we fall too far behind the oldest updates
+/// are skipped.
+#[cfg(feature = "streaming")]
+pub struct HeightStream {
+    updates: BroadcastStream<HeightUpdate>,
+}
+
+#[cfg(feature = "streaming")]
+impl Stream for HeightStream {
+    type Item = HeightUpdate;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            match ready!(self.updates.poll_next_unpin(cx)) {
+                Some(Ok(update)) => return Poll::Ready(Some(update)),
+                Some(Err(BroadcastStreamRecvError::Lagged(missed))) => {
+                    log::warn!("Missed {missed} height updates");
+                }
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+/// Whether we're still connected to the desk, from our adapter's events
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionState {
+    /// We've connected to the peripheral but the desk hasn't answered us yet
+    Connecting,
+    Connected,
+    /// The desk dropped the connection and we're trying to connect to it again
+    Reconnecting,
+    /// We disconnected, or gave up reconnecting
+    Disconnected,
+}
+
+impl fmt::Display for ConnectionState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ConnectionState::Connecting => "connecting",
+            ConnectionState::Connected => "connected",
+            ConnectionState::Reconnecting => "reconnecting",
+            ConnectionState::Disconnected => "disconnected",
+        };
+
+        f.write_str(name)
+    }
+}
+
+/// What the desk did after we sent it a command
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CommandOutcome {
+    /// The desk answered a query we sent right behind the command, or changed course if it was
+    /// already moving
+    Acknowledged,
+    /// We didn't hear back from the desk, it might have ignored the command
+    Unacknowledged,
+}
+
+/// Commands written back to back by [Desk::send_batch], each one still paced by our write spacing
+/// but we only wait for the desk once at the end
+///
+/// ```
+/// use uplift_lib::desk::CommandBatch;
+/// use uplift_lib::protocol::STOP;
+///
+/// let batch = CommandBatch::new().command(STOP).verify();
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CommandBatch {
+    packets: Vec<[u8; 6]>,
+    verify: bool,
+}
+
+impl CommandBatch {
+    pub fn new() -> CommandBatch {
+        CommandBatch::default()
+    }
+
+    /// Queue a command without a payload, ie: [protocol::STOP]
+    pub fn command(mut self, command: u8) -> CommandBatch {
+        self.packets.push(protocol::command_packet(command));
+        self
+    }
+
+    /// Finish with a height query so the outcome has the height the desk reports afterwards
+    pub fn verify(mut self) -> CommandBatch {
+        self.verify = true;
+        self
+    }
+}
+
+/// What the desk did after a [CommandBatch]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct BatchOutcome {
+    /// Whether the desk sent us any notification after our first write
+    pub outcome: CommandOutcome,
+    /// The height from the verify query, if we asked for one and the desk answered
+    pub height: Option<isize>,
+}
+
+/// Which Bluetooth adapters we connect with, matched case-insensitively against the adapter info.
+/// An empty `allow` list allows every adapter that isn't denied.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdapterFilter {
+    /// Only use this adapter, by its name like `hci1` or its address. Unlike `allow` we fail if it's
+    /// missing instead of trying the other adapters.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub adapter: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub allow: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub deny: Vec<String>,
+}
+
+impl AdapterFilter {
+    pub fn is_empty(&self) -> bool {
+        self.adapter.is_none() && self.allow.is_empty() && self.deny.is_empty()
+    }
+
+    /// Does the adapter info start with our adapter's name, like `hci1 (usb:...)`, or mention its
+    /// whole address. `hci1` doesn't match `hci10` and part of an address doesn't match at all.
+    fn is_configured(&self, adapter: &str) -> bool {
+        self.adapter.as_ref().is_none_or(|configured| {
+            let mut words = adapter.split(|c: char| c.is_whitespace() || "(),".contains(c));
+            let name = words.next().unwrap_or_default();
+            if name.eq_ignore_ascii_case(configured) {
+                return true;
+            }
+
+            let address = normalize_address(configured);
+            address.is_some() && words.any(|word| normalize_address(word) == address)
+        })
+    }
+
+    pub fn allows(&self, adapter: &str) -> bool {
+        let adapter = adapter.to_lowercase();
+        let matches = |pattern: &String| adapter.contains(&pattern.to_lowercase());
+
+        (self.allow.is_empty() || self.allow.iter().any(matches)) && !self.deny.iter().any(matches)
+    }
+}
+
+/// A Bluetooth address as 12 lowercase hex digits, whether it's written with `:`, `-` or `_`
+fn normalize_address(address: &str) -> Option<String> {
+    let digits = address
+        .chars()
+        .filter(|c| !matches!(c, ':' | '-' | '_'))
+        .map(|c| c.to_ascii_lowercase())
+        .collect::<String>();
+
+    (digits.len() == 12 && digits.chars().all(|c| c.is_ascii_hexdigit())).then_some(digits)
+}
+
+/// How close [Desk::move_to_with] needs to get to its target, in tenths of an inch
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tolerance(pub isize);
+
+impl Default for Tolerance {
+    /// 0.3"
+    fn default() -> Self {
+        Tolerance(3)
+    }
+}
+
+impl Tolerance {
+    /// Check if `height` is close enough to `target`
+    pub fn reached(&self, height: isize, target: isize) -> bool {
+        (height - target).abs() <= self.0
+    }
+}
+
+/// How [Desk::move_to_with] pulses the desk towards its target
+#[derive(Clone, Copy, Debug, Default)]
+pub enum MoveProfile {
+    /// Send a single packet every pulse
+    #[default]
+    Constant,
+    /// Start with short pulses, lengthen them while we're far away and shorten them again as we
+    /// approach the target to reduce jerk
+    Ramp(Ramp),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Ramp {
+    /// The most packets we send in a single pulse
+    pub max_packets: usize,
+    /// Start shortening our pulses within this distance of the target, in tenths of an inch
+    pub slow_distance: isize,
+}
 
-// const UP_PACKET: [u8; 6] = [0xf1, 0xf1, 0x01, 0x00, 0x01, 0x7e];
-// const DOWN_PACKET: [u8; 6] = [0xf1, 0xf1, 0x02, 0x00, 0x02, 0x7e];
-const SAVE_SIT_PACKET: [u8; 6] = [0xf1, 0xf1, 0x03, 0x00, 0x03, 0x7e];
-const SAVE_STAND_PACKET: [u8; 6] = [0xf1, 0xf1, 0x04, 0x00, 0x04, 0x7e];
-const SIT_PACKET: [u8; 6] = [0xf1, 0xf1, 0x05, 0x00, 0x05, 0x7e];
-const STAND_PACKET: [u8; 6] = [0xf1, 0xf1, 0x06, 0x00, 0x06, 0x7e];
-// const STOP_PACKET: [u8; 6] = [0xf1, 0xf1, 0x02, 0x00, 0x2b, 0x7e];
-const QUERY_PACKET: [u8; 6] = [0xf1, 0xf1, 0x07, 0x00, 0x07, 0x7e];
+impl Default for Ramp {
+    fn default() -> Self {
+        Ramp {
+            max_packets: 4,
+            slow_distance: 20,
+        }
+    }
+}
+
+impl MoveProfile {
+    /// How many packets to send for our `pulse`th pulse while `distance` away from the target
+    fn packets(&self, pulse: usize, distance: isize) -> usize {
+        match self {
+            MoveProfile::Constant => 1,
+            MoveProfile::Ramp(ramp) => {
+                let max_packets = ramp.max_packets.max(1);
+                let slowing =
+                    (distance * max_packets as isize / ramp.slow_distance.max(1)) as usize;
+
+                (pulse + 1).min(slowing).clamp(1, max_packets)
+            }
+        }
+    }
+}
+
+/// How hard [Desk::force] tries before giving up
+#[derive(Clone, Copy, Debug)]
+pub struct ForcePolicy {
+    pub attempts: usize,
+    /// How long we wait between height checks, if the height didn't change we've stopped moving
+    pub settle_interval: Duration,
+    /// How close we need to stop to the preset we've learned for our target
+    pub tolerance: Tolerance,
+}
 
-pub const DESK_SERVICE_UUID: Uuid = bleuuid::uuid_from_u16(0xff12);
+impl Default for ForcePolicy {
+    fn default() -> Self {
+        ForcePolicy {
+            attempts: 5,
+            settle_interval: Duration::from_millis(1000),
+            tolerance: Tolerance::default(),
+        }
+    }
+}
 
-const DESK_DATA_IN_UUID: Uuid = bleuuid::uuid_from_u16(0xff01);
-const DESK_DATA_OUT_UUID: Uuid = bleuuid::uuid_from_u16(0xff02);
-const DESK_NAME_UUID: Uuid = bleuuid::uuid_from_u16(0xff06);
+/// Reported by [Desk::force] as it makes progress
+#[derive(Clone, Copy, Debug)]
+pub enum ForceProgress {
+    Attempt {
+        attempt: usize,
+        attempts: usize,
+    },
+    Moved {
+        from: isize,
+        to: isize,
+    },
+    /// We stopped at `height`, close to `preset` if we've learned it
+    Reached {
+        height: isize,
+        preset: Option<isize>,
+    },
+}
 
 pub struct Desk {
+    id: String,
     height: Arc<AtomicIsize>,
     raw_height: Arc<(AtomicU8, AtomicU8)>,
+    /// How many notifications we've received, used to check if the desk responded to a command
+    notifications: Arc<AtomicUsize>,
+    #[cfg(feature = "streaming")]
+    height_updates: broadcast::Sender<HeightUpdate>,
+    #[cfg(feature = "streaming")]
+    posture: watch::Receiver<Posture>,
+    /// Kept up to date by [track_connection], which stops once we're dropped
+    connection: Arc<watch::Sender<ConnectionState>>,
+    presets: Mutex<Presets>,
+    write_spacing: Mutex<Duration>,
+    range: Mutex<HeightRange>,
+    /// When we last wrote to the desk, writes wait on this in order so they're evenly spaced
+    last_write: tokio::sync::Mutex<Option<Instant>>,
+    /// How many writes we delayed to keep them spaced out
+    delayed_writes: AtomicUsize,
     data_in_characteristic: Characteristic,
+    data_out_characteristic: Characteristic,
+    name_characteristic: Characteristic,
+    /// Set once we start disconnecting so we don't disconnect again when we're dropped, or
+    /// reconnect when the adapter tells us we've disconnected
+    disconnected: Arc<AtomicBool>,
     peripheral: Peripheral,
     _manager: Manager,
 }
 
 impl Desk {
-    pub async fn new() -> Result<Desk, anyhow::Error> {
-        let (manager, peripheral) = connect().await?;
+    /// Connect to a desk, optionally only matching a desk with the given address or name
+    pub async fn new(desk: Option<&str>) -> Result<Desk, anyhow::Error> {
+        Desk::with_options(desk, None, &AdapterFilter::default()).await
+    }
 
-        log::debug!("{:?} - Connected to peripheral", peripheral.address());
+    /// Connect to a desk with one of the `adapters`. Heights are decoded with `decoder`, which is
+    /// remembered for this desk, or the decoder we last used with this desk.
+    pub async fn with_options(
+        desk: Option<&str>,
+        decoder: Option<DecoderKind>,
+        adapters: &AdapterFilter,
+    ) -> Result<Desk, anyhow::Error> {
+        let (manager, central, peripheral) = connect(desk, adapters).await?;
 
-        // start discovering characteristics on our peripheral
-        peripheral
-            .discover_services()
-            .await
-            .with_context(|| format!("{:?} - Discovering Services", peripheral.address()))?;
+        Desk::from_peripheral(manager, &central, peripheral, decoder).await
+    }
+
+    /// Set up a desk on a peripheral we've connected to with `central`: subscribe to its heights,
+    /// follow its connection and wake it
+    async fn from_peripheral(
+        manager: Manager,
+        central: &Adapter,
+        peripheral: Peripheral,
+        decoder: Option<DecoderKind>,
+    ) -> Result<Desk, anyhow::Error> {
+        log::debug!("{:?} - Connected to peripheral", peripheral.address());
 
-        let (data_in_characteristic, data_out_characteristic, _name_characteristic) =
-            get_characteristics(peripheral.characteristics())?;
+        let id = peripheral.id().to_string();
+        let decoder = choose_decoder(&id, decoder).decoder();
+        // none of these depend on each other, so we don't wait on each round trip in turn
+        let (
+            mut height_receiver,
+            (data_in_characteristic, data_out_characteristic, name_characteristic),
+            connection_events,
+        ) = tokio::try_join!(
+            async { Ok(peripheral.notifications().await?) },
+            subscribe_to_desk(&peripheral, &id),
+            async { Ok(central.events().await?) },
+        )?;
 
         let height = Arc::new(AtomicIsize::new(-1));
         let raw_height = Arc::new((AtomicU8::new(0), AtomicU8::new(0)));
+        let notifications = Arc::new(AtomicUsize::new(0));
+        #[cfg(feature = "streaming")]
+        let (height_updates, _) = broadcast::channel(HEIGHT_UPDATE_CAPACITY);
 
         // subscribe to events (height) on our peripheral
         {
             let updated_height = height.clone();
             let updated_raw_height = raw_height.clone();
-
-            let mut height_receiver = peripheral.notifications().await?;
-            peripheral
-                .subscribe(&data_out_characteristic)
-                .await
-                .with_context(|| {
-                    format!("{:?} - Subscribing to desk updates", peripheral.address())
-                })?;
+            let received_notifications = notifications.clone();
+            #[cfg(feature = "streaming")]
+            let sent_height_updates = height_updates.clone();
 
             let address = peripheral.address();
+            let subscribed = Instant::now();
             tokio::spawn(async move {
+                let mut first_notification = true;
                 while let Some(ValueNotification { value, .. }) = height_receiver.next().await {
+                    if first_notification {
+                        timings::record("first notification", subscribed);
+                        first_notification = false;
+                    }
+
                     let last_height = updated_height.load(Ordering::Relaxed);
-                    let (low, high) = get_raw_height(&value);
-                    let height = estimate_height((low, high), last_height);
+                    let Some((low, high)) = protocol::decode_height(&value) else {
+                        log::warn!("{address:?} - Ignoring malformed notification: {value:x?}");
+                        continue;
+                    };
+                    let height = decoder.decode((low, high), last_height);
 
                     log::trace!(
                         "{:?} - Updated Height: ({:x},{:x}) -> {:x}",
@@ -84,32 +718,236 @@ impl Desk {
                         high,
                         height
                     );
+                    chatter!("Notification: {value:x?}");
                     updated_height.store(height, Ordering::Relaxed);
                     updated_raw_height.0.store(low, Ordering::Relaxed);
                     updated_raw_height.1.store(high, Ordering::Relaxed);
+                    received_notifications.fetch_add(1, Ordering::Relaxed);
+                    // it's fine if nobody is listening
+                    #[cfg(feature = "streaming")]
+                    let _ = sent_height_updates.send(HeightUpdate {
+                        height,
+                        raw: (low, high),
+                        received: Instant::now(),
+                    });
                 }
             });
         }
 
+        #[cfg(feature = "streaming")]
+        let posture = posture::track(height.clone());
+        let connection = Arc::new(watch::Sender::new(ConnectionState::Connecting));
+        let disconnected = Arc::new(AtomicBool::new(false));
+        track_connection(
+            connection_events,
+            &peripheral,
+            &data_out_characteristic,
+            &connection,
+            &disconnected,
+        );
+
+        let presets = storage::presets(&id).unwrap_or_else(|e| {
+            log::warn!("{id} - Failed to load our presets: {e:?}");
+            Presets::default()
+        });
+
         let desk = Desk {
+            id,
             height,
             raw_height,
+            notifications,
+            #[cfg(feature = "streaming")]
+            height_updates,
+            #[cfg(feature = "streaming")]
+            posture,
+            connection,
+            presets: Mutex::new(presets),
+            write_spacing: Mutex::new(DEFAULT_WRITE_SPACING),
+            range: Mutex::new(HeightRange::default()),
+            last_write: tokio::sync::Mutex::new(None),
+            delayed_writes: AtomicUsize::new(0),
             data_in_characteristic,
+            data_out_characteristic,
+            name_characteristic,
+            disconnected,
             peripheral,
             _manager: manager,
         };
 
-        // we need to do an initial query to actually write anything, so just get that out of the way
-        desk.write(&desk.data_in_characteristic, &QUERY_PACKET)
-            .await?;
+        if let Err(e) = desk.wake().await {
+            // our cached characteristics might be why the desk isn't responding
+            if let Err(e) = storage::save_cached_characteristics(&desk.id, None) {
+                log::warn!(
+                    "{} - Failed to clear our cached characteristics: {e:?}",
+                    desk.id
+                );
+            }
+            return Err(e);
+        }
+        desk.connection.send_replace(ConnectionState::Connected);
 
         Ok(desk)
     }
 
+    /// The desk ignores our commands until it's been queried, so query it until we get our first
+    /// notification back. Fails with a [WakeError] if the desk never responds.
+    pub async fn wake(&self) -> Result<(), anyhow::Error> {
+        let _timing = timings::phase("wake");
+
+        for attempt in 1..=WAKE_ATTEMPTS {
+            verbose!("Waking the desk ({attempt}/{WAKE_ATTEMPTS})");
+            self.write(&self.data_in_characteristic, &QUERY_PACKET)
+                .await
+                .with_context(|| format!("{:?} - Waking", self.peripheral.address()))?;
+
+            let woke = time::timeout(WAKE_TIMEOUT, async {
+                while self.height.load(Ordering::Relaxed) < 0 {
+                    time::sleep(Duration::from_millis(100)).await;
+                }
+            })
+            .await;
+            if woke.is_ok() {
+                log::debug!(
+                    "{:?} - Woke after {attempt} attempts",
+                    self.peripheral.address()
+                );
+                return Ok(());
+            }
+        }
+
+        Err(WakeError {
+            address: self.address(),
+            attempts: WAKE_ATTEMPTS,
+        }
+        .into())
+    }
+
+    /// The peripheral id of this desk, this is what `--desk` matches against
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn address(&self) -> String {
+        self.peripheral.address().to_string()
+    }
+
+    /// Unsubscribe from height notifications and disconnect, waiting until the adapter reports that
+    /// we've disconnected. Returns our id once we've disconnected.
+    pub async fn disconnect(self) -> Result<String, anyhow::Error> {
+        let address = self.peripheral.address();
+        log::debug!("{address:?} - Disconnecting");
+        // we're trying now, so there's no point trying again when we're dropped
+        self.disconnected.store(true, Ordering::Relaxed);
+
+        if let Err(e) = self
+            .peripheral
+            .unsubscribe(&self.data_out_characteristic)
+            .await
+        {
+            log::debug!("{address:?} - Failed to unsubscribe: {e:?}");
+        }
+        disconnect_and_wait(
+            &self.peripheral,
+            self.connection.subscribe(),
+            DISCONNECT_TIMEOUT,
+        )
+        .await
+        .with_context(|| format!("{address:?} - Disconnecting"))?;
+
+        Ok(self.id.clone())
+    }
+
+    /// Read the name the desk reports for itself
+    pub async fn name(&self) -> Result<String, anyhow::Error> {
+        let name = self
+            .peripheral
+            .read(&self.name_characteristic)
+            .await
+            .with_context(|| format!("{:?} - Reading our name", self.peripheral.address()))?;
+
+        Ok(String::from_utf8_lossy(&name)
+            .trim_end_matches('\0')
+            .to_string())
+    }
+
+    /// The preset heights we've learned so far
+    pub fn presets(&self) -> Presets {
+        *self.presets.lock().expect("Presets lock poisoned")
+    }
+
+    /// Remember that `preset` is at `height`, we can't query our presets so we learn them whenever
+    /// they're saved or reached
+    pub fn remember_preset(&self, preset: Preset, height: isize) {
+        let presets = {
+            let mut presets = self.presets.lock().expect("Presets lock poisoned");
+            match preset {
+                Preset::Sit => presets.sit = Some(height),
+                Preset::Stand => presets.stand = Some(height),
+            }
+            *presets
+        };
+
+        if let Err(e) = storage::save_presets(&self.id, presets) {
+            log::warn!("{} - Failed to save our presets: {e:?}", self.id);
+        }
+    }
+
     pub fn height(&self) -> isize {
         self.height.load(Ordering::Relaxed)
     }
 
+    /// Leave at least `spacing` between our writes
+    pub fn set_write_spacing(&self, spacing: Duration) {
+        *self
+            .write_spacing
+            .lock()
+            .expect("Write spacing lock poisoned") = spacing;
+    }
+
+    /// Only move to heights within `range`, this is clamped to what the desk can report
+    pub fn set_range(&self, range: HeightRange) {
+        let physical = HeightRange::default();
+        *self.range.lock().expect("Range lock poisoned") = HeightRange {
+            min: range.min.max(physical.min),
+            max: range.max.min(physical.max),
+        };
+    }
+
+    /// How many writes we've delayed to keep them spaced out, see [Desk::set_write_spacing]
+    pub fn delayed_writes(&self) -> usize {
+        self.delayed_writes.load(Ordering::Relaxed)
+    }
+
+    /// Stream every height notification from now on
+    #[cfg(feature = "streaming")]
+    pub fn subscribe_height(&self) -> HeightStream {
+        HeightStream {
+            updates: BroadcastStream::new(self.height_updates.subscribe()),
+        }
+    }
+
+    /// Our connection to the desk right now
+    pub fn connection_state(&self) -> ConnectionState {
+        *self.connection.borrow()
+    }
+
+    /// Whether we can talk to the desk right now, commands sent while we're reconnecting will fail
+    pub fn is_connected(&self) -> bool {
+        self.connection_state() == ConnectionState::Connected
+    }
+
+    /// Stream every change in our connection to the desk, starting with the current state
+    #[cfg(feature = "streaming")]
+    pub fn subscribe_connection(&self) -> WatchStream<ConnectionState> {
+        WatchStream::new(self.connection.subscribe())
+    }
+
+    /// Stream every change in our posture, starting with the current posture
+    #[cfg(feature = "streaming")]
+    pub fn subscribe_posture(&self) -> WatchStream<Posture> {
+        WatchStream::new(self.posture.clone())
+    }
+
     pub fn raw_height(&self) -> (u8, u8) {
         (
             self.raw_height.0.load(Ordering::Relaxed),
@@ -122,7 +960,12 @@ impl Desk {
 
         self.write(&self.data_in_characteristic, &SAVE_SIT_PACKET)
             .await
-            .with_context(|| format!("{:?} - Saving Sit", self.peripheral.address()))
+            .with_context(|| format!("{:?} - Saving Sit", self.peripheral.address()))?;
+
+        let height = self.query_height().await?;
+        self.remember_preset(Preset::Sit, height);
+
+        Ok(())
     }
 
     pub async fn save_stand(&self) -> Result<(), anyhow::Error> {
@@ -130,159 +973,1443 @@ impl Desk {
 
         self.write(&self.data_in_characteristic, &SAVE_STAND_PACKET)
             .await
-            .with_context(|| format!("{:?} - Saving Stand", self.peripheral.address()))
+            .with_context(|| format!("{:?} - Saving Stand", self.peripheral.address()))?;
+
+        let height = self.query_height().await?;
+        self.remember_preset(Preset::Stand, height);
+
+        Ok(())
     }
 
-    pub async fn sit(&self) -> Result<(), anyhow::Error> {
+    pub async fn sit(&self) -> Result<CommandOutcome, anyhow::Error> {
         log::debug!("{:?} - Sit", self.peripheral.address());
 
-        self.write(&self.data_in_characteristic, &SIT_PACKET)
+        self.command(&SIT_PACKET)
             .await
             .with_context(|| format!("{:?} - Sitting", self.peripheral.address()))
     }
 
-    pub async fn stand(&self) -> Result<(), anyhow::Error> {
+    pub async fn stand(&self) -> Result<CommandOutcome, anyhow::Error> {
         log::debug!("{:?} - Stand", self.peripheral.address());
 
-        self.write(&self.data_in_characteristic, &STAND_PACKET)
+        self.command(&STAND_PACKET)
             .await
             .with_context(|| format!("{:?} - Standing", self.peripheral.address()))
     }
 
-    pub async fn query_height(&self) -> Result<isize, anyhow::Error> {
-        // since we're querying, clear our height so we can check if it's updated
-        self.height.store(-1, Ordering::Relaxed);
-        self.write(&self.data_in_characteristic, &QUERY_PACKET)
-            .await
-            .with_context(|| format!("{:?} - Querying", self.peripheral.address()))?;
+    /// Stop the desk wherever it is, this only interrupts a sit or stand since nudges stop on
+    /// their own
+    pub async fn stop(&self) -> Result<CommandOutcome, anyhow::Error> {
+        log::debug!("{:?} - Stop", self.peripheral.address());
 
-        // wait for our height to update (is there a better way than polling?)
-        while self.height.load(Ordering::Relaxed) <= 0 {
-            time::sleep(Duration::from_millis(100)).await;
-        }
+        self.command(&STOP_PACKET)
+            .await
+            .with_context(|| format!("{:?} - Stopping", self.peripheral.address()))
+    }
 
-        Ok(self.height.load(Ordering::Relaxed))
+    /// Nudge the desk towards `target` until we're within the default [Tolerance] of it
+    pub async fn move_to(&self, target: isize) -> Result<isize, anyhow::Error> {
+        self.move_to_with(target, MoveProfile::Constant, Tolerance::default())
+            .await
     }
 
-    async fn write(
+    /// Nudge the desk towards `target` with pulses shaped by `profile` until we're within
+    /// `tolerance` of it
+    pub async fn move_to_with(
         &self,
-        characteristic: &Characteristic,
-        data: &[u8],
-    ) -> Result<(), anyhow::Error> {
-        self.peripheral
-            .write(characteristic, data, WriteType::WithoutResponse)
+        target: isize,
+        profile: MoveProfile,
+        tolerance: Tolerance,
+    ) -> Result<isize, anyhow::Error> {
+        self.move_to_with_progress(target, profile, tolerance, |_| ())
             .await
-            .with_context(|| format!("{:?} - Failed to write data", self.peripheral.address()))
     }
-}
 
-fn get_raw_height(data: &[u8]) -> (u8, u8) {
-    (data[5], data[7])
-}
+    /// [Desk::move_to_with], reporting our height to `progress` after every pulse
+    pub async fn move_to_with_progress(
+        &self,
+        target: isize,
+        profile: MoveProfile,
+        tolerance: Tolerance,
+        mut progress: impl FnMut(isize),
+    ) -> Result<isize, anyhow::Error> {
+        let range = *self.range.lock().expect("Range lock poisoned");
+        range.check(target)?;
+        log::debug!("{:?} - Move to {target}", self.peripheral.address());
 
-// 25.2"
-pub const MIN_PHYSICAL_HEIGHT: isize = 252;
-// 25.2" + 0xff
-pub const MAX_PHYSICAL_HEIGHT: isize = MIN_PHYSICAL_HEIGHT + 0xff;
-pub const MID_PHYSICAL_HEIGHT: isize = (MIN_PHYSICAL_HEIGHT + MAX_PHYSICAL_HEIGHT) / 2;
+        let mut height = self.query_height().await?;
+        let mut stalled = 0;
+        let mut pulse = 0;
+        while !tolerance.reached(height, target) {
+            let packet = if height < target {
+                &UP_PACKET
+            } else {
+                &DOWN_PACKET
+            };
+            let packets = profile.packets(pulse, (height - target).abs());
+            for sent in 0..packets {
+                if sent > 0 {
+                    time::sleep(RAMP_PACKET_INTERVAL).await;
+                }
+                self.write(&self.data_in_characteristic, packet)
+                    .await
+                    .with_context(|| format!("{:?} - Moving", self.peripheral.address()))?;
+            }
+            time::sleep(MOVE_PULSE_INTERVAL).await;
+            pulse += 1;
+
+            let next_height = self.height();
+            log::trace!("Height moved from: {height} -> {next_height}");
+            progress(next_height);
+            if next_height == height {
+                stalled += 1;
+                if stalled >= MAX_STALLED_PULSES {
+                    return Err(anyhow!(
+                        "The desk stopped moving at {} before reaching {}",
+                        output::height(height),
+                        output::height(target)
+                    ));
+                }
+            } else {
+                stalled = 0;
+            }
+
+            // we overshot our target so stop here rather than bouncing back and forth
+            if (height < target) != (next_height < target) {
+                height = next_height;
+                break;
+            }
+            height = next_height;
+        }
+
+        Ok(height)
+    }
+
+    /// Send a single up or down pulse, which moves the desk about 0.1". Returns our height after the
+    /// pulse.
+    pub async fn nudge(&self, direction: Nudge) -> Result<isize, anyhow::Error> {
+        log::debug!("{:?} - Nudge {direction:?}", self.peripheral.address());
+
+        let packet = match direction {
+            Nudge::Up => &UP_PACKET,
+            Nudge::Down => &DOWN_PACKET,
+        };
+        self.write(&self.data_in_characteristic, packet)
+            .await
+            .with_context(|| format!("{:?} - Nudging", self.peripheral.address()))?;
+        time::sleep(MOVE_PULSE_INTERVAL).await;
+
+        Ok(self.height())
+    }
+
+    /// Keep sending sit or stand until the desk settles at the preset we've learned for it, or on
+    /// the right side of the midpoint if we haven't learned it yet. This works around the desk
+    /// sometimes ignoring a command or stopping early. Returns the height we settled at.
+    pub async fn force(
+        &self,
+        target: Target,
+        policy: ForcePolicy,
+        mut progress: impl FnMut(ForceProgress),
+    ) -> Result<isize, anyhow::Error> {
+        let mut previous_height = self.query_height().await?;
+        let preset = match target {
+            Target::Sit => Preset::Sit,
+            Target::Stand => Preset::Stand,
+            Target::Toggle if previous_height > AVG_MID_HEIGHT => Preset::Sit,
+            Target::Toggle => Preset::Stand,
+        };
+        let preset_height = match preset {
+            Preset::Sit => self.presets().sit,
+            Preset::Stand => self.presets().stand,
+        };
+        let right_side = |height: isize| preset.is_past_midpoint(height);
+        let _timing = timings::phase("movement");
+
+        // where we stopped on the right side of the midpoint but away from our preset
+        let mut last_stop = None;
+        let mut attempts = 0;
+        while attempts < policy.attempts {
+            attempts += 1;
+            log::trace!("Running forced attempt {attempts}");
+            progress(ForceProgress::Attempt {
+                attempt: attempts,
+                attempts: policy.attempts,
+            });
+            // we check the height ourselves so we don't need the acknowledgement
+            match preset {
+                Preset::Sit => self.sit().await?,
+                Preset::Stand => self.stand().await?,
+            };
+
+            'query_height: loop {
+                time::sleep(policy.settle_interval).await;
+                let next_height = self.height();
+                log::trace!("Height moved from: {previous_height} -> {next_height}");
+
+                // we've stopped moving so check our height
+                if previous_height == next_height {
+                    let done = match preset_height {
+                        Some(preset_height) if right_side(next_height) => {
+                            let reached = policy.tolerance.reached(next_height, preset_height);
+                            // stopping at the same place twice means the preset was changed with
+                            // the keypad, so this is our new preset
+                            let moved = !reached && last_stop == Some(next_height);
+                            if moved {
+                                log::warn!(
+                                    "{} - Stopped at {next_height} twice, our {preset:?} preset \
+                                    changed from {preset_height}",
+                                    self.id
+                                );
+                            }
+                            last_stop = Some(next_height);
+                            reached || moved
+                        }
+                        _ => right_side(next_height),
+                    };
+                    if done {
+                        self.remember_preset(preset, next_height);
+                        progress(ForceProgress::Reached {
+                            height: next_height,
+                            preset: preset_height,
+                        });
+                        return Ok(next_height);
+                    } else {
+                        break 'query_height;
+                    }
+                }
+                progress(ForceProgress::Moved {
+                    from: previous_height,
+                    to: next_height,
+                });
+                previous_height = next_height;
+            }
+        }
+
+        Err(anyhow!(
+            "Failed to force the desk to the intended height after {attempts} attempts"
+        ))
+    }
+
+    /// Wait for the desk to stop after a sit or stand, then check that it stopped within
+    /// `policy.tolerance` of the preset we've learned, or past the midpoint if we haven't learned it.
+    /// Unlike [Desk::force] this never sends another command. Returns the height we stopped at.
+    pub async fn wait_for_preset(
+        &self,
+        preset: Preset,
+        policy: ForcePolicy,
+    ) -> Result<isize, anyhow::Error> {
+        let _timing = timings::phase("movement");
+        let preset_height = match preset {
+            Preset::Sit => self.presets().sit,
+            Preset::Stand => self.presets().stand,
+        };
+        let reached = |height: isize| match preset_height {
+            Some(preset_height) => policy.tolerance.reached(height, preset_height),
+            None => preset.is_past_midpoint(height),
+        };
+
+        let mut previous_height = self.query_height().await?;
+        // the desk can take a moment to start moving, so a still desk gets a few checks
+        let mut still_checks = 0;
+        loop {
+            time::sleep(policy.settle_interval).await;
+            let height = self.height();
+            if height != previous_height {
+                log::trace!("Height moved from: {previous_height} -> {height}");
+                previous_height = height;
+                still_checks = 0;
+                continue;
+            }
+
+            still_checks += 1;
+            if reached(height) {
+                return Ok(height);
+            }
+            if still_checks >= STILL_CHECKS {
+                return Err(anyhow!(
+                    "The desk stopped at {} instead of moving to {}",
+                    output::height(height),
+                    preset_height
+                        .map_or_else(|| format!("{preset:?}").to_lowercase(), output::height)
+                ));
+            }
+        }
+    }
+
+    /// Ask the desk for its height, failing with a [QueryTimeout] if it doesn't answer within 5s
+    pub async fn query_height(&self) -> Result<isize, anyhow::Error> {
+        self.query_height_timeout(QUERY_TIMEOUT).await
+    }
+
+    /// Ask the desk for its height, failing with a [QueryTimeout] if it doesn't answer in time. This
+    /// doesn't touch our last known height so it's safe to cancel.
+    pub async fn query_height_timeout(&self, timeout: Duration) -> Result<isize, anyhow::Error> {
+        let notifications = self.notifications.load(Ordering::Relaxed);
+        self.write(&self.data_in_characteristic, &QUERY_PACKET)
+            .await
+            .with_context(|| format!("{:?} - Querying", self.peripheral.address()))?;
+
+        // wait for our next notification (is there a better way than polling?)
+        time::timeout(timeout, async {
+            while self.notifications.load(Ordering::Relaxed) == notifications {
+                time::sleep(Duration::from_millis(100)).await;
+            }
+        })
+        .await
+        .map_err(|_| QueryTimeout { timeout })?;
+
+        Ok(self.height.load(Ordering::Relaxed))
+    }
+
+    /// Ask the desk for its height without waiting for the answer, it arrives as a normal
+    /// notification
+    pub async fn request_height(&self) -> Result<(), anyhow::Error> {
+        self.write(&self.data_in_characteristic, &QUERY_PACKET)
+            .await
+            .with_context(|| format!("{:?} - Requesting our height", self.peripheral.address()))
+    }
+
+    /// Write a command and wait to see if the desk responds to it, see [acknowledge]
+    async fn command(&self, packet: &[u8]) -> Result<CommandOutcome, anyhow::Error> {
+        let mut link = LockedDesk {
+            desk: self,
+            last_write: self.last_write.lock().await,
+        };
+        let outcome = acknowledge(&mut link, packet, ACKNOWLEDGEMENT_TIMEOUT).await?;
+        if outcome == CommandOutcome::Unacknowledged {
+            log::debug!(
+                "{:?} - No response to {packet:x?}",
+                self.peripheral.address()
+            );
+        }
+
+        Ok(outcome)
+    }
+
+    /// Write every command in `batch` without waiting on the desk between them, then wait once for
+    /// its notification. A single write lock is held throughout so nothing else is interleaved.
+    pub async fn send_batch(&self, batch: &CommandBatch) -> Result<BatchOutcome, anyhow::Error> {
+        let _timing = timings::phase("batch");
+        log::debug!(
+            "{:?} - Batch {:x?}",
+            self.peripheral.address(),
+            batch.packets
+        );
+
+        let notifications = self.notifications.load(Ordering::Relaxed);
+        let verify_after = {
+            let mut last_write = self.last_write.lock().await;
+            for packet in &batch.packets {
+                self.write_paced(&mut last_write, &self.data_in_characteristic, packet)
+                    .await?;
+            }
+
+            if batch.verify {
+                let verify_after = self.notifications.load(Ordering::Relaxed);
+                self.write_paced(&mut last_write, &self.data_in_characteristic, &QUERY_PACKET)
+                    .await
+                    .with_context(|| format!("{:?} - Verifying", self.peripheral.address()))?;
+                verify_after
+            } else {
+                notifications
+            }
+        };
+
+        // a notification after our verify query carries the height we want, otherwise any
+        // notification is an acknowledgement
+        let answered = time::timeout(ACKNOWLEDGEMENT_TIMEOUT, async {
+            while self.notifications.load(Ordering::Relaxed) == verify_after {
+                time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .is_ok();
+
+        let outcome = if self.notifications.load(Ordering::Relaxed) != notifications {
+            CommandOutcome::Acknowledged
+        } else {
+            log::debug!("{:?} - No response to our batch", self.peripheral.address());
+            CommandOutcome::Unacknowledged
+        };
+
+        Ok(BatchOutcome {
+            outcome,
+            height: (batch.verify && answered).then(|| self.height.load(Ordering::Relaxed)),
+        })
+    }
+
+    async fn write(
+        &self,
+        characteristic: &Characteristic,
+        data: &[u8],
+    ) -> Result<(), anyhow::Error> {
+        // hold the lock through our write so queued writes go out in order
+        let mut last_write = self.last_write.lock().await;
+        self.write_paced(&mut last_write, characteristic, data)
+            .await
+    }
+
+    /// Write once `last_write` is far enough behind us, the caller holds the write lock
+    async fn write_paced(
+        &self,
+        last_write: &mut Option<Instant>,
+        characteristic: &Characteristic,
+        data: &[u8],
+    ) -> Result<(), anyhow::Error> {
+        let spacing = *self
+            .write_spacing
+            .lock()
+            .expect("Write spacing lock poisoned");
+        if let Some(ready) = last_write.map(|last_write| last_write + spacing) {
+            if ready > Instant::now() {
+                let delayed = Instant::now();
+                self.delayed_writes.fetch_add(1, Ordering::Relaxed);
+                time::sleep_until(ready.into()).await;
+                timings::record("write pacing", delayed);
+            }
+        }
+
+        let _timing = timings::phase("command write");
+        let result = self
+            .peripheral
+            .write(characteristic, data, WriteType::WithoutResponse)
+            .await
+            .with_context(|| format!("{:?} - Failed to write data", self.peripheral.address()));
+        *last_write = Some(Instant::now());
+
+        result
+    }
+}
+
+/// What [acknowledge] needs from the desk, with nothing else writing to it
+trait CommandLink {
+    async fn write(&mut self, packet: &[u8]) -> Result<(), anyhow::Error>;
+
+    /// How many notifications we've received and the height from the latest one
+    fn seen(&self) -> (usize, isize);
+}
+
+/// Our desk with its write lock held
+struct LockedDesk<'a> {
+    desk: &'a Desk,
+    last_write: tokio::sync::MutexGuard<'a, Option<Instant>>,
+}
+
+impl CommandLink for LockedDesk<'_> {
+    async fn write(&mut self, packet: &[u8]) -> Result<(), anyhow::Error> {
+        self.desk
+            .write_paced(
+                &mut self.last_write,
+                &self.desk.data_in_characteristic,
+                packet,
+            )
+            .await
+    }
+
+    fn seen(&self) -> (usize, isize) {
+        (
+            self.desk.notifications.load(Ordering::Relaxed),
+            self.desk.height.load(Ordering::Relaxed),
+        )
+    }
+}
+
+/// Write `packet` and decide whether the desk heard it. A moving desk streams its height whether or
+/// not it heard us, so a notification alone isn't enough. We take a fresh height with a query first
+/// to see if the desk is already moving, then write the command with a query right behind it:
+///
+/// * a desk that was still has heard us once it answers that query, or starts moving
+/// * a desk that was moving has heard us once it changes course, stopping or turning around
+async fn acknowledge(
+    link: &mut impl CommandLink,
+    packet: &[u8],
+    timeout: Duration,
+) -> Result<CommandOutcome, anyhow::Error> {
+    let (notifications, last_height) = link.seen();
+    link.write(&QUERY_PACKET).await?;
+    let Ok(fresh) = time::timeout(timeout, next_notification(link, notifications)).await else {
+        return Ok(CommandOutcome::Unacknowledged);
+    };
+    // which way the desk was already heading, a still desk answers with the height we had
+    let course = if last_height < 0 {
+        0
+    } else {
+        (fresh.1 - last_height).signum()
+    };
+
+    link.write(packet).await?;
+    link.write(&QUERY_PACKET).await?;
+
+    let heard = time::timeout(timeout, async {
+        let mut previous = fresh;
+        loop {
+            let seen = next_notification(link, previous.0).await;
+            if course == 0 || (seen.1 - previous.1).signum() != course {
+                break;
+            }
+            previous = seen;
+        }
+    })
+    .await;
+
+    Ok(if heard.is_ok() {
+        CommandOutcome::Acknowledged
+    } else {
+        CommandOutcome::Unacknowledged
+    })
+}
+
+/// Wait for a notification after the first `notifications`
+async fn next_notification(link: &impl CommandLink, notifications: usize) -> (usize, isize) {
+    loop {
+        let seen = link.seen();
+        if seen.0 > notifications {
+            return seen;
+        }
+        time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+// 25.2"
+pub const MIN_PHYSICAL_HEIGHT: isize = 252;
+// 25.2" + 0xff
+pub const MAX_PHYSICAL_HEIGHT: isize = MIN_PHYSICAL_HEIGHT + 0xff;
+pub const MID_PHYSICAL_HEIGHT: isize = (MIN_PHYSICAL_HEIGHT + MAX_PHYSICAL_HEIGHT) / 2;
 // 26.0" based on a 5'6" person
 pub const AVG_SITTING_HEIGHT: isize = 260;
 // 40.5" based on a 5'6" person
 pub const AVG_STANDING_HEIGHT: isize = 405;
 pub const AVG_MID_HEIGHT: isize = (AVG_SITTING_HEIGHT + AVG_STANDING_HEIGHT) / 2;
 
+/// The decoders we know how to remember for a desk
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DecoderKind {
+    /// [LowHighDecoder]
+    #[default]
+    LowHigh,
+    /// [LowByteDecoder]
+    LowByte,
+}
+
+impl DecoderKind {
+    pub fn decoder(self) -> Box<dyn HeightDecoder> {
+        match self {
+            DecoderKind::LowHigh => Box::new(LowHighDecoder),
+            DecoderKind::LowByte => Box::new(LowByteDecoder),
+        }
+    }
+}
+
+/// Use and remember the decoder we were asked for, otherwise use the one we remembered
+fn choose_decoder(id: &str, decoder: Option<DecoderKind>) -> DecoderKind {
+    let remembered = storage::decoder(id).unwrap_or_else(|e| {
+        log::warn!("{id} - Failed to load our height decoder: {e:?}");
+        None
+    });
+
+    match decoder {
+        Some(decoder) => {
+            if remembered != Some(decoder) {
+                if let Err(e) = storage::save_decoder(id, decoder) {
+                    log::warn!("{id} - Failed to remember our height decoder: {e:?}");
+                }
+            }
+            decoder
+        }
+        None => remembered.unwrap_or_default(),
+    }
+}
+
+/// Turns the raw `(low, high)` bytes of a height notification into a height in tenths of an inch.
 /// The height ranges from 0x00 to 0xff. 0x01 roughly seems to be 0.1"
-fn estimate_height((low, high): (u8, u8), last_height: isize) -> isize {
-    let low = low as isize;
-    let high = high as isize;
-
-    let raw_height = if low >= 0xfd {
-        // anything outside of this range seems to be "special"
-        if last_height < MID_PHYSICAL_HEIGHT {
-            high
+pub trait HeightDecoder: Send + Sync {
+    fn decode(&self, raw: (u8, u8), last_height: isize) -> isize;
+}
+
+/// Only trust the low byte, this is how older versions decoded heights
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LowByteDecoder;
+
+impl HeightDecoder for LowByteDecoder {
+    fn decode(&self, (low, _): (u8, u8), _last_height: isize) -> isize {
+        MIN_PHYSICAL_HEIGHT + low as isize
+    }
+}
+
+/// Use the low byte unless it's one of the "special" values at the top of the range, then use our
+/// last height to pick between the low and high bytes
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LowHighDecoder;
+
+impl HeightDecoder for LowHighDecoder {
+    fn decode(&self, (low, high): (u8, u8), last_height: isize) -> isize {
+        let low = low as isize;
+        let high = high as isize;
+
+        let raw_height = if low >= 0xfd {
+            // anything outside of this range seems to be "special"
+            if last_height < MID_PHYSICAL_HEIGHT {
+                high
+            } else {
+                low
+            }
         } else {
             low
-        }
-    } else {
-        low
-    };
+        };
+
+        MIN_PHYSICAL_HEIGHT + raw_height
+    }
+}
+
+/// The moves and queries automation needs from a desk. [Desk] talks to a real one over Bluetooth,
+/// implementing this lets the same automation, ie: our schedule, drive a fake one in tests and
+/// examples.
+pub trait DeskControl: Sync {
+    /// What our saved state is keyed by, [Desk::id]
+    fn id(&self) -> &str;
+
+    /// [Desk::height]
+    fn height(&self) -> isize;
+
+    /// [Desk::query_height]
+    fn query_height(&self) -> impl Future<Output = Result<isize, anyhow::Error>> + Send;
+
+    /// [Desk::nudge]
+    fn nudge(&self, direction: Nudge) -> impl Future<Output = Result<isize, anyhow::Error>> + Send;
+
+    /// [Desk::move_to_with]
+    fn move_to_with(
+        &self,
+        target: isize,
+        profile: MoveProfile,
+        tolerance: Tolerance,
+    ) -> impl Future<Output = Result<isize, anyhow::Error>> + Send;
+
+    /// [Desk::force] without progress
+    fn force(
+        &self,
+        target: Target,
+        policy: ForcePolicy,
+    ) -> impl Future<Output = Result<isize, anyhow::Error>> + Send;
+}
+
+impl DeskControl for Desk {
+    fn id(&self) -> &str {
+        Desk::id(self)
+    }
+
+    fn height(&self) -> isize {
+        Desk::height(self)
+    }
+
+    async fn query_height(&self) -> Result<isize, anyhow::Error> {
+        Desk::query_height(self).await
+    }
+
+    async fn nudge(&self, direction: Nudge) -> Result<isize, anyhow::Error> {
+        Desk::nudge(self, direction).await
+    }
+
+    async fn move_to_with(
+        &self,
+        target: isize,
+        profile: MoveProfile,
+        tolerance: Tolerance,
+    ) -> Result<isize, anyhow::Error> {
+        Desk::move_to_with(self, target, profile, tolerance).await
+    }
 
-    MIN_PHYSICAL_HEIGHT + raw_height
+    async fn force(&self, target: Target, policy: ForcePolicy) -> Result<isize, anyhow::Error> {
+        Desk::force(self, target, policy, |_| ()).await
+    }
 }
 
 impl Drop for Desk {
     fn drop(&mut self) {
-        executor::block_on(self.peripheral.disconnect()).unwrap();
+        if self.disconnected.swap(true, Ordering::Relaxed) {
+            return;
+        }
+
+        if let Err(e) = executor::block_on(self.peripheral.disconnect()) {
+            log::warn!(
+                "{:?} - Failed to disconnect: {e:?}",
+                self.peripheral.address()
+            );
+        }
+    }
+}
+
+/// Scan for and connect to the first desk matching our `desk` filter. Each adapter allowed by
+/// `adapters` is tried in order until one connects, that adapter is returned with the desk. The
+/// [Manager] has to outlive the [Peripheral].
+pub async fn connect(
+    desk: Option<&str>,
+    adapters: &AdapterFilter,
+) -> Result<(Manager, Adapter, Peripheral), anyhow::Error> {
+    let manager = manager().await?;
+
+    // if `desk` is an id we've seen before, remember its name in case the id changed
+    let previous = desk.and_then(|desk| {
+        storage::desk_history()
+            .unwrap_or_else(|e| {
+                log::warn!("Failed to load the desks we've seen: {e:?}");
+                Vec::new()
+            })
+            .into_iter()
+            .find(|sighting| sighting.id == desk)
+    });
+    let rediscover = previous
+        .as_ref()
+        .and_then(|previous| previous.name.as_deref());
+
+    let mut result = Err(no_adapter());
+    for (central, adapter) in allowed_adapters(&manager, adapters).await? {
+        log::debug!("Using adapter: {adapter:?}");
+
+        match connect_with(&central, &adapter, desk, rediscover).await {
+            Ok(peripheral) => {
+                if let Some(previous) = &previous {
+                    follow_new_id(&previous.id, &peripheral.id().to_string());
+                }
+                return Ok((manager, central, peripheral));
+            }
+            Err(e) => {
+                log::warn!("{adapter} - Failed to connect, trying the next adapter: {e:?}");
+                result = Err(e);
+            }
+        }
+    }
+
+    result
+}
+
+/// Move what we know about a desk to the new id it connected with
+fn follow_new_id(old_id: &str, new_id: &str) {
+    if old_id != new_id {
+        log::info!("{old_id} - Desk is now {new_id}");
+        verbose!("Desk {old_id} is now {new_id}");
+        if let Err(e) = storage::rename_desk(old_id, new_id) {
+            log::warn!("{new_id} - Failed to move our state from {old_id}: {e:?}");
+        }
+    }
+}
+
+/// Scan for and connect to our desk on a single adapter. Desks are often discovered several times and
+/// a connection attempt can hang, so we race a few attempts and keep the first one that connects.
+/// If we don't find `desk` for a while we also accept a desk named `rediscover`, in case its id
+/// changed.
+async fn connect_with(
+    central: &Adapter,
+    adapter: &str,
+    desk: Option<&str>,
+    rediscover: Option<&str>,
+) -> Result<Peripheral, anyhow::Error> {
+    let events = central.events().await?;
+
+    scan_and_connect(central, events, adapter, desk, rediscover, scan_retry()).await
+}
+
+/// What [scan_and_connect] needs from an adapter besides its events
+trait Scanner: Sync {
+    /// Scan for our desk service
+    async fn start_scanning(&self) -> Result<(), anyhow::Error>;
+
+    async fn stop_scanning(&self) -> Result<(), anyhow::Error>;
+
+    /// The peripheral behind an event and its properties, if it has any yet
+    async fn discovered(
+        &self,
+        id: &PeripheralId,
+    ) -> Result<Option<(Peripheral, PeripheralProperties)>, anyhow::Error>;
+}
+
+impl Scanner for Adapter {
+    async fn start_scanning(&self) -> Result<(), anyhow::Error> {
+        Ok(self
+            .start_scan(ScanFilter {
+                services: vec![DESK_SERVICE_UUID],
+            })
+            .await?)
+    }
+
+    async fn stop_scanning(&self) -> Result<(), anyhow::Error> {
+        Ok(self.stop_scan().await?)
+    }
+
+    async fn discovered(
+        &self,
+        id: &PeripheralId,
+    ) -> Result<Option<(Peripheral, PeripheralProperties)>, anyhow::Error> {
+        let peripheral = self
+            .peripheral(id)
+            .await
+            .context(format!("{id:?} - Couldn't get our Peripheral"))?;
+
+        log::trace!("{:?} - Discovered peripheral", peripheral.address());
+
+        let properties = peripheral.properties().await.context(format!(
+            "{:?} - Couldn't get properties",
+            peripheral.address()
+        ))?;
+
+        Ok(properties.map(|properties| (peripheral, properties)))
+    }
+}
+
+/// The event loop behind [connect_with], `events` are the adapter's so our tests can end the scan
+/// or never find anything
+async fn scan_and_connect(
+    scanner: &impl Scanner,
+    mut events: impl Stream<Item = CentralEvent> + Unpin,
+    adapter: &str,
+    desk: Option<&str>,
+    rediscover: Option<&str>,
+    retry: ScanRetry,
+) -> Result<Peripheral, anyhow::Error> {
+    // scan for our desk service
+    verbose!("Scanning for desks");
+    let mut scan_timing = Some(timings::phase("scan"));
+    let scan_started = Instant::now();
+    let mut connect_timing = None;
+    scanner.start_scanning().await?;
+
+    let mut result = Err(ConnectError::ScanEnded {
+        adapter: adapter.to_string(),
+    }
+    .into());
+    let mut attempts = FuturesUnordered::new();
+    let mut attempted = HashMap::new();
+    // the names we've recorded for each desk during this scan
+    let mut remembered = HashMap::new();
+    let mut scanning = true;
+    let mut window = 1;
+    let window_end = time::sleep(retry.window);
+    tokio::pin!(window_end);
+    while scanning || !attempts.is_empty() {
+        tokio::select! {
+            // we haven't found a matching desk this window, pause and scan again
+            _ = &mut window_end, if scanning && attempted.is_empty() => {
+                if window >= retry.windows {
+                    result = Err(ConnectError::NoDeskFound {
+                        adapter: adapter.to_string(),
+                        windows: window,
+                    }
+                    .into());
+                    break;
+                }
+
+                scanner.stop_scanning().await?;
+                let pause = retry.jittered_pause();
+                window += 1;
+                log::info!(
+                    "{adapter} - No desks found, retrying in {} ({window}/{})",
+                    humantime::format_duration(pause),
+                    retry.windows
+                );
+                verbose!("No desks found, retrying ({window}/{})", retry.windows);
+                time::sleep(pause).await;
+                scanner.start_scanning().await?;
+                window_end.as_mut().reset(time::Instant::now() + retry.window);
+            },
+            Some(attempt) = attempts.next(), if !attempts.is_empty() => match attempt {
+                Ok(peripheral) => {
+                    result = Ok(peripheral);
+                    break;
+                }
+                Err(e) if attempts.is_empty() => {
+                    result = Err(e);
+                    break;
+                }
+                Err(e) => log::debug!("Connection attempt failed, waiting on the others: {e:?}"),
+            },
+            event = events.next(), if scanning => {
+                let Some(event) = event else {
+                    scanning = false;
+                    continue;
+                };
+                chatter!("Bluetooth event: {event:?}");
+                let (DeviceDiscovered(id) | DeviceUpdated(id) | DeviceConnected(id)) = event else {
+                    log::trace!("Unhandled Event: {:?}", event);
+                    continue;
+                };
+                // give an earlier attempt at this peripheral a chance before racing another, there's
+                // no point fetching its properties again until then
+                let retry = attempted
+                    .get(&id)
+                    .is_none_or(|attempted: &Instant| attempted.elapsed() >= CONNECT_RETRY_DELAY);
+                if !retry {
+                    continue;
+                }
+
+                let Some((peripheral, properties)) = &scanner.discovered(&id).await? else {
+                    log::trace!("{id:?} - Peripheral didn't have any properties");
+                    continue;
+                };
+                // even with the ScanFilter we still get initial unmatched devices, filter those out
+                if !properties.services.contains(&DESK_SERVICE_UUID) {
+                    log::trace!("{properties:?} - Peripheral didn't contain the Desk Service");
+                    continue;
+                }
+                // updates mostly change the signal strength, so only write to storage for new names
+                if remembered.get(&id) != Some(&properties.local_name) {
+                    remember_desk(peripheral, properties, adapter);
+                    remembered.insert(id.clone(), properties.local_name.clone());
+                }
+
+                let matches = desk.is_none_or(|desk| {
+                    matches_desk(desk, &peripheral.id().to_string(), properties)
+                }) || (scan_started.elapsed() >= REDISCOVERY_DELAY
+                    && rediscover.is_some_and(|name| properties.local_name.as_deref() == Some(name)));
+                if matches && attempts.len() < MAX_CONNECT_ATTEMPTS {
+                    log::debug!("{:?} - Attempting to connect", peripheral.address());
+                    verbose!("Connecting to {}", peripheral.address());
+                    attempted.insert(id, Instant::now());
+                    scan_timing.take();
+                    connect_timing.get_or_insert_with(|| timings::phase("connect"));
+
+                    attempts.push(connect_peripheral(peripheral.clone()));
+                }
+            }
+        }
+    }
+
+    scanner.stop_scanning().await?;
+
+    result
+}
+
+/// Connect to a peripheral we found, retrying with a backoff since the first connect often fails
+async fn connect_peripheral(peripheral: Peripheral) -> Result<Peripheral, anyhow::Error> {
+    let retry = connect_retry();
+    let attempts = retry.attempts.max(1);
+    let mut backoff = retry.backoff;
+
+    for attempt in 1.. {
+        match peripheral.connect().await {
+            Ok(()) => break,
+            Err(e) if attempt < attempts => {
+                log::info!(
+                    "{:?} - Connect attempt {attempt} of {attempts} failed, retrying in {}: {e}",
+                    peripheral.address(),
+                    humantime::format_duration(backoff)
+                );
+                verbose!("Connect attempt {attempt} of {attempts} failed, retrying");
+                time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            Err(e) => {
+                return Err(anyhow::Error::new(e).context(format!(
+                    "{:?} - Connection failed after {attempts} attempts",
+                    peripheral.address()
+                )))
+            }
+        }
+    }
+
+    Ok(peripheral)
+}
+
+/// Follow our adapter's connect and disconnect events for this peripheral. If the desk drops the
+/// connection when we didn't ask it to, we reconnect with our usual retries and subscribe to its
+/// heights again. This stays on our adapter: the desk's id, characteristics and notifications all
+/// belong to this peripheral, so failing over to another adapter is left to the next [connect].
+fn track_connection(
+    mut events: BoxStream<'static, CentralEvent>,
+    peripheral: &Peripheral,
+    data_out: &Characteristic,
+    connection: &Arc<watch::Sender<ConnectionState>>,
+    disconnected: &Arc<AtomicBool>,
+) {
+    let peripheral = peripheral.clone();
+    let data_out = data_out.clone();
+    let connection = Arc::downgrade(connection);
+    let disconnected = disconnected.clone();
+    let id = peripheral.id();
+
+    tokio::spawn(async move {
+        while let Some(event) = events.next().await {
+            // the desk was dropped
+            let Some(connection) = connection.upgrade() else {
+                break;
+            };
+
+            match event {
+                DeviceConnected(event_id) if event_id == id => {
+                    log::debug!("{id:?} - Connected");
+                    // our own connects publish their state once they've set up the desk, but if the
+                    // OS brought back a desk we gave up on we need to subscribe to its heights before
+                    // we count as connected again
+                    if disconnected.load(Ordering::Relaxed)
+                        || *connection.borrow() != ConnectionState::Disconnected
+                    {
+                        continue;
+                    }
+
+                    log::info!("{id:?} - The desk came back, subscribing again");
+                    resubscribe(&peripheral, &data_out, &connection).await;
+                }
+                DeviceDisconnected(event_id) if event_id == id => {
+                    log::debug!("{id:?} - Disconnected");
+                    if disconnected.load(Ordering::Relaxed)
+                        || *connection.borrow() == ConnectionState::Connecting
+                    {
+                        connection.send_replace(ConnectionState::Disconnected);
+                        continue;
+                    }
+
+                    log::warn!("{id:?} - The desk disconnected, reconnecting");
+                    resubscribe(&peripheral, &data_out, &connection).await;
+                }
+                _ => {}
+            }
+        }
+    });
+}
+
+/// What [disconnect_and_wait] needs from our peripheral
+trait Disconnect: Sync {
+    /// Ask to disconnect, the adapter tells us once we have
+    async fn start_disconnect(&self) -> Result<(), anyhow::Error>;
+}
+
+impl Disconnect for Peripheral {
+    async fn start_disconnect(&self) -> Result<(), anyhow::Error> {
+        Ok(self.disconnect().await?)
     }
 }
 
-async fn connect() -> Result<(Manager, Peripheral), anyhow::Error> {
+/// Disconnect `peripheral` and wait for the adapter's disconnect event, which [track_connection]
+/// turns into `connection` becoming [ConnectionState::Disconnected]
+async fn disconnect_and_wait(
+    peripheral: &impl Disconnect,
+    mut connection: watch::Receiver<ConnectionState>,
+    timeout: Duration,
+) -> Result<(), anyhow::Error> {
+    peripheral.start_disconnect().await?;
+
+    time::timeout(
+        timeout,
+        connection.wait_for(|state| *state == ConnectionState::Disconnected),
+    )
+    .await
+    .map_err(|_| {
+        anyhow!(
+            "Still connected after {}",
+            humantime::format_duration(timeout)
+        )
+    })?
+    .map_err(|_| anyhow!("Stopped following our connection"))?;
+
+    Ok(())
+}
+
+/// [reconnect], publishing that we're reconnecting and then whether it worked
+async fn resubscribe(
+    peripheral: &Peripheral,
+    data_out: &Characteristic,
+    connection: &watch::Sender<ConnectionState>,
+) {
+    connection.send_replace(ConnectionState::Reconnecting);
+    let state = match reconnect(peripheral, data_out).await {
+        Ok(()) => ConnectionState::Connected,
+        Err(e) => {
+            log::warn!("{:?} - Failed to reconnect: {e:?}", peripheral.id());
+            ConnectionState::Disconnected
+        }
+    };
+    connection.send_replace(state);
+}
+
+async fn reconnect(
+    peripheral: &Peripheral,
+    data_out: &Characteristic,
+) -> Result<(), anyhow::Error> {
+    let peripheral = connect_peripheral(peripheral.clone()).await?;
+    peripheral
+        .subscribe(data_out)
+        .await
+        .with_context(|| format!("{:?} - Subscribing to our heights", peripheral.address()))?;
+
+    Ok(())
+}
+
+/// What to do when the OS won't let us use Bluetooth
+pub const PERMISSION_HELP: &str = "Bluetooth access was denied, allow uplift (or your terminal) \
+    in System Settings > Privacy & Security > Bluetooth and check with `uplift permissions`";
+
+/// Connect to the OS Bluetooth manager, explaining the usual reasons we can't
+pub async fn manager() -> Result<Manager, anyhow::Error> {
     log::debug!("Connecting to Bluetooth Manager");
-    let manager = Manager::new().await?;
+    Manager::new().await.map_err(|e| {
+        let help = if matches!(e, btleplug::Error::PermissionDenied) {
+            PERMISSION_HELP
+        } else if wsl::is_wsl() {
+            wsl::NO_BLUETOOTH_HELP
+        } else {
+            "Couldn't connect to the Bluetooth Manager"
+        };
 
-    let adapters = manager.adapters().await?;
-    let central = adapters
+        anyhow::Error::new(e).context(help)
+    })
+}
+
+/// The adapter we use to talk to desks
+pub async fn first_adapter(manager: &Manager) -> Result<Adapter, anyhow::Error> {
+    manager
+        .adapters()
+        .await?
         .into_iter()
         .next()
-        .ok_or_else(|| anyhow!("Couldn't find an adapter"))?;
+        .ok_or_else(no_adapter)
+}
 
-    log::debug!("Using adapter: {:?}", central.adapter_info().await?);
+/// Every adapter our filter allows along with its info. Fails with
+/// [ConnectError::AdapterNotFound] if the filter names an adapter we don't have.
+pub async fn allowed_adapters(
+    manager: &Manager,
+    filter: &AdapterFilter,
+) -> Result<Vec<(Adapter, String)>, anyhow::Error> {
+    let mut all = Vec::new();
+    for central in manager.adapters().await? {
+        let adapter = central.adapter_info().await?;
+        all.push((central, adapter));
+    }
 
-    let mut events = central.events().await?;
+    if let Some(configured) = &filter.adapter {
+        if !all.iter().any(|(_, adapter)| filter.is_configured(adapter)) {
+            return Err(ConnectError::AdapterNotFound {
+                adapter: configured.clone(),
+                available: all.into_iter().map(|(_, adapter)| adapter).collect(),
+            }
+            .into());
+        }
+    }
 
-    // scan for our desk service
-    central
-        .start_scan(ScanFilter {
-            services: vec![DESK_SERVICE_UUID],
+    Ok(all
+        .into_iter()
+        .filter(|(_, adapter)| {
+            let allowed = filter.is_configured(adapter) && filter.allows(adapter);
+            if !allowed {
+                log::debug!("Skipping adapter: {adapter:?}");
+            }
+            allowed
         })
-        .await?;
-
-    let mut result = Err(anyhow!("Our adapter stopped looking for peripherals"));
-    while let Some(event) = events.next().await {
-        match event {
-            DeviceDiscovered(id) | DeviceUpdated(id) | DeviceConnected(id) => {
-                let peripheral = central
-                    .peripheral(&id)
-                    .await
-                    .context(format!("{id:?} - Couldn't get our Peripheral"))?;
+        .collect())
+}
 
-                log::trace!("{:?} - Discovered peripheral", peripheral.address());
+fn no_adapter() -> anyhow::Error {
+    ConnectError::NoAdapter.into()
+}
 
-                let properties = peripheral.properties().await.context(format!(
-                    "{:?} - Couldn't get properties",
-                    peripheral.address()
-                ))?;
+/// Add this desk to our inventory, this is best effort so we only log failures
+fn remember_desk(peripheral: &Peripheral, properties: &PeripheralProperties, adapter: &str) {
+    if let Err(e) = storage::record_desks([sighting(peripheral, properties, adapter)]) {
+        log::warn!("Failed to remember desk {}: {e:?}", peripheral.id());
+    }
+}
+
+fn sighting(
+    peripheral: &Peripheral,
+    properties: &PeripheralProperties,
+    adapter: &str,
+) -> DeskSighting {
+    DeskSighting {
+        id: peripheral.id().to_string(),
+        address: properties.address.to_string(),
+        name: properties.local_name.clone(),
+        adapter: adapter.to_string(),
+        rssi: properties.rssi,
+        last_seen: SystemTime::now(),
+    }
+}
+
+/// How [scan_with] looks for desks
+#[derive(Clone, Debug)]
+pub struct ScanOptions {
+    /// How long to scan for
+    pub duration: Duration,
+    /// Stop early once we've seen this many desks
+    pub max_desks: Option<usize>,
+    /// Only report each desk once, with its latest sighting
+    pub dedupe: bool,
+    pub adapters: AdapterFilter,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        ScanOptions {
+            duration: Duration::from_secs(5),
+            max_desks: None,
+            dedupe: true,
+            adapters: AdapterFilter::default(),
+        }
+    }
+}
 
-                if let Some(properties) = &properties {
-                    // even with the ScanFilter we still get initial unmatched devices, filter those out
-                    if properties.services.contains(&DESK_SERVICE_UUID) {
-                        log::debug!("{:?} - Attempting to connect", peripheral.address());
+/// Listen to and scan with every adapter, tagging each event with the index of its adapter. If one
+/// adapter fails to start we stop the ones we already started so none of them are left scanning.
+async fn start_scans(
+    centrals: &[(Adapter, String)],
+) -> Result<BoxStream<'static, (usize, CentralEvent)>, anyhow::Error> {
+    let mut streams = Vec::new();
+    for (index, (central, adapter)) in centrals.iter().enumerate() {
+        let started = async {
+            let events = central.events().await?;
+            central.start_scanning().await?;
+            Ok::<_, anyhow::Error>(events)
+        };
+        match started.await {
+            Ok(events) => streams.push(events.map(move |event| (index, event))),
+            Err(e) => {
+                stop_scans(&centrals[..index]).await;
+                return Err(e.context(format!("{adapter} - Failed to start scanning")));
+            }
+        }
+    }
 
-                        peripheral
-                            .connect()
-                            .await
-                            .context(format!("{:?} - Connection failed", peripheral.address()))?;
+    Ok(futures::stream::select_all(streams).boxed())
+}
+
+/// Stop scanning with every adapter, carrying on past failures so the rest still stop
+async fn stop_scans(centrals: &[(Adapter, String)]) {
+    for (central, adapter) in centrals {
+        if let Err(e) = central.stop_scanning().await {
+            log::warn!("{adapter} - Failed to stop scanning: {e:?}");
+        }
+    }
+}
+
+/// Scan every allowed adapter for desks without connecting to them, in the order we saw them
+pub async fn scan_with(options: &ScanOptions) -> Result<Vec<DeskSighting>, anyhow::Error> {
+    let manager = manager().await?;
+
+    let centrals = allowed_adapters(&manager, &options.adapters).await?;
+    if centrals.is_empty() {
+        return Err(no_adapter());
+    }
 
-                        result = Ok((manager, peripheral));
-                        break;
+    let mut events = start_scans(&centrals).await?;
+
+    let mut desks: Vec<DeskSighting> = Vec::new();
+    let deadline = time::sleep(options.duration);
+    tokio::pin!(deadline);
+    while options.max_desks.is_none_or(|max| desks.len() < max) {
+        let (index, id) = tokio::select! {
+            _ = &mut deadline => break,
+            event = events.next() => match event {
+                Some((index, DeviceDiscovered(id) | DeviceUpdated(id))) => (index, id),
+                Some((_, event)) => {
+                    chatter!("Bluetooth event: {event:?}");
+                    continue;
+                }
+                None => break,
+            },
+        };
+
+        let (central, adapter) = &centrals[index];
+        // one device going away mid scan shouldn't end the scan for the rest
+        let (peripheral, properties) = match central.discovered(&id).await {
+            Ok(Some(found)) => found,
+            Ok(None) => continue,
+            Err(e) => {
+                log::debug!("{e:?}");
+                continue;
+            }
+        };
+        // even with the ScanFilter we still get initial unmatched devices, filter those out
+        if !properties.services.contains(&DESK_SERVICE_UUID) {
+            continue;
+        }
+
+        let sighting = sighting(&peripheral, &properties, adapter);
+        match desks.iter_mut().find(|desk| desk.id == sighting.id) {
+            Some(desk) if options.dedupe => *desk = sighting,
+            _ => desks.push(sighting),
+        }
+    }
+
+    stop_scans(&centrals).await;
+
+    if let Err(e) = storage::record_desks(desks.clone()) {
+        log::warn!("Failed to remember desks: {e:?}");
+    }
+
+    Ok(desks)
+}
+
+/// Scan every allowed adapter once for all of the `desks` and connect to each one as soon as we find
+/// it. Connecting to several desks with their own [Desk::with_options] would race their scans on the
+/// same adapter, the first desk to connect stops the scan the others are still waiting on.
+///
+/// Every desk comes back on the stream with the filter it matched, either connected or why we
+/// couldn't connect. We scan for up to `timeout`, each desk gets up to `timeout` to connect once we
+/// find it and up to `concurrency` desks connect at once.
+pub async fn connect_each(
+    desks: Vec<String>,
+    adapters: &AdapterFilter,
+    timeout: Duration,
+    concurrency: usize,
+) -> Result<BoxStream<'static, (String, Result<Desk, anyhow::Error>)>, anyhow::Error> {
+    if desks.is_empty() {
+        return Ok(futures::stream::empty().boxed());
+    }
+    let manager = manager().await?;
+
+    let centrals = allowed_adapters(&manager, adapters).await?;
+    if centrals.is_empty() {
+        return Err(no_adapter());
+    }
+
+    let mut events = start_scans(&centrals).await?;
+
+    let (sender, receiver) = mpsc::channel(desks.len().max(1));
+    tokio::spawn(async move {
+        let permits = Arc::new(Semaphore::new(concurrency.max(1)));
+        let mut missing = desks.into_iter().collect::<HashSet<_>>();
+        let deadline = time::sleep(timeout);
+        tokio::pin!(deadline);
+        while !missing.is_empty() {
+            let (index, id) = tokio::select! {
+                _ = &mut deadline => break,
+                event = events.next() => match event {
+                    Some((index, DeviceDiscovered(id) | DeviceUpdated(id))) => (index, id),
+                    Some((_, event)) => {
+                        chatter!("Bluetooth event: {event:?}");
+                        continue;
                     }
+                    None => break,
+                },
+            };
+
+            let (central, adapter) = &centrals[index];
+            let (peripheral, properties) = match central.discovered(&id).await {
+                Ok(Some(found)) => found,
+                Ok(None) => continue,
+                Err(e) => {
+                    log::debug!("{e:?}");
+                    continue;
                 }
+            };
+            let peripheral_id = peripheral.id().to_string();
+            let Some(desk) = missing
+                .iter()
+                .find(|desk| matches_desk(desk, &peripheral_id, &properties))
+                .cloned()
+            else {
+                continue;
+            };
+            missing.remove(&desk);
+            remember_desk(&peripheral, &properties, adapter);
 
-                log::trace!(
-                    "{:?} - Peripheral didn't contain the Desk Service",
-                    properties
-                );
+            log::debug!("{:?} - Found {desk}, connecting", peripheral.address());
+            let manager = manager.clone();
+            let central = central.clone();
+            let permits = permits.clone();
+            let sender = sender.clone();
+            tokio::spawn(async move {
+                let _permit = permits.acquire_owned().await;
+                let connected = time::timeout(timeout, async {
+                    let peripheral = connect_peripheral(peripheral).await?;
+                    Desk::from_peripheral(manager, &central, peripheral, None).await
+                })
+                .await
+                .unwrap_or_else(|_| Err(anyhow!("Timed out connecting")));
+                let _ = sender.send((desk, connected)).await;
+            });
+        }
+
+        stop_scans(&centrals).await;
+        for desk in missing {
+            let e = anyhow!(
+                "Didn't find it after scanning for {}",
+                humantime::format_duration(timeout)
+            );
+            let _ = sender.send((desk, Err(e))).await;
+        }
+    });
+
+    Ok(
+        futures::stream::unfold(receiver, |mut receiver| async move {
+            receiver.recv().await.map(|desk| (desk, receiver))
+        })
+        .boxed(),
+    )
+}
+
+/// Check if our `desk` filter matches this peripheral's id, address or name
+fn matches_desk(desk: &str, id: &str, properties: &PeripheralProperties) -> bool {
+    desk.eq_ignore_ascii_case(id)
+        || desk.eq_ignore_ascii_case(&properties.address.to_string())
+        || properties.local_name.as_deref() == Some(desk)
+}
+
+/// Subscribe to height notifications, returning the data-in, data-out and name characteristics. We
+/// try the characteristics cached from our last connection first since service discovery is slow,
+/// but most platforms can't use them without discovery so we fall back to discovering them.
+async fn subscribe_to_desk(
+    peripheral: &Peripheral,
+    id: &str,
+) -> Result<(Characteristic, Characteristic, Characteristic), anyhow::Error> {
+    let cached = storage::cached_characteristics(id).unwrap_or_else(|e| {
+        log::warn!("{id} - Failed to load our cached characteristics: {e:?}");
+        None
+    });
+    if let Some(cached) = cached {
+        let [data_in, data_out, name] =
+            [cached.data_in, cached.data_out, cached.name].map(|characteristic| Characteristic {
+                uuid: characteristic.uuid,
+                service_uuid: characteristic.service_uuid,
+                properties: CharPropFlags::from_bits_truncate(characteristic.properties),
+                descriptors: BTreeSet::new(),
+            });
+
+        match peripheral.subscribe(&data_out).await {
+            Ok(()) => {
+                verbose!("Using cached characteristics");
+                return Ok((data_in, data_out, name));
             }
-            event => log::trace!("Unhandled Event: {:?}", event),
+            Err(e) => log::debug!("{id} - Couldn't use our cached characteristics: {e:?}"),
         }
     }
 
-    central.stop_scan().await?;
+    verbose!("Discovering services");
+    {
+        let _timing = timings::phase("service discovery");
+        peripheral
+            .discover_services()
+            .await
+            .with_context(|| format!("{:?} - Discovering Services", peripheral.address()))?;
+    }
 
-    result
+    let (data_in, data_out, name) = {
+        let _timing = timings::phase("characteristic lookup");
+        get_characteristics(peripheral.characteristics())?
+    };
+
+    peripheral
+        .subscribe(&data_out)
+        .await
+        .with_context(|| format!("{:?} - Subscribing to desk updates", peripheral.address()))?;
+
+    let cache = |characteristic: &Characteristic| CachedCharacteristic {
+        uuid: characteristic.uuid,
+        service_uuid: characteristic.service_uuid,
+        properties: characteristic.properties.bits(),
+    };
+    let cached = CachedCharacteristics {
+        data_in: cache(&data_in),
+        data_out: cache(&data_out),
+        name: cache(&name),
+    };
+    if let Err(e) = storage::save_cached_characteristics(id, Some(&cached)) {
+        log::warn!("{id} - Failed to cache our characteristics: {e:?}");
+    }
+
+    Ok((data_in, data_out, name))
 }
 
-fn get_characteristics(
+pub(crate) fn get_characteristics(
     characteristics: BTreeSet<Characteristic>,
 ) -> Result<(Characteristic, Characteristic, Characteristic), anyhow::Error> {
     let mut data_in_characteristic = None;
@@ -300,8 +2427,383 @@ fn get_characteristics(
     }
 
     Ok((
-        data_in_characteristic.ok_or_else(|| anyhow!("Couldn't get data-in characteristic"))?,
-        data_out_characteristic.ok_or_else(|| anyhow!("Couldn't find data-out characteristic"))?,
-        name_characteristic.ok_or_else(|| anyhow!("Couldn't find name characteristic"))?,
+        data_in_characteristic.ok_or(ConnectError::MissingCharacteristic("data-in"))?,
+        data_out_characteristic.ok_or(ConnectError::MissingCharacteristic("data-out"))?,
+        name_characteristic.ok_or(ConnectError::MissingCharacteristic("name"))?,
     ))
 }
+
+#[cfg(test)]
+mod tests {
+    use btleplug::api::CentralState;
+
+    use super::*;
+
+    /// A height notification laid out the way the desk sends them, `decode_height` reads the low
+    /// byte at 5 and the high byte at 7
+    fn notification(low: u8, high: u8) -> [u8; 9] {
+        [0xf2, 0xf2, 0x01, 0x03, 0x01, low, 0x07, high, 0x7e]
+    }
+
+    /// Run a notification through the same path as our notification task
+    fn decode(decoder: DecoderKind, frame: &[u8], last_height: isize) -> isize {
+        let raw = protocol::decode_height(frame).expect("A complete notification");
+        decoder.decoder().decode(raw, last_height)
+    }
+
+    #[test]
+    fn low_byte_decoder() {
+        for (low, high, last_height, expected) in [
+            // the bottom of the range
+            (0x00, 0x00, -1, MIN_PHYSICAL_HEIGHT),
+            // our average sitting and standing heights
+            (0x08, 0x00, 252, AVG_SITTING_HEIGHT),
+            (0x99, 0x00, 390, AVG_STANDING_HEIGHT),
+            // the special values are taken at face value, even near the bottom
+            (0xfe, 0x02, 254, 506),
+            (0xff, 0x03, -1, MAX_PHYSICAL_HEIGHT),
+        ] {
+            assert_eq!(
+                decode(DecoderKind::LowByte, &notification(low, high), last_height),
+                expected,
+                "({low:x}, {high:x}) after {last_height}"
+            );
+        }
+    }
+
+    #[test]
+    fn low_high_decoder() {
+        for (low, high, last_height, expected) in [
+            // below the special values the high byte never matters
+            (0x00, 0x00, -1, MIN_PHYSICAL_HEIGHT),
+            (0x08, 0x55, 252, AVG_SITTING_HEIGHT),
+            (0x99, 0x02, 390, AVG_STANDING_HEIGHT),
+            (0xfc, 0x00, 500, 504),
+            // a special low byte near the bottom means the high byte has our height
+            (0xfe, 0x02, 254, 254),
+            (0xfd, 0x03, MID_PHYSICAL_HEIGHT - 1, 255),
+            // before our first height we assume we're near the bottom
+            (0xff, 0x01, -1, 253),
+            // near the top the low byte is our height
+            (0xfe, 0x02, 500, 506),
+            (0xff, 0x03, MID_PHYSICAL_HEIGHT, MAX_PHYSICAL_HEIGHT),
+        ] {
+            assert_eq!(
+                decode(DecoderKind::LowHigh, &notification(low, high), last_height),
+                expected,
+                "({low:x}, {high:x}) after {last_height}"
+            );
+        }
+    }
+
+    #[test]
+    fn decoders_only_diverge_on_special_low_bytes() {
+        for low in 0..=0xff {
+            let frame = notification(low, 0x02);
+            let diverged = decode(DecoderKind::LowByte, &frame, 254)
+                != decode(DecoderKind::LowHigh, &frame, 254);
+
+            assert_eq!(diverged, low >= 0xfd, "low byte {low:x}");
+        }
+    }
+
+    #[test]
+    fn decoder_kinds_round_trip() {
+        for kind in [DecoderKind::LowHigh, DecoderKind::LowByte] {
+            let json = serde_json::to_string(&kind).unwrap();
+            assert_eq!(serde_json::from_str::<DecoderKind>(&json).unwrap(), kind);
+        }
+        assert_eq!(DecoderKind::default(), DecoderKind::LowHigh);
+    }
+
+    #[test]
+    fn adapters_match_whole_names_and_addresses() {
+        let only = |adapter: &str| AdapterFilter {
+            adapter: Some(adapter.to_string()),
+            ..Default::default()
+        };
+        let hci1 = "hci1 (usb:v1D6Bp0246d0537) 00:1A:7D:DA:71:13";
+        let hci10 = "hci10 (usb:v1D6Bp0246d0537) 00:1A:7D:DA:71:14";
+
+        assert!(only("hci1").is_configured(hci1));
+        assert!(only("HCI1").is_configured(hci1));
+        assert!(!only("hci1").is_configured(hci10));
+        assert!(only("hci10").is_configured(hci10));
+        assert!(!only("hci10").is_configured(hci1));
+
+        assert!(only("00:1a:7d:da:71:13").is_configured(hci1));
+        assert!(only("00-1A-7D-DA-71-13").is_configured(hci1));
+        assert!(!only("00:1A:7D:DA:71:13").is_configured(hci10));
+        // a prefix of an address isn't enough
+        assert!(!only("00:1A:7D").is_configured(hci1));
+        assert!(!only("usb").is_configured(hci1));
+
+        assert!(AdapterFilter::default().is_configured(hci10));
+    }
+
+    /// An adapter that never finds any peripherals, counting how often we scan
+    #[derive(Default)]
+    struct EmptyScanner {
+        started: AtomicUsize,
+        stopped: AtomicUsize,
+    }
+
+    impl Scanner for EmptyScanner {
+        async fn start_scanning(&self) -> Result<(), anyhow::Error> {
+            self.started.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        }
+
+        async fn stop_scanning(&self) -> Result<(), anyhow::Error> {
+            self.stopped.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        }
+
+        async fn discovered(
+            &self,
+            _id: &PeripheralId,
+        ) -> Result<Option<(Peripheral, PeripheralProperties)>, anyhow::Error> {
+            Ok(None)
+        }
+    }
+
+    const RETRY: ScanRetry = ScanRetry {
+        windows: 3,
+        window: Duration::from_secs(10),
+        pause: Duration::from_secs(5),
+    };
+
+    #[tokio::test(start_paused = true)]
+    async fn scan_ends_when_the_adapter_stops_sending_events() {
+        let scanner = EmptyScanner::default();
+        let e = scan_and_connect(
+            &scanner,
+            futures::stream::empty(),
+            "hci0",
+            None,
+            None,
+            RETRY,
+        )
+        .await
+        .unwrap_err();
+
+        assert!(
+            matches!(
+                e.downcast_ref(),
+                Some(ConnectError::ScanEnded { adapter }) if adapter == "hci0"
+            ),
+            "{e:?}"
+        );
+        assert_eq!(scanner.started.load(Ordering::Relaxed), 1);
+        assert_eq!(scanner.stopped.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn no_desk_found_after_every_window() {
+        let scanner = EmptyScanner::default();
+        // the adapter keeps talking but never about a desk
+        let events = futures::stream::iter([CentralEvent::StateUpdate(CentralState::PoweredOn)])
+            .chain(futures::stream::pending());
+        let started = time::Instant::now();
+        let e = scan_and_connect(&scanner, events, "hci0", Some("desk"), None, RETRY)
+            .await
+            .unwrap_err();
+
+        assert!(
+            matches!(
+                e.downcast_ref(),
+                Some(ConnectError::NoDeskFound { adapter, windows: 3 }) if adapter == "hci0"
+            ),
+            "{e:?}"
+        );
+        // every window and a jittered pause between each of them
+        let elapsed = started.elapsed();
+        assert!(
+            elapsed >= RETRY.window * 3 + RETRY.pause * 2
+                && elapsed <= RETRY.window * 3 + RETRY.pause.mul_f64(1.2) * 2,
+            "{elapsed:?}"
+        );
+        assert_eq!(scanner.started.load(Ordering::Relaxed), 3);
+        assert_eq!(scanner.stopped.load(Ordering::Relaxed), 3);
+    }
+
+    /// A desk that answers queries 50ms later and streams a notification every 100ms while it
+    /// moves at 2" a second, which only follows our commands if it `listens`
+    struct FakeDesk {
+        listens: bool,
+        height: isize,
+        /// When we started moving, and which way
+        moving: Option<(time::Instant, isize)>,
+        /// Notifications from moves we've finished
+        streamed: usize,
+        answers: Vec<time::Instant>,
+    }
+
+    impl FakeDesk {
+        fn still(listens: bool) -> Self {
+            FakeDesk {
+                listens,
+                height: AVG_STANDING_HEIGHT,
+                moving: None,
+                streamed: 0,
+                answers: Vec::new(),
+            }
+        }
+
+        /// Already on its way down, say from the keypad
+        fn sitting(listens: bool) -> Self {
+            FakeDesk {
+                moving: Some((time::Instant::now() - Duration::from_secs(1), -1)),
+                ..FakeDesk::still(listens)
+            }
+        }
+
+        fn moved(&self) -> (usize, isize) {
+            match self.moving {
+                Some((started, direction)) => {
+                    let millis = started.elapsed().as_millis() as isize;
+                    ((millis / 100) as usize, direction * millis / 50)
+                }
+                None => (0, 0),
+            }
+        }
+
+        fn stop(&mut self) {
+            let (streamed, moved) = self.moved();
+            self.streamed += streamed;
+            self.height += moved;
+            self.moving = None;
+        }
+    }
+
+    impl CommandLink for FakeDesk {
+        async fn write(&mut self, packet: &[u8]) -> Result<(), anyhow::Error> {
+            if packet == QUERY_PACKET {
+                if self.listens {
+                    self.answers
+                        .push(time::Instant::now() + Duration::from_millis(50));
+                }
+            } else if self.listens {
+                self.stop();
+                if packet == SIT_PACKET {
+                    self.moving = Some((time::Instant::now(), -1));
+                }
+            }
+            Ok(())
+        }
+
+        fn seen(&self) -> (usize, isize) {
+            let answered = self
+                .answers
+                .iter()
+                .filter(|answer| **answer <= time::Instant::now())
+                .count();
+            let (streamed, moved) = self.moved();
+            (self.streamed + answered + streamed, self.height + moved)
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn still_desks_acknowledge_by_answering() {
+        for (packet, listens, expected) in [
+            (SIT_PACKET, true, CommandOutcome::Acknowledged),
+            (STOP_PACKET, true, CommandOutcome::Acknowledged),
+            (SIT_PACKET, false, CommandOutcome::Unacknowledged),
+        ] {
+            let mut desk = FakeDesk::still(listens);
+            assert_eq!(
+                acknowledge(&mut desk, &packet, ACKNOWLEDGEMENT_TIMEOUT)
+                    .await
+                    .unwrap(),
+                expected,
+                "{packet:x?}"
+            );
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn moving_desks_acknowledge_by_changing_course() {
+        let mut desk = FakeDesk::sitting(true);
+        assert_eq!(
+            acknowledge(&mut desk, &STOP_PACKET, ACKNOWLEDGEMENT_TIMEOUT)
+                .await
+                .unwrap(),
+            CommandOutcome::Acknowledged
+        );
+        assert!(desk.moving.is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn height_streams_arent_acknowledgements() {
+        // the desk keeps moving and notifying us, but it never heard our stop
+        let mut desk = FakeDesk::sitting(false);
+        let started = time::Instant::now();
+        assert_eq!(
+            acknowledge(&mut desk, &STOP_PACKET, ACKNOWLEDGEMENT_TIMEOUT)
+                .await
+                .unwrap(),
+            CommandOutcome::Unacknowledged
+        );
+        assert!(desk.moving.is_some());
+        assert!(started.elapsed() >= ACKNOWLEDGEMENT_TIMEOUT);
+    }
+
+    /// Disconnects when asked, the adapter's disconnect event follows `event_after` later
+    struct FakePeripheral {
+        connection: Arc<watch::Sender<ConnectionState>>,
+        event_after: Option<Duration>,
+    }
+
+    impl FakePeripheral {
+        fn connected(event_after: Option<Duration>) -> Self {
+            FakePeripheral {
+                connection: Arc::new(watch::Sender::new(ConnectionState::Connected)),
+                event_after,
+            }
+        }
+    }
+
+    impl Disconnect for FakePeripheral {
+        async fn start_disconnect(&self) -> Result<(), anyhow::Error> {
+            if let Some(after) = self.event_after {
+                let connection = self.connection.clone();
+                tokio::spawn(async move {
+                    time::sleep(after).await;
+                    connection.send_replace(ConnectionState::Disconnected);
+                });
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn disconnect_waits_for_the_disconnect_event() {
+        let peripheral = FakePeripheral::connected(Some(Duration::from_secs(1)));
+        let started = time::Instant::now();
+        disconnect_and_wait(
+            &peripheral,
+            peripheral.connection.subscribe(),
+            DISCONNECT_TIMEOUT,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(started.elapsed(), Duration::from_secs(1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn disconnect_times_out_without_the_disconnect_event() {
+        let peripheral = FakePeripheral::connected(None);
+        let started = time::Instant::now();
+        let e = disconnect_and_wait(
+            &peripheral,
+            peripheral.connection.subscribe(),
+            DISCONNECT_TIMEOUT,
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(e.to_string(), "Still connected after 5s");
+        assert_eq!(started.elapsed(), DISCONNECT_TIMEOUT);
+    }
+}
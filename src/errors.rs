@@ -0,0 +1,53 @@
+use serde_json::{json, Value};
+use tokio::time::error::Elapsed;
+
+use crate::run_id;
+use uplift_lib::desk::{ConnectError, OutOfRange, QueryTimeout, WakeError};
+use uplift_lib::quiet_hours::QuietHoursError;
+
+/// A machine readable version of our error for `--json-errors`, the codes are based on the typed
+/// errors anywhere in the error chain
+pub fn to_json(error: &anyhow::Error, desk: Option<&str>) -> Value {
+    let (code, phase, retryable) = classify(error);
+
+    json!({
+        "code": code,
+        "phase": phase,
+        "desk": desk,
+        "message": format!("{error:#}"),
+        "retryable": retryable,
+        "run_id": run_id::get(),
+    })
+}
+
+fn classify(error: &anyhow::Error) -> (&'static str, Option<&'static str>, bool) {
+    for cause in error.chain() {
+        if let Some(connect) = cause.downcast_ref::<ConnectError>() {
+            return match connect {
+                ConnectError::NoAdapter => ("no_adapter", Some("connect"), false),
+                ConnectError::AdapterNotFound { .. } => {
+                    ("adapter_not_found", Some("connect"), false)
+                }
+                ConnectError::ScanEnded { .. } => ("scan_ended", Some("scan"), true),
+                ConnectError::NoDeskFound { .. } => ("no_desk_found", Some("scan"), true),
+                ConnectError::MissingCharacteristic(_) => {
+                    ("unsupported_desk", Some("connect"), false)
+                }
+            };
+        } else if let Some(btleplug::Error::PermissionDenied) = cause.downcast_ref() {
+            return ("permission_denied", Some("connect"), false);
+        } else if cause.is::<WakeError>() {
+            return ("desk_not_responding", Some("wake"), true);
+        } else if cause.is::<QueryTimeout>() {
+            return ("query_timeout", Some("query"), true);
+        } else if cause.is::<OutOfRange>() {
+            return ("out_of_range", None, false);
+        } else if cause.is::<QuietHoursError>() {
+            return ("quiet_hours", None, false);
+        } else if cause.is::<Elapsed>() {
+            return ("timeout", None, true);
+        }
+    }
+
+    ("error", None, false)
+}
@@ -0,0 +1,60 @@
+//! Detect if the OS is in Do Not Disturb / Focus mode, this is best effort since every platform
+//! exposes it differently
+
+/// Returns `Ok(false)` on platforms where we can't tell
+pub fn is_focus_active() -> Result<bool, anyhow::Error> {
+    platform::is_focus_active()
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::fs;
+
+    use anyhow::{anyhow, Context};
+    use serde_json::Value;
+
+    /// Focus modes are stored as assertions, if there are any then a focus is active
+    pub fn is_focus_active() -> Result<bool, anyhow::Error> {
+        let path = dirs::home_dir()
+            .ok_or_else(|| anyhow!("Couldn't find our home directory"))?
+            .join("Library/DoNotDisturb/DB/Assertions.json");
+        let assertions: Value = serde_json::from_slice(
+            &fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?,
+        )?;
+
+        Ok(assertions["data"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .any(|data| {
+                data["storeAssertionRecords"]
+                    .as_array()
+                    .is_some_and(|records| !records.is_empty())
+            }))
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::process::Command;
+
+    use anyhow::Context;
+
+    /// GNOME turns off notification banners while Do Not Disturb is on
+    pub fn is_focus_active() -> Result<bool, anyhow::Error> {
+        let output = Command::new("gsettings")
+            .args(["get", "org.gnome.desktop.notifications", "show-banners"])
+            .output()
+            .context("Failed to run gsettings")?;
+
+        Ok(output.status.success() && String::from_utf8_lossy(&output.stdout).trim() == "false")
+    }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+mod platform {
+    pub fn is_focus_active() -> Result<bool, anyhow::Error> {
+        log::debug!("Focus detection isn't supported on this platform");
+        Ok(false)
+    }
+}
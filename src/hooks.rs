@@ -0,0 +1,414 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::{anyhow, Context};
+use tokio::process::Command;
+
+use crate::config::Config;
+use crate::run_id;
+use uplift_lib::output::{self, Height, HeightFormat, HeightUnit};
+
+static ANNOUNCE: AtomicBool = AtomicBool::new(false);
+
+/// Speak every event we fire with the OS text to speech, on top of `announce` in `[hooks]`
+pub fn set_announce(announce: bool) {
+    ANNOUNCE.store(announce, Ordering::Relaxed);
+}
+
+/// Why a hook is running, this is `UPLIFT_EVENT` and `{event}` in the hook
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HookEvent {
+    Sit,
+    Stand,
+    /// We reached the height we were moving to
+    Target,
+    Error,
+    /// An action's `before` hook
+    Before,
+    /// An action's `after` hook
+    After,
+    /// An `uplift alert` threshold was crossed
+    #[cfg(feature = "streaming")]
+    Alert,
+}
+
+impl HookEvent {
+    fn name(self) -> &'static str {
+        match self {
+            HookEvent::Sit => "sit",
+            HookEvent::Stand => "stand",
+            HookEvent::Target => "target",
+            HookEvent::Error => "error",
+            HookEvent::Before => "before",
+            HookEvent::After => "after",
+            #[cfg(feature = "streaming")]
+            HookEvent::Alert => "alert",
+        }
+    }
+}
+
+/// What we tell a hook about, as `UPLIFT_*` environment variables and `{...}` placeholders in the
+/// hook itself
+#[derive(Clone, Debug)]
+pub struct HookContext<'a> {
+    pub event: HookEvent,
+    pub desk_id: Option<&'a str>,
+    pub height: Option<isize>,
+    pub target: Option<isize>,
+    pub error: Option<String>,
+}
+
+impl<'a> HookContext<'a> {
+    pub fn new(event: HookEvent, desk_id: Option<&'a str>) -> HookContext<'a> {
+        HookContext {
+            event,
+            desk_id,
+            height: None,
+            target: None,
+            error: None,
+        }
+    }
+
+    pub fn height(self, height: isize) -> Self {
+        HookContext {
+            height: Some(height),
+            ..self
+        }
+    }
+
+    pub fn target(self, target: isize) -> Self {
+        HookContext {
+            target: Some(target),
+            ..self
+        }
+    }
+
+    pub fn error(self, error: &anyhow::Error) -> Self {
+        HookContext {
+            error: Some(format!("{error:#}")),
+            ..self
+        }
+    }
+
+    /// What we say for `--announce`, with heights in the units we display them in. The words
+    /// themselves are always English, pick a voice that can read English with `voice` in `[hooks]`.
+    fn announcement(&self, format: HeightFormat) -> String {
+        let unit = match format.unit {
+            HeightUnit::In => "inches",
+            HeightUnit::Cm => "centimeters",
+        };
+        let height = self
+            .height
+            .map(|height| format!(" at {} {unit}", format.number(height)))
+            .unwrap_or_default();
+
+        match self.event {
+            HookEvent::Sit => format!("Desk sitting{height}"),
+            HookEvent::Stand => format!("Desk standing{height}"),
+            HookEvent::Target => format!("Desk reached its target{height}"),
+            HookEvent::Error => "Desk command failed".to_string(),
+            HookEvent::Before | HookEvent::After => format!("Desk action{height}"),
+            #[cfg(feature = "streaming")]
+            HookEvent::Alert => format!("Desk height alert{height}"),
+        }
+    }
+
+    /// Every variable we know, ie: `("height_in", "30.5")`
+    fn variables(&self) -> Vec<(&'static str, String)> {
+        let mut variables = vec![
+            ("event", self.event.name().to_string()),
+            ("run_id", run_id::get().to_string()),
+        ];
+        if let Some(desk_id) = self.desk_id {
+            variables.push(("desk_id", desk_id.to_string()));
+        }
+        if let Some(height) = self.height.map(Height::from_tenths) {
+            variables.push(("height_in", height.height_in.to_string()));
+            variables.push(("height_cm", height.height_cm.to_string()));
+        }
+        if let Some(target) = self.target.map(Height::from_tenths) {
+            variables.push(("target_in", target.height_in.to_string()));
+            variables.push(("target_cm", target.height_cm.to_string()));
+        }
+        if let Some(error) = &self.error {
+            variables.push(("error", error.clone()));
+        }
+
+        variables
+    }
+}
+
+/// Run the hook from `[hooks]` for our event, if there is one. Hook failures are only logged since
+/// the desk has already moved.
+pub async fn fire(context: HookContext<'_>) {
+    let (hook, announce, voice) = match Config::load() {
+        Ok(config) => (
+            config.hooks.get(context.event).map(str::to_string),
+            config.hooks.announce,
+            config.hooks.voice,
+        ),
+        Err(e) => {
+            log::warn!("Failed to load our hooks: {e:?}");
+            (None, false, None)
+        }
+    };
+
+    if announce || ANNOUNCE.load(Ordering::Relaxed) {
+        let announcement = context.announcement(output::height_format());
+        if let Err(e) = speak(&announcement, voice.as_deref()).await {
+            log::warn!("Failed to announce {}: {e:?}", context.event.name());
+        }
+    }
+
+    if let Some(hook) = hook {
+        if let Err(e) = run_hook(&hook, &context).await {
+            log::warn!("The {} hook failed: {e:?}", context.event.name());
+        }
+    }
+}
+
+/// Run a user hook through the shell, failing if it exits unsuccessfully
+pub async fn run_hook(hook: &str, context: &HookContext<'_>) -> Result<(), anyhow::Error> {
+    let variables = context.variables();
+    let names = variables.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+    let hook = render(hook, &names);
+    log::debug!("Running hook: {hook}");
+
+    let status = shell(&hook)
+        .envs(
+            variables
+                .iter()
+                .map(|(name, value)| (env_name(name), value)),
+        )
+        .status()
+        .await
+        .with_context(|| format!("Failed to run hook `{hook}`"))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(anyhow!("Hook `{hook}` failed with {status}"))
+    }
+}
+
+/// Say `text` with the OS text to speech, in its default voice unless we pick one
+async fn speak(text: &str, voice: Option<&str>) -> Result<(), anyhow::Error> {
+    let status = speech(text, voice)
+        .status()
+        .await
+        .context("Failed to run text to speech")?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(anyhow!("Text to speech failed with {status}"))
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn speech(text: &str, voice: Option<&str>) -> Command {
+    let mut command = Command::new("say");
+    if let Some(voice) = voice {
+        command.arg("-v").arg(voice);
+    }
+    command.arg(text);
+    command
+}
+
+#[cfg(windows)]
+fn speech(text: &str, voice: Option<&str>) -> Command {
+    let quote = |text: &str| format!("'{}'", text.replace('\'', "''"));
+    let select_voice = voice
+        .map(|voice| format!("$speech.SelectVoice({}); ", quote(voice)))
+        .unwrap_or_default();
+    let mut command = Command::new("powershell");
+    command.arg("-NoProfile").arg("-Command").arg(format!(
+        "Add-Type -AssemblyName System.Speech; \
+        $speech = New-Object System.Speech.Synthesis.SpeechSynthesizer; \
+        {select_voice}$speech.Speak({})",
+        quote(text)
+    ));
+    command
+}
+
+/// speech-dispatcher, which is on most Linux desktops
+#[cfg(not(any(target_os = "macos", windows)))]
+fn speech(text: &str, voice: Option<&str>) -> Command {
+    let mut command = Command::new("spd-say");
+    command.arg("--wait");
+    if let Some(voice) = voice {
+        command.arg("--synthesis-voice").arg(voice);
+    }
+    command.arg(text);
+    command
+}
+
+fn env_name(name: &str) -> String {
+    format!("UPLIFT_{}", name.to_uppercase())
+}
+
+/// Replace each `{name}` with a reference to its `UPLIFT_*` variable instead of the value, so the
+/// shell never parses what's inside a value, ie: the backticks in our errors. The reference depends
+/// on the quotes around the placeholder so `'at {height_in}in'` still expands.
+#[cfg(not(windows))]
+fn render(hook: &str, names: &[&str]) -> String {
+    let mut rendered = String::with_capacity(hook.len());
+    let mut quote = None;
+    let mut rest = hook;
+    while let Some(c) = rest.chars().next() {
+        let placeholder = names
+            .iter()
+            .find(|name| rest.starts_with(&format!("{{{name}}}")));
+        if let Some(name) = placeholder {
+            let variable = env_name(name);
+            match quote {
+                None => rendered.push_str(&format!("\"${{{variable}}}\"")),
+                Some('\'') => rendered.push_str(&format!("'\"${{{variable}}}\"'")),
+                _ => rendered.push_str(&format!("${{{variable}}}")),
+            }
+            rest = &rest[name.len() + 2..];
+            continue;
+        }
+
+        rendered.push(c);
+        rest = &rest[c.len_utf8()..];
+        match (c, quote) {
+            // a backslash escapes the next character everywhere but single quotes
+            ('\\', None | Some('"')) => {
+                if let Some(escaped) = rest.chars().next() {
+                    rendered.push(escaped);
+                    rest = &rest[escaped.len_utf8()..];
+                }
+            }
+            ('\'' | '"', None) => quote = Some(c),
+            (c, Some(open)) if c == open => quote = None,
+            _ => {}
+        }
+    }
+
+    rendered
+}
+
+/// Replace each `{name}` with its `UPLIFT_*` variable, delayed expansion happens after cmd has
+/// parsed the hook so the value is never parsed
+#[cfg(windows)]
+fn render(hook: &str, names: &[&str]) -> String {
+    names.iter().fold(hook.to_string(), |hook, name| {
+        hook.replace(&format!("{{{name}}}"), &format!("!{}!", env_name(name)))
+    })
+}
+
+#[cfg(not(windows))]
+fn shell(hook: &str) -> Command {
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(hook);
+    command
+}
+
+#[cfg(windows)]
+fn shell(hook: &str) -> Command {
+    let mut command = Command::new("cmd");
+    command.arg("/V:ON").arg("/C").arg(hook);
+    command
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use super::*;
+
+    #[cfg(not(windows))]
+    #[test]
+    fn renders_placeholders_as_variables() {
+        let names = ["error", "height_in"];
+        for (hook, expected) in [
+            ("echo {error}", r#"echo "${UPLIFT_ERROR}""#),
+            (
+                "echo '{height_in}in'",
+                r#"echo ''"${UPLIFT_HEIGHT_IN}"'in'"#,
+            ),
+            (
+                r#"echo "failed: {error}""#,
+                r#"echo "failed: ${UPLIFT_ERROR}""#,
+            ),
+            // an escaped quote doesn't start a quote
+            (r#"echo \'{error}"#, r#"echo \'"${UPLIFT_ERROR}""#),
+            (r#"echo "\"{error}""#, r#"echo "\"${UPLIFT_ERROR}""#),
+            (
+                "echo {unknown} {{error}}",
+                r#"echo {unknown} {"${UPLIFT_ERROR}"}"#,
+            ),
+        ] {
+            assert_eq!(render(hook, &names), expected, "{hook}");
+        }
+    }
+
+    /// Run `hook` with an error that tries to run a command, returning what the hook wrote
+    #[cfg(unix)]
+    async fn run_with_error(name: &str, hook: &str) -> String {
+        let dir = env::temp_dir().join(format!("uplift-hooks-{}-{name}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("output");
+        let injected = dir.join("injected");
+
+        let error = anyhow!(
+            "Unknown position `touch {}` $(touch {}) 'quoted' \"double\"",
+            injected.display(),
+            injected.display()
+        );
+        let context = HookContext::new(HookEvent::Error, Some("desk"))
+            .height(300)
+            .error(&error);
+        let hook = format!("{hook} > '{}'", output.display());
+        run_hook(&hook, &context).await.unwrap();
+
+        assert!(!injected.exists(), "The error ran a command");
+        let written = fs::read_to_string(&output).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        written.replace(&dir.display().to_string(), "DIR")
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn values_are_never_run() {
+        let error =
+            r#"Unknown position `touch DIR/injected` $(touch DIR/injected) 'quoted' "double""#;
+
+        assert_eq!(
+            run_with_error("double", r#"printf '%s' "uplift: {error}""#).await,
+            format!("uplift: {error}")
+        );
+        assert_eq!(
+            run_with_error("single", "printf '%s' 'uplift: {error} at {height_in}in'").await,
+            format!("uplift: {error} at 30in")
+        );
+        assert_eq!(
+            run_with_error("bare", "printf '%s|' {desk_id} {error}").await,
+            format!("desk|{error}|")
+        );
+    }
+
+    #[test]
+    fn announces_heights_in_our_units() {
+        let stand = HookContext::new(HookEvent::Stand, None).height(405);
+        let inches = HeightFormat::default();
+        let centimeters = HeightFormat {
+            unit: HeightUnit::Cm,
+            decimal_separator: ',',
+            step: 1,
+        };
+
+        assert_eq!(stand.announcement(inches), "Desk standing at 40.5 inches");
+        assert_eq!(
+            stand.announcement(centimeters),
+            "Desk standing at 102,9 centimeters"
+        );
+        assert_eq!(
+            HookContext::new(HookEvent::Error, None).announcement(centimeters),
+            "Desk command failed"
+        );
+    }
+}
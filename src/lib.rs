@@ -0,0 +1,18 @@
+//! Everything needed to find and control an Uplift desk, the cli is a thin wrapper around this
+
+pub mod calendar;
+pub mod desk;
+#[cfg(feature = "schedule")]
+pub mod focus;
+pub mod output;
+pub mod peripheral;
+#[cfg(feature = "streaming")]
+pub mod posture;
+pub mod protocol;
+pub mod quiet_hours;
+pub mod redact;
+#[cfg(feature = "schedule")]
+pub mod schedule;
+pub mod storage;
+pub mod timings;
+pub mod wsl;
@@ -1,17 +1,27 @@
 use std::convert::identity;
-use std::future::Future;
-use std::time::Duration;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process;
+use std::time::Instant;
 
-use anyhow::{anyhow, Context};
-use clap::{Parser, Subcommand};
-use tokio::time;
+use anyhow::Context;
+use clap::{CommandFactory, FromArgMatches, Parser};
 use tokio::time::timeout;
 
-use crate::desk::{Desk, AVG_MID_HEIGHT, AVG_SITTING_HEIGHT, AVG_STANDING_HEIGHT};
-
-mod desk;
-
-const FORCE_ATTEMPTS: usize = 5;
+use crate::commands::{Commands, HeightDecoder, Session};
+use crate::config::Config;
+use crate::hooks::{HookContext, HookEvent};
+use crate::units::Seconds;
+use uplift_lib::output::{self, Verbosity};
+use uplift_lib::{desk, redact, timings, wsl};
+
+mod commands;
+mod config;
+mod errors;
+mod hooks;
+mod print;
+mod run_id;
+mod units;
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
@@ -19,9 +29,49 @@ const FORCE_ATTEMPTS: usize = 5;
 struct Args {
     #[clap(subcommand)]
     command: Commands,
-    /// Set the timeout in seconds, 0 for infinite
-    #[clap(long, default_value_t = 60)]
-    timeout: u64,
+    /// Use this config file instead of `<config dir>/uplift/config.toml`
+    #[clap(long, env = config::CONFIG_ENV)]
+    config: Option<PathBuf>,
+    /// Only connect to the desk with this address or name
+    #[clap(long, env = "UPLIFT_DESK")]
+    desk: Option<String>,
+    /// How to decode the height the desk reports, try `low-byte` if heights jump around. This is
+    /// remembered for each desk
+    #[clap(long, value_enum, env = "UPLIFT_HEIGHT_DECODER")]
+    height_decoder: Option<HeightDecoder>,
+    /// Set the timeout, ie: `90s` or `2m`, plain numbers are seconds and 0 is infinite. Defaults to
+    /// the command's timeout in the config, then `UPLIFT_TIMEOUT` or 60s
+    #[clap(long)]
+    timeout: Option<Seconds>,
+    /// Only print final results, useful for scripts
+    #[clap(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Print more progress, use `-vv` to also print Bluetooth events
+    #[clap(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+    /// Print how long each phase (scan, connect, discovery, ...) took
+    #[clap(long)]
+    timings: bool,
+    /// Print failures as a JSON object on stderr
+    #[clap(long)]
+    json_errors: bool,
+    /// Move the desk even during the quiet hours from the config
+    #[clap(long)]
+    override_quiet_hours: bool,
+    /// Wait for sit, stand and toggle to stop moving and fail if they stop short, defaults to `wait`
+    /// in the config
+    #[clap(long, overrides_with = "no_wait")]
+    wait: bool,
+    /// Return as soon as the desk hears sit, stand or toggle
+    #[clap(long, overrides_with = "wait")]
+    no_wait: bool,
+    /// Replace desk addresses, ids and names with pseudonyms in our logs, progress, errors and
+    /// `desks` output so they can be shared
+    #[clap(long, env = "UPLIFT_REDACT")]
+    redact: bool,
+    /// Speak when the desk sits, stands, reaches a target or fails
+    #[clap(long)]
+    announce: bool,
     /// Set the environment log level
     #[clap(long, env = env_logger::DEFAULT_FILTER_ENV, default_value_t = String::from("info"))]
     log_level: String,
@@ -30,54 +80,76 @@ struct Args {
     log_style: Option<String>,
 }
 
-#[derive(Subcommand, Debug)]
-enum Commands {
-    /// Sit or use `save` to store the current height
-    Sit {
-        #[clap(subcommand)]
-        save: Option<SaveCommand>,
-    },
-    /// Retry the Sit operation 5 times if the desk doesn't complete it
-    ForceSit,
-    /// Stand or use `save` to store the current height
-    Stand {
-        #[clap(subcommand)]
-        save: Option<SaveCommand>,
-    },
-    /// Retry the Stand operation 5 times if the desk doesn't complete it
-    ForceStand,
-    /// Get the estimated desk height in inches
-    Query,
-    /// Sit -> Stand or Stand -> Sit
-    Toggle,
-    /// Retry the Toggle operation 5 times if the desk doesn't complete it
-    ForceToggle,
-    /// Listen for height changes
-    Listen,
-}
-
-#[derive(Subcommand, Debug)]
-enum SaveCommand {
-    Save,
-}
-
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
-    let args = Args::parse();
+    let matches = Args::command().get_matches();
+    let args = Args::from_arg_matches(&matches)?;
 
+    redact::set_enabled(args.redact);
+    output::set_verbosity(Verbosity::from_flags(args.quiet, args.verbose));
     setup_logging(&args)?;
+    log::debug!("Run id {}", run_id::get());
+
+    if let Some(status) = wsl::forward_to_windows().await? {
+        process::exit(status.code().unwrap_or(1));
+    }
 
-    let runner = run_command(&args);
-    if args.timeout > 0 {
-        timeout(Duration::from_secs(args.timeout), runner)
+    hooks::set_announce(args.announce);
+    if let Some(path) = &args.config {
+        config::set_path(path.clone());
+    }
+    // `config` commands read the file themselves and report what's wrong with it
+    let config = match args.command {
+        Commands::Config { .. } => Config::default(),
+        _ => Config::load()?,
+    };
+    output::set_height_format(config.height_format());
+    desk::set_connect_retry(config.connect_retry());
+    desk::set_scan_retry(config.scan_retry());
+
+    let command_timeout = config.command_timeout(matches.subcommand_name(), args.timeout)?;
+
+    let start = Instant::now();
+    let height_range = config.height_range();
+    let session = Session::new(
+        config.desk_filter(args.command.desk().or_else(|| args.desk.clone())),
+        args.height_decoder,
+        config.adapters,
+        config.write_spacing.map(|spacing| spacing.0),
+        height_range,
+        args.override_quiet_hours,
+        (config.wait || args.wait) && !args.no_wait,
+    );
+    let runner = args.command.run(&session);
+    let result = if !command_timeout.is_zero() && !args.command.ignores_timeout() {
+        timeout(command_timeout, runner)
             .await
             .context("Operation timed out")
-            .and_then(identity)?
+            .and_then(identity)
     } else {
-        runner.await?
+        runner.await
+    };
+
+    if args.timings {
+        timings::print_summary(start.elapsed());
+    }
+
+    if let Err(e) = &result {
+        hooks::fire(HookContext::new(HookEvent::Error, session.desk_id()).error(e)).await;
     }
 
-    Ok(())
+    match result {
+        Err(e) if args.json_errors => {
+            let json = errors::to_json(&e, session.desk_id()).to_string();
+            eprintln!("{}", redact::scrub(json));
+            process::exit(1);
+        }
+        Err(e) if args.redact => {
+            eprintln!("Error: {}", redact::redact(&format!("{e:?}")));
+            process::exit(1);
+        }
+        result => result,
+    }
 }
 
 fn setup_logging(args: &Args) -> Result<(), anyhow::Error> {
@@ -87,132 +159,80 @@ fn setup_logging(args: &Args) -> Result<(), anyhow::Error> {
     if let Some(s) = &args.log_style {
         builder.parse_write_style(s);
     }
+    if args.redact {
+        builder.format(|buf, record| {
+            writeln!(
+                buf,
+                "[{} {:<5} {}] {}",
+                buf.timestamp(),
+                record.level(),
+                record.target(),
+                redact::redact(&record.args().to_string())
+            )
+        });
+    }
 
     builder.try_init().context("Failed to setup logger")
 }
 
-async fn run_command(args: &Args) -> Result<(), anyhow::Error> {
-    let desk = Desk::new().await?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    match &args.command {
-        Commands::Sit { save } => {
-            if save.is_some() {
-                desk.save_sit().await?;
-            } else {
-                desk.sit().await?;
-            }
+    fn has_command(name: &str) -> bool {
+        Args::command().find_subcommand(name).is_some()
+    }
 
-            // let the packet actually send
-            desk.query_height().await?;
-        }
-        Commands::ForceSit => {
-            force_sit(&desk).await?;
-        }
-        Commands::Stand { save } => {
-            if save.is_some() {
-                desk.save_stand().await?;
-            } else {
-                desk.stand().await?;
-            }
-
-            // let the packet actually send
-            desk.query_height().await?;
-        }
-        Commands::ForceStand => {
-            force_stand(&desk).await?;
-        }
-        Commands::Query => {
-            println!("{}", desk.query_height().await? as f32 / 10.0);
-        }
-        Commands::Toggle => {
-            let height = desk.query_height().await?;
-            if height > AVG_MID_HEIGHT {
-                desk.sit().await?;
-            } else {
-                desk.stand().await?;
-            }
-
-            // let the packet actually send
-            desk.query_height().await?;
-        }
-        Commands::ForceToggle => {
-            let height = desk.query_height().await?;
-            if height > AVG_MID_HEIGHT {
-                force_sit(&desk).await?;
-            } else {
-                force_stand(&desk).await?;
-            }
-        }
-        Commands::Listen => {
-            let mut height = 0;
-            loop {
-                let next_height = desk.height();
-                if height != next_height {
-                    let (low, high) = desk.raw_height();
-                    println!("height: ({low:x},{high:x}) -> {next_height}");
-                }
-                height = next_height;
-
-                time::sleep(Duration::from_millis(100)).await;
-            }
-        }
+    fn listen_has(arg: &str) -> bool {
+        Args::command()
+            .find_subcommand("listen")
+            .expect("Listen is always there")
+            .get_arguments()
+            .any(|argument| argument.get_id() == arg)
     }
 
-    Ok(())
-}
+    #[cfg(feature = "schedule")]
+    #[test]
+    fn schedule_feature_adds_schedule() {
+        assert!(has_command("schedule"));
+        let _: fn() -> Result<bool, anyhow::Error> = uplift_lib::focus::is_focus_active;
+        let _ = uplift_lib::schedule::SystemClock;
+    }
 
-async fn force_sit(desk: &Desk) -> Result<(), anyhow::Error> {
-    force(
-        || async { desk.sit().await },
-        |height| height < (AVG_MID_HEIGHT + AVG_SITTING_HEIGHT) / 2,
-        desk,
-    )
-    .await
-}
+    #[cfg(not(feature = "schedule"))]
+    #[test]
+    fn no_schedule_without_its_feature() {
+        assert!(!has_command("schedule"));
+    }
 
-async fn force_stand(desk: &Desk) -> Result<(), anyhow::Error> {
-    force(
-        || async { desk.stand().await },
-        |height| height > (AVG_MID_HEIGHT + AVG_STANDING_HEIGHT) / 2,
-        desk,
-    )
-    .await
-}
+    #[cfg(feature = "url-handler")]
+    #[test]
+    fn url_handler_feature_adds_handle_url() {
+        assert!(has_command("handle-url"));
+    }
 
-async fn force<AFut>(
-    mut action: impl FnMut() -> AFut,
-    mut done: impl FnMut(isize) -> bool,
-    desk: &Desk,
-) -> Result<(), anyhow::Error>
-where
-    AFut: Future<Output = Result<(), anyhow::Error>>,
-{
-    let mut attempts = 0;
-    let mut previous_height = desk.query_height().await?;
-
-    while attempts < FORCE_ATTEMPTS {
-        attempts += 1;
-        log::trace!("Running forced attempt {attempts}");
-        action().await?;
-
-        'query_height: loop {
-            time::sleep(Duration::from_millis(1000)).await;
-            let next_height = desk.height();
-            log::trace!("Height moved from: {previous_height} -> {next_height}");
-
-            // we've stopped moving so check our height
-            if previous_height == next_height {
-                if done(next_height) {
-                    return Ok(());
-                } else {
-                    break 'query_height;
-                }
-            }
-            previous_height = next_height;
-        }
+    #[cfg(not(feature = "url-handler"))]
+    #[test]
+    fn no_handle_url_without_its_feature() {
+        assert!(!has_command("handle-url"));
+    }
+
+    #[cfg(feature = "streaming")]
+    #[test]
+    fn streaming_feature_adds_streams() {
+        assert!(has_command("stream"));
+        assert!(has_command("alert"));
+        assert!(listen_has("posture"));
+        let _ = desk::Desk::subscribe_height;
+        let _ = desk::Desk::subscribe_posture;
+        let _ = desk::Desk::subscribe_connection;
     }
 
-    Err(anyhow!(
-        "Failed to force the desk to the intended height after {attempts} attempts"
-    ))
+    #[cfg(not(feature = "streaming"))]
+    #[test]
+    fn no_streams_without_their_feature() {
+        assert!(!has_command("stream"));
+        assert!(!has_command("alert"));
+        assert!(!listen_has("posture"));
+    }
 }
@@ -0,0 +1,273 @@
+use std::env;
+use std::io::{self, IsTerminal};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::RwLock;
+
+use serde::{Deserialize, Serialize};
+
+/// A height for other programs, every JSON output reports heights with these fields
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Height {
+    pub height_in: f64,
+    pub height_cm: f64,
+}
+
+impl Height {
+    pub fn from_tenths(tenths: isize) -> Height {
+        Height {
+            height_in: tenths as f64 / 10.0,
+            height_cm: (tenths as f64 * 2.54).round() / 10.0,
+        }
+    }
+}
+
+/// How much human facing output we print. This is separate from `--log-level` which only controls
+/// the `log` crate output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum Verbosity {
+    /// Only print final results
+    Quiet = 0,
+    /// Print results and short progress lines
+    Normal = 1,
+    /// Print every step we take
+    Verbose = 2,
+    /// Print every step and every Bluetooth event we see
+    Chatty = 3,
+}
+
+// programs using us as a library don't want our progress on their stderr unless they ask for it
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Quiet as u8);
+
+impl Verbosity {
+    pub fn from_flags(quiet: bool, verbose: u8) -> Verbosity {
+        if quiet {
+            Verbosity::Quiet
+        } else {
+            match verbose {
+                0 => Verbosity::Normal,
+                1 => Verbosity::Verbose,
+                _ => Verbosity::Chatty,
+            }
+        }
+    }
+}
+
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+pub fn enabled(verbosity: Verbosity) -> bool {
+    VERBOSITY.load(Ordering::Relaxed) >= verbosity as u8
+}
+
+/// The unit we display heights in
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HeightUnit {
+    /// Plain numbers in inches, ie: `30.5`
+    In,
+    /// Centimeters with a suffix, ie: `77.5cm`
+    Cm,
+}
+
+/// How we display heights
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HeightFormat {
+    pub unit: HeightUnit,
+    pub decimal_separator: char,
+    /// Round to a multiple of this many tenths of our unit, ie: `5` rounds to the nearest 0.5
+    pub step: isize,
+}
+
+// regions that still measure in inches
+const IMPERIAL_REGIONS: [&str; 3] = ["US", "LR", "MM"];
+// languages that write 30,5 instead of 30.5
+const DECIMAL_COMMA_LANGUAGES: [&str; 24] = [
+    "bg", "ca", "cs", "da", "de", "el", "es", "et", "fi", "fr", "hr", "hu", "id", "it", "lt", "lv",
+    "nb", "nl", "pl", "pt", "ro", "ru", "sv", "tr",
+];
+
+impl Default for HeightFormat {
+    fn default() -> Self {
+        HeightFormat {
+            unit: HeightUnit::In,
+            decimal_separator: '.',
+            step: 1,
+        }
+    }
+}
+
+impl HeightFormat {
+    /// Pick a format from the `LC_*` / `LANG` environment, falling back to inches
+    pub fn from_locale() -> HeightFormat {
+        let unit = match locale("LC_MEASUREMENT").and_then(|(_, region)| region) {
+            Some(region) if !IMPERIAL_REGIONS.contains(&region.as_str()) => HeightUnit::Cm,
+            _ => HeightUnit::In,
+        };
+        let decimal_separator = match locale("LC_NUMERIC") {
+            Some((language, _)) if DECIMAL_COMMA_LANGUAGES.contains(&language.as_str()) => ',',
+            _ => '.',
+        };
+
+        HeightFormat {
+            unit,
+            decimal_separator,
+            ..HeightFormat::default()
+        }
+    }
+
+    /// Format a height in tenths of an inch, rounded to our `step` in our unit
+    pub fn format(&self, tenths: isize) -> String {
+        let suffix = match self.unit {
+            HeightUnit::In => "",
+            HeightUnit::Cm => "cm",
+        };
+
+        format!("{}{suffix}", self.number(tenths))
+    }
+
+    /// [HeightFormat::format] without the unit
+    pub fn number(&self, tenths: isize) -> String {
+        let tenths_of_unit = match self.unit {
+            HeightUnit::In => tenths as f64,
+            // 1 tenth of an inch = 0.254cm = 2.54 tenths of a cm
+            HeightUnit::Cm => tenths as f64 * 2.54,
+        };
+        let step = self.step.max(1);
+        let tenths_of_unit = (tenths_of_unit / step as f64).round() as isize * step;
+
+        let whole = tenths_of_unit / 10;
+        let fraction = (tenths_of_unit % 10).abs();
+        if fraction == 0 {
+            whole.to_string()
+        } else {
+            format!("{whole}{}{fraction}", self.decimal_separator)
+        }
+    }
+}
+
+/// The language and region of a locale like `de_DE.UTF-8`, `LC_ALL` overrides `category` which
+/// overrides `LANG`
+fn locale(category: &str) -> Option<(String, Option<String>)> {
+    let locale = ["LC_ALL", category, "LANG"]
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|value| !value.is_empty())?;
+    if locale == "C" || locale == "POSIX" {
+        return None;
+    }
+
+    let locale = locale.split(['.', '@']).next().unwrap_or_default();
+    let mut parts = locale.split(['_', '-']);
+    let language = parts.next()?.to_lowercase();
+    let region = parts.next().map(str::to_uppercase);
+
+    Some((language, region))
+}
+
+// how many characters wide our progress bars are
+const PROGRESS_BAR_WIDTH: usize = 30;
+
+/// A single line progress bar on stderr. It's only drawn on a terminal at normal verbosity, otherwise
+/// our usual progress lines are all we print.
+pub struct ProgressBar {
+    label: &'static str,
+    visible: bool,
+}
+
+impl ProgressBar {
+    pub fn new(label: &'static str) -> ProgressBar {
+        ProgressBar {
+            label,
+            visible: enabled(Verbosity::Normal)
+                && !enabled(Verbosity::Verbose)
+                && io::stderr().is_terminal(),
+        }
+    }
+
+    /// Redraw the bar `fraction` of the way full, or without a bar if we don't know how far along
+    /// we are
+    pub fn update(&self, fraction: Option<f64>, message: &str) {
+        if !self.visible {
+            return;
+        }
+
+        match fraction {
+            Some(fraction) => {
+                let filled =
+                    (fraction.clamp(0.0, 1.0) * PROGRESS_BAR_WIDTH as f64).round() as usize;
+                eprint!(
+                    "\r{} [{}{}] {message}\x1b[K",
+                    self.label,
+                    "#".repeat(filled),
+                    " ".repeat(PROGRESS_BAR_WIDTH - filled)
+                );
+            }
+            None => eprint!("\r{} {message}\x1b[K", self.label),
+        }
+    }
+}
+
+impl Drop for ProgressBar {
+    /// Clear our line so the next progress line starts fresh
+    fn drop(&mut self) {
+        if self.visible {
+            eprint!("\r\x1b[K");
+        }
+    }
+}
+
+static HEIGHT_FORMAT: RwLock<Option<HeightFormat>> = RwLock::new(None);
+
+pub fn set_height_format(format: HeightFormat) {
+    if let Ok(mut height_format) = HEIGHT_FORMAT.write() {
+        *height_format = Some(format);
+    }
+}
+
+/// The format from [set_height_format], or inches
+pub fn height_format() -> HeightFormat {
+    HEIGHT_FORMAT
+        .read()
+        .ok()
+        .and_then(|format| *format)
+        .unwrap_or_default()
+}
+
+/// Format a height in tenths of an inch for people to read
+pub fn height(tenths: isize) -> String {
+    height_format().format(tenths)
+}
+
+/// Short progress lines, hidden by `--quiet`. Only the schedule prints these.
+#[cfg(feature = "schedule")]
+macro_rules! progress {
+    ($($arg:tt)*) => {
+        if $crate::output::enabled($crate::output::Verbosity::Normal) {
+            eprintln!("{}", $crate::redact::scrub(format!($($arg)*)));
+        }
+    };
+}
+
+/// Detailed progress lines, shown with `-v`
+macro_rules! verbose {
+    ($($arg:tt)*) => {
+        if $crate::output::enabled($crate::output::Verbosity::Verbose) {
+            eprintln!("{}", $crate::redact::scrub(format!($($arg)*)));
+        }
+    };
+}
+
+/// Bluetooth event chatter, shown with `-vv`
+macro_rules! chatter {
+    ($($arg:tt)*) => {
+        if $crate::output::enabled($crate::output::Verbosity::Chatty) {
+            eprintln!("{}", $crate::redact::scrub(format!($($arg)*)));
+        }
+    };
+}
+
+#[cfg(feature = "schedule")]
+pub(crate) use progress;
+pub(crate) use {chatter, verbose};
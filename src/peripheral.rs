@@ -0,0 +1,74 @@
+//! Desk control for apps that already manage their own btleplug [Peripheral], without the [Desk]
+//! connection lifecycle
+//!
+//! [Desk]: crate::desk::Desk
+
+use std::future::Future;
+
+use btleplug::api::{Characteristic, Peripheral as _, WriteType};
+use btleplug::platform::Peripheral;
+
+use crate::desk;
+use crate::protocol::{self, DESK_SERVICE_UUID};
+
+/// The characteristics we use to control a desk
+#[derive(Clone, Debug)]
+pub struct UpliftCharacteristics {
+    /// Commands are written here
+    pub data_in: Characteristic,
+    /// Height notifications come from here
+    pub data_out: Characteristic,
+    /// The desk's name
+    pub name: Characteristic,
+}
+
+pub trait UpliftPeripheralExt {
+    /// Check if this peripheral advertises or has discovered the desk service
+    fn is_uplift_desk(&self) -> impl Future<Output = Result<bool, anyhow::Error>> + Send;
+
+    /// Find the desk characteristics, services must already be discovered
+    fn uplift_characteristics(&self) -> Result<UpliftCharacteristics, anyhow::Error>;
+
+    /// Send one of the [protocol] commands like [protocol::STAND]
+    fn send_uplift_command(
+        &self,
+        command: u8,
+    ) -> impl Future<Output = Result<(), anyhow::Error>> + Send;
+}
+
+impl UpliftPeripheralExt for Peripheral {
+    async fn is_uplift_desk(&self) -> Result<bool, anyhow::Error> {
+        let advertised = self
+            .properties()
+            .await?
+            .is_some_and(|properties| properties.services.contains(&DESK_SERVICE_UUID));
+
+        Ok(advertised
+            || self
+                .services()
+                .iter()
+                .any(|service| service.uuid == DESK_SERVICE_UUID))
+    }
+
+    fn uplift_characteristics(&self) -> Result<UpliftCharacteristics, anyhow::Error> {
+        let (data_in, data_out, name) = desk::get_characteristics(self.characteristics())?;
+
+        Ok(UpliftCharacteristics {
+            data_in,
+            data_out,
+            name,
+        })
+    }
+
+    async fn send_uplift_command(&self, command: u8) -> Result<(), anyhow::Error> {
+        let characteristics = self.uplift_characteristics()?;
+        self.write(
+            &characteristics.data_in,
+            &protocol::command_packet(command),
+            WriteType::WithoutResponse,
+        )
+        .await?;
+
+        Ok(())
+    }
+}
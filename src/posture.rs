@@ -0,0 +1,153 @@
+use std::fmt;
+use std::sync::atomic::{AtomicIsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::sync::watch;
+use tokio::time;
+
+use crate::desk::AVG_MID_HEIGHT;
+
+// how far past the midpoint we need to be before we switch between sitting and standing, 2.0"
+const HYSTERESIS: isize = 20;
+// if the height changed within this window we consider the desk to be moving
+const MOVEMENT_WINDOW: Duration = Duration::from_millis(500);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Posture {
+    Sitting,
+    Standing,
+    Moving,
+    Unknown,
+}
+
+impl Posture {
+    /// Classify a resting height, heights close to the midpoint keep the `previous` posture so we
+    /// don't flap between sitting and standing
+    fn classify(height: isize, previous: Posture) -> Posture {
+        if height < AVG_MID_HEIGHT - HYSTERESIS {
+            Posture::Sitting
+        } else if height > AVG_MID_HEIGHT + HYSTERESIS {
+            Posture::Standing
+        } else {
+            match previous {
+                Posture::Sitting | Posture::Standing => previous,
+                Posture::Moving | Posture::Unknown => {
+                    if height < AVG_MID_HEIGHT {
+                        Posture::Sitting
+                    } else {
+                        Posture::Standing
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Posture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Posture::Sitting => "sitting",
+            Posture::Standing => "standing",
+            Posture::Moving => "moving",
+            Posture::Unknown => "unknown",
+        };
+
+        f.write_str(name)
+    }
+}
+
+/// Watch our height and track our posture from it. The tracker stops once every receiver is dropped
+pub fn track(height: Arc<AtomicIsize>) -> watch::Receiver<Posture> {
+    let (sender, receiver) = watch::channel(Posture::Unknown);
+
+    tokio::spawn(async move {
+        // the last real height we saw and the posture we'll settle on once we stop moving
+        let mut last_height = -1;
+        let mut resting = Posture::Unknown;
+
+        while !sender.is_closed() {
+            time::sleep(MOVEMENT_WINDOW).await;
+
+            // we don't have a real height until the desk's first notification
+            let next_height = height.load(Ordering::Relaxed);
+            if next_height <= 0 {
+                continue;
+            }
+
+            let posture = if last_height > 0 && last_height != next_height {
+                Posture::Moving
+            } else {
+                resting = Posture::classify(next_height, resting);
+                resting
+            };
+            last_height = next_height;
+
+            sender.send_if_modified(|current| {
+                let modified = *current != posture;
+                *current = posture;
+                modified
+            });
+        }
+    });
+
+    receiver
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_heights_clear_of_the_midpoint() {
+        for previous in [
+            Posture::Sitting,
+            Posture::Standing,
+            Posture::Moving,
+            Posture::Unknown,
+        ] {
+            assert_eq!(
+                Posture::classify(AVG_MID_HEIGHT - HYSTERESIS - 1, previous),
+                Posture::Sitting
+            );
+            assert_eq!(
+                Posture::classify(AVG_MID_HEIGHT + HYSTERESIS + 1, previous),
+                Posture::Standing
+            );
+        }
+    }
+
+    #[test]
+    fn keeps_our_posture_near_the_midpoint() {
+        for height in [
+            AVG_MID_HEIGHT - HYSTERESIS,
+            AVG_MID_HEIGHT,
+            AVG_MID_HEIGHT + HYSTERESIS,
+        ] {
+            assert_eq!(
+                Posture::classify(height, Posture::Sitting),
+                Posture::Sitting
+            );
+            assert_eq!(
+                Posture::classify(height, Posture::Standing),
+                Posture::Standing
+            );
+        }
+    }
+
+    #[test]
+    fn splits_at_the_midpoint_without_a_resting_posture() {
+        for previous in [Posture::Moving, Posture::Unknown] {
+            assert_eq!(
+                Posture::classify(AVG_MID_HEIGHT - 1, previous),
+                Posture::Sitting
+            );
+            assert_eq!(
+                Posture::classify(AVG_MID_HEIGHT, previous),
+                Posture::Standing
+            );
+        }
+    }
+}
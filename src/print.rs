@@ -0,0 +1,22 @@
+//! Human facing progress lines on stderr, hidden by `--quiet` and expanded by `-v`. The library
+//! stays quiet unless we raise its verbosity, these are our own.
+
+/// Short progress lines, hidden by `--quiet`
+macro_rules! progress {
+    ($($arg:tt)*) => {
+        if uplift_lib::output::enabled(uplift_lib::output::Verbosity::Normal) {
+            eprintln!("{}", uplift_lib::redact::scrub(format!($($arg)*)));
+        }
+    };
+}
+
+/// Detailed progress lines, shown with `-v`
+macro_rules! verbose {
+    ($($arg:tt)*) => {
+        if uplift_lib::output::enabled(uplift_lib::output::Verbosity::Verbose) {
+            eprintln!("{}", uplift_lib::redact::scrub(format!($($arg)*)));
+        }
+    };
+}
+
+pub(crate) use {progress, verbose};
@@ -0,0 +1,294 @@
+//! Encoding of the packets we write to the desk and decoding of the notifications it sends back.
+//! This doesn't depend on a Bluetooth stack so it can be reused by bridges and other controllers.
+//!
+//! ```
+//! use uplift_lib::protocol::{self, STAND};
+//!
+//! let packet = protocol::command_packet(STAND);
+//! let frame = protocol::parse_frame(&packet).unwrap();
+//! assert_eq!(frame.command, STAND);
+//! assert!(frame.has_valid_checksum());
+//! ```
+
+use uuid::Uuid;
+
+/// The GATT service every desk advertises
+pub const DESK_SERVICE_UUID: Uuid = uuid_from_u16(0xff12);
+/// Commands are written to this characteristic
+pub const DESK_DATA_IN_UUID: Uuid = uuid_from_u16(0xff01);
+/// Height notifications come from this characteristic
+pub const DESK_DATA_OUT_UUID: Uuid = uuid_from_u16(0xff02);
+/// The desk's name can be read from this characteristic
+pub const DESK_NAME_UUID: Uuid = uuid_from_u16(0xff06);
+
+// the Bluetooth base uuid that 16 bit uuids are shorthand for
+const BASE_UUID: u128 = 0x00000000_0000_1000_8000_00805f9b34fb;
+
+const fn uuid_from_u16(short: u16) -> Uuid {
+    Uuid::from_u128(BASE_UUID | ((short as u128) << 96))
+}
+
+/// The header of the packets we send, notifications from the desk use their own header
+pub const HEADER: [u8; 2] = [0xf1, 0xf1];
+pub const FOOTER: u8 = 0x7e;
+
+pub const UP: u8 = 0x01;
+pub const DOWN: u8 = 0x02;
+pub const SAVE_SIT: u8 = 0x03;
+pub const SAVE_STAND: u8 = 0x04;
+pub const SIT: u8 = 0x05;
+pub const STAND: u8 = 0x06;
+pub const QUERY: u8 = 0x07;
+/// Stop a sit or stand that's still moving
+pub const STOP: u8 = 0x2b;
+
+pub const UP_PACKET: [u8; 6] = command_packet(UP);
+pub const DOWN_PACKET: [u8; 6] = command_packet(DOWN);
+pub const SAVE_SIT_PACKET: [u8; 6] = command_packet(SAVE_SIT);
+pub const SAVE_STAND_PACKET: [u8; 6] = command_packet(SAVE_STAND);
+pub const SIT_PACKET: [u8; 6] = command_packet(SIT);
+pub const STAND_PACKET: [u8; 6] = command_packet(STAND);
+pub const STOP_PACKET: [u8; 6] = command_packet(STOP);
+pub const QUERY_PACKET: [u8; 6] = command_packet(QUERY);
+
+/// Build a packet for a command without a payload: header, command, payload length, checksum, footer
+///
+/// ```
+/// use uplift_lib::protocol::{self, QUERY};
+///
+/// assert_eq!(
+///     protocol::command_packet(QUERY),
+///     [0xf1, 0xf1, 0x07, 0x00, 0x07, 0x7e]
+/// );
+/// ```
+pub const fn command_packet(command: u8) -> [u8; 6] {
+    let length = 0x00;
+
+    [
+        HEADER[0],
+        HEADER[1],
+        command,
+        length,
+        checksum(&[command, length]),
+        FOOTER,
+    ]
+}
+
+/// The checksum is the sum of the command, length, and payload bytes, wrapping at 0xff
+pub const fn checksum(bytes: &[u8]) -> u8 {
+    let mut sum: u8 = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        sum = sum.wrapping_add(bytes[i]);
+        i += 1;
+    }
+
+    sum
+}
+
+/// A packet or notification split into its parts
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Frame<'a> {
+    pub header: [u8; 2],
+    pub command: u8,
+    pub payload: &'a [u8],
+    pub checksum: u8,
+}
+
+impl Frame<'_> {
+    /// Check our checksum against the command, length and payload
+    pub fn has_valid_checksum(&self) -> bool {
+        let sum = checksum(&[self.command, self.payload.len() as u8]);
+        sum.wrapping_add(checksum(self.payload)) == self.checksum
+    }
+}
+
+/// Split a frame into its parts: a two byte header, command, payload length, payload, checksum and
+/// footer. Returns `None` if the frame is truncated or doesn't end with our footer, the checksum
+/// isn't checked so see [Frame::has_valid_checksum].
+///
+/// ```
+/// use uplift_lib::protocol;
+///
+/// assert_eq!(protocol::parse_frame(&[0xf1, 0xf1, 0x07, 0x00]), None);
+/// ```
+pub fn parse_frame(frame: &[u8]) -> Option<Frame<'_>> {
+    let [header_0, header_1, command, length, rest @ ..] = frame else {
+        return None;
+    };
+    let length = *length as usize;
+    let (payload, rest) = rest.split_at_checked(length)?;
+    let [checksum, FOOTER] = rest else {
+        return None;
+    };
+
+    Some(Frame {
+        header: [*header_0, *header_1],
+        command: *command,
+        payload,
+        checksum: *checksum,
+    })
+}
+
+/// Pull the raw (low, high) height bytes out of a height notification, returns `None` if the frame
+/// is too short to contain them
+pub fn decode_height(frame: &[u8]) -> Option<(u8, u8)> {
+    match frame {
+        [_, _, _, _, _, low, _, high, ..] => Some((*low, *high)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn parse_frame_never_panics(bytes in prop::collection::vec(any::<u8>(), 0..64)) {
+            if let Some(frame) = parse_frame(&bytes) {
+                prop_assert_eq!(frame.payload.len(), bytes[3] as usize);
+                prop_assert_eq!(bytes.len(), frame.payload.len() + 6);
+            }
+        }
+
+        #[test]
+        fn decode_height_never_panics(bytes in prop::collection::vec(any::<u8>(), 0..64)) {
+            match decode_height(&bytes) {
+                Some(raw) => prop_assert_eq!(raw, (bytes[5], bytes[7])),
+                None => prop_assert!(bytes.len() < 8),
+            }
+        }
+
+        #[test]
+        fn command_packet_checksum_round_trips(command in any::<u8>()) {
+            let packet = command_packet(command);
+            let frame = parse_frame(&packet).expect("Our own packets should parse");
+
+            prop_assert_eq!(frame.header, HEADER);
+            prop_assert_eq!(frame.command, command);
+            prop_assert!(frame.payload.is_empty());
+            prop_assert!(frame.has_valid_checksum());
+        }
+
+        #[test]
+        fn checksum_rejects_corrupted_commands(command in any::<u8>(), corrupted in any::<u8>()) {
+            prop_assume!(command != corrupted);
+            let mut packet = command_packet(command);
+            packet[2] = corrupted;
+
+            let frame = parse_frame(&packet).expect("Only the command changed");
+            prop_assert!(!frame.has_valid_checksum());
+        }
+    }
+
+    #[test]
+    fn uuids_expand_from_16_bits() {
+        assert_eq!(
+            DESK_SERVICE_UUID.to_string(),
+            "0000ff12-0000-1000-8000-00805f9b34fb"
+        );
+        assert_eq!(
+            DESK_DATA_IN_UUID.to_string(),
+            "0000ff01-0000-1000-8000-00805f9b34fb"
+        );
+        assert_eq!(
+            DESK_DATA_OUT_UUID.to_string(),
+            "0000ff02-0000-1000-8000-00805f9b34fb"
+        );
+        assert_eq!(
+            DESK_NAME_UUID.to_string(),
+            "0000ff06-0000-1000-8000-00805f9b34fb"
+        );
+    }
+
+    #[test]
+    fn command_packets() {
+        for (packet, expected) in [
+            (UP_PACKET, [0xf1, 0xf1, 0x01, 0x00, 0x01, 0x7e]),
+            (DOWN_PACKET, [0xf1, 0xf1, 0x02, 0x00, 0x02, 0x7e]),
+            (SAVE_SIT_PACKET, [0xf1, 0xf1, 0x03, 0x00, 0x03, 0x7e]),
+            (SAVE_STAND_PACKET, [0xf1, 0xf1, 0x04, 0x00, 0x04, 0x7e]),
+            (SIT_PACKET, [0xf1, 0xf1, 0x05, 0x00, 0x05, 0x7e]),
+            (STAND_PACKET, [0xf1, 0xf1, 0x06, 0x00, 0x06, 0x7e]),
+            (QUERY_PACKET, [0xf1, 0xf1, 0x07, 0x00, 0x07, 0x7e]),
+            (STOP_PACKET, [0xf1, 0xf1, 0x2b, 0x00, 0x2b, 0x7e]),
+        ] {
+            assert_eq!(packet, expected);
+        }
+    }
+
+    #[test]
+    fn checksum_wraps() {
+        assert_eq!(checksum(&[]), 0x00);
+        assert_eq!(checksum(&[0x07, 0x00]), 0x07);
+        assert_eq!(checksum(&[0x01, 0x03, 0x01, 0x8a, 0x07]), 0x96);
+        assert_eq!(checksum(&[0xff, 0x02]), 0x01);
+    }
+
+    #[test]
+    fn parses_frames_with_payloads() {
+        let frame = parse_frame(&[0xf2, 0xf2, 0x01, 0x03, 0x01, 0x8a, 0x07, 0x96, 0x7e])
+            .expect("A complete frame");
+
+        assert_eq!(
+            frame,
+            Frame {
+                header: [0xf2, 0xf2],
+                command: 0x01,
+                payload: &[0x01, 0x8a, 0x07],
+                checksum: 0x96,
+            }
+        );
+        assert!(frame.has_valid_checksum());
+    }
+
+    #[test]
+    fn rejects_truncated_frames() {
+        let frame = [0xf2, 0xf2, 0x01, 0x03, 0x01, 0x8a, 0x07, 0x96, 0x7e];
+        for length in 0..frame.len() {
+            assert_eq!(parse_frame(&frame[..length]), None, "{length} bytes");
+        }
+    }
+
+    #[test]
+    fn rejects_frames_without_a_footer() {
+        assert_eq!(parse_frame(&[0xf1, 0xf1, 0x07, 0x00, 0x07, 0x00]), None);
+        // a length longer than the frame
+        assert_eq!(parse_frame(&[0xf1, 0xf1, 0x07, 0x04, 0x07, 0x7e]), None);
+        // trailing bytes after the footer
+        assert_eq!(
+            parse_frame(&[0xf1, 0xf1, 0x07, 0x00, 0x07, 0x7e, 0x7e]),
+            None
+        );
+    }
+
+    #[test]
+    fn detects_bad_checksums() {
+        let frame = parse_frame(&[0xf1, 0xf1, 0x07, 0x00, 0x08, 0x7e]).expect("Well formed");
+        assert!(!frame.has_valid_checksum());
+
+        let frame = parse_frame(&[0xf2, 0xf2, 0x01, 0x03, 0x01, 0x8b, 0x07, 0x96, 0x7e])
+            .expect("Well formed");
+        assert!(!frame.has_valid_checksum());
+    }
+
+    #[test]
+    fn decodes_heights() {
+        assert_eq!(
+            decode_height(&[0xf2, 0xf2, 0x01, 0x03, 0x01, 0x8a, 0x07, 0x96, 0x7e]),
+            Some((0x8a, 0x96))
+        );
+        assert_eq!(
+            decode_height(&[0xf2, 0xf2, 0x01, 0x03, 0x01, 0x8a, 0x07, 0x96]),
+            Some((0x8a, 0x96))
+        );
+        assert_eq!(
+            decode_height(&[0xf2, 0xf2, 0x01, 0x03, 0x01, 0x8a, 0x07]),
+            None
+        );
+        assert_eq!(decode_height(&[]), None);
+    }
+}
@@ -0,0 +1,78 @@
+//! Times of day when the desk shouldn't move on its own, ie: while recording
+
+use std::fmt;
+use std::time::Duration;
+
+use jiff::Zoned;
+use serde::{Deserialize, Serialize};
+
+use crate::calendar::TimeWindow;
+
+/// What automated moves do during quiet hours
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuietMode {
+    /// Wait until quiet hours end and then move
+    #[default]
+    Defer,
+    /// Skip the move
+    Refuse,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuietHours {
+    /// Local times like `12:00-13:00`, windows that end before they start run overnight
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub windows: Vec<TimeWindow>,
+    pub mode: QuietMode,
+}
+
+impl QuietHours {
+    pub fn is_empty(&self) -> bool {
+        self.windows.is_empty()
+    }
+
+    /// The window we're in right now and how long until it ends
+    pub fn active(&self) -> Option<(&TimeWindow, Duration)> {
+        self.active_at(&Zoned::now())
+    }
+
+    /// The window we're in at `now` and how long until it ends
+    pub fn active_at(&self, now: &Zoned) -> Option<(&TimeWindow, Duration)> {
+        self.windows
+            .iter()
+            .find_map(|window| window.remaining(now).map(|remaining| (window, remaining)))
+    }
+
+    /// Fail with a [QuietHoursError] if we're in quiet hours right now
+    pub fn check(&self) -> Result<(), QuietHoursError> {
+        match self.active() {
+            Some((window, remaining)) => Err(QuietHoursError {
+                window: *window,
+                remaining,
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// We were asked to move the desk during quiet hours
+#[derive(Debug)]
+pub struct QuietHoursError {
+    pub window: TimeWindow,
+    pub remaining: Duration,
+}
+
+impl fmt::Display for QuietHoursError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "It's quiet hours ({}) for another {}, use --override-quiet-hours to move anyway",
+            self.window,
+            humantime::format_duration(self.remaining)
+        )
+    }
+}
+
+impl std::error::Error for QuietHoursError {}
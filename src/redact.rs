@@ -0,0 +1,278 @@
+//! Pseudonyms for desk addresses, ids and names so logs and exports can be shared in bug reports.
+//! The same desk always gets the same pseudonym on this machine, but they're salted so they can't be
+//! turned back into addresses.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+use uuid::Uuid;
+
+use crate::storage;
+
+// every 16 bit Bluetooth uuid ends like this, they're the same for everyone so we leave them alone
+const BASE_UUID_SUFFIX: &str = "-0000-1000-8000-00805f9b34fb";
+// `E8:5F:12:34:56:78`
+const ADDRESS_LEN: usize = 17;
+// `0c3f1a6e-2d4b-4c8f-9a1e-7b6d5c4e3f2a`
+const UUID_LEN: usize = 36;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static REDACTOR: OnceLock<Redactor> = OnceLock::new();
+
+/// Redact our logs and progress output, and anything passed through [scrub]
+pub fn set_enabled(enabled: bool) {
+    if enabled {
+        // load our salt now instead of in the middle of logging something
+        redactor();
+    }
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// [redact] `text` if redaction is on
+pub fn scrub(text: String) -> String {
+    if enabled() {
+        redact(&text)
+    } else {
+        text
+    }
+}
+
+/// Replace every Bluetooth address, peripheral uuid and desk name we've seen in `text` with a
+/// pseudonym like `desk-1a2b3c4d`. Addresses match whether they're written with `:`, `-` or `_`.
+pub fn redact(text: &str) -> String {
+    redactor().redact(text)
+}
+
+fn redactor() -> &'static Redactor {
+    REDACTOR.get_or_init(Redactor::load)
+}
+
+struct Redactor {
+    salt: Uuid,
+    /// The names of desks we've seen, longest first so one name containing another still works
+    names: Vec<String>,
+}
+
+impl Redactor {
+    /// This can run while we're logging, so it falls back quietly instead of logging failures
+    fn load() -> Redactor {
+        let salt = storage::redaction_salt().unwrap_or_else(|_| Uuid::now_v7());
+        let names = storage::desk_history()
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|desk| desk.name)
+            .collect();
+
+        Redactor::new(salt, names)
+    }
+
+    fn new(salt: Uuid, mut names: Vec<String>) -> Redactor {
+        names.retain(|name| !name.trim().is_empty());
+        names.sort_by_key(|name| std::cmp::Reverse(name.len()));
+        names.dedup();
+
+        Redactor { salt, names }
+    }
+
+    fn pseudonym(&self, kind: &str, value: &str) -> String {
+        let mut hasher = DefaultHasher::new();
+        self.salt.hash(&mut hasher);
+        value.hash(&mut hasher);
+
+        format!("{kind}-{:08x}", hasher.finish() as u32)
+    }
+
+    fn redact(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for name in &self.names {
+            if text.contains(name.as_str()) {
+                text = text.replace(name.as_str(), &self.pseudonym("name", name));
+            }
+        }
+
+        let mut redacted = String::with_capacity(text.len());
+        let mut skip_until = 0;
+        let mut previous = None;
+        for (index, c) in text.char_indices() {
+            if index < skip_until {
+                continue;
+            }
+            let rest = &text[index..];
+            let at_boundary =
+                previous.is_none_or(|previous: char| !previous.is_ascii_alphanumeric());
+
+            if let Some(address) =
+                address_at(rest).filter(|_| at_boundary && !after_octet(&text[..index]))
+            {
+                redacted.push_str(&self.pseudonym("desk", &address));
+                skip_until = index + ADDRESS_LEN;
+            } else if let Some(uuid) = uuid_at(rest).filter(|_| at_boundary) {
+                redacted.push_str(&self.pseudonym("desk", &uuid));
+                skip_until = index + UUID_LEN;
+            } else {
+                redacted.push(c);
+            }
+            previous = Some(c);
+        }
+
+        redacted
+    }
+}
+
+/// The address at the start of `text` in `E8:5F:12:34:56:78` form, however it was separated
+fn address_at(text: &str) -> Option<String> {
+    let candidate = text.get(..ADDRESS_LEN)?.as_bytes();
+    let separator = candidate[2];
+    if ![b':', b'-', b'_'].contains(&separator)
+        || ends_in_word(text, ADDRESS_LEN)
+        || text.as_bytes().get(ADDRESS_LEN) == Some(&separator)
+    {
+        return None;
+    }
+
+    let mut octets = Vec::with_capacity(6);
+    for (index, octet) in candidate.chunks(3).enumerate() {
+        let (hex, rest) = octet.split_at(2);
+        if !hex.iter().all(u8::is_ascii_hexdigit) || (index < 5 && rest != [separator]) {
+            return None;
+        }
+        octets.push(String::from_utf8_lossy(hex).to_ascii_uppercase());
+    }
+
+    Some(octets.join(":"))
+}
+
+/// Whether `text` ends in an octet like `12:`, so an address right after it is really the end of a
+/// longer run
+fn after_octet(text: &str) -> bool {
+    match text.as_bytes() {
+        [.., high, low, separator] => {
+            high.is_ascii_hexdigit()
+                && low.is_ascii_hexdigit()
+                && [b':', b'-', b'_'].contains(separator)
+        }
+        _ => false,
+    }
+}
+
+/// The lowercase peripheral uuid at the start of `text`, skipping standard Bluetooth uuids
+fn uuid_at(text: &str) -> Option<String> {
+    let candidate = text.get(..UUID_LEN)?;
+    if ends_in_word(text, UUID_LEN) {
+        return None;
+    }
+    let uuid = Uuid::parse_str(candidate).ok()?.hyphenated().to_string();
+
+    (!uuid.ends_with(BASE_UUID_SUFFIX)).then_some(uuid)
+}
+
+/// Whether the character after `len` bytes continues the word, ie: a longer hex string
+fn ends_in_word(text: &str, len: usize) -> bool {
+    text[len..]
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphanumeric())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redactor(names: &[&str]) -> Redactor {
+        Redactor::new(
+            Uuid::nil(),
+            names.iter().map(|name| name.to_string()).collect(),
+        )
+    }
+
+    #[test]
+    fn addresses_with_any_separator() {
+        for text in [
+            "E8:5F:12:34:56:78",
+            "e8-5f-12-34-56-78",
+            "E8_5F_12_34_56_78 and more",
+        ] {
+            assert_eq!(
+                address_at(text).as_deref(),
+                Some("E8:5F:12:34:56:78"),
+                "{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn not_addresses() {
+        for text in [
+            // mixed separators
+            "E8:5F-12:34:56:78",
+            // part of a longer hex run
+            "E8:5F:12:34:56:789",
+            "E8:5F:12:34:56:78:9A",
+            "E8:5F:12:34:56:7G",
+            "E8:5F:12:34:56",
+            "E85F12345678",
+        ] {
+            assert_eq!(address_at(text), None, "{text}");
+        }
+    }
+
+    #[test]
+    fn peripheral_uuids() {
+        assert_eq!(
+            uuid_at("0C3F1A6E-2D4B-4C8F-9A1E-7B6D5C4E3F2A)").as_deref(),
+            Some("0c3f1a6e-2d4b-4c8f-9a1e-7b6d5c4e3f2a")
+        );
+        // part of a longer hex run
+        assert_eq!(uuid_at("0c3f1a6e-2d4b-4c8f-9a1e-7b6d5c4e3f2a0"), None);
+        // standard Bluetooth uuids are the same everywhere
+        assert_eq!(uuid_at("0000ff12-0000-1000-8000-00805f9b34fb"), None);
+    }
+
+    #[test]
+    fn redacts_addresses_uuids_and_names() {
+        let redactor = redactor(&["Uplift Desk"]);
+        let address = redactor.pseudonym("desk", "E8:5F:12:34:56:78");
+        let uuid = redactor.pseudonym("desk", "0c3f1a6e-2d4b-4c8f-9a1e-7b6d5c4e3f2a");
+        let name = redactor.pseudonym("name", "Uplift Desk");
+
+        assert_eq!(
+            redactor.redact(
+                "/org/bluez/hci0/dev_E8_5F_12_34_56_78 e8:5f:12:34:56:78 \
+                 0c3f1a6e-2d4b-4c8f-9a1e-7b6d5c4e3f2a Uplift Desk"
+            ),
+            format!("/org/bluez/hci0/dev_{address} {address} {uuid} {name}")
+        );
+    }
+
+    #[test]
+    fn leaves_everything_else_alone() {
+        let redactor = redactor(&[]);
+        for text in [
+            "service 0000ff12-0000-1000-8000-00805f9b34fb",
+            "hash E8:5F:12:34:56:78:9A",
+            "hash 00:E8:5F:12:34:56:78",
+            "XE8:5F:12:34:56:78",
+            "height 30.5in",
+        ] {
+            assert_eq!(redactor.redact(text), text);
+        }
+    }
+
+    #[test]
+    fn longer_names_win() {
+        let redactor = redactor(&["Desk", "", "Office Desk", "Desk"]);
+        let office = redactor.pseudonym("name", "Office Desk");
+        let desk = redactor.pseudonym("name", "Desk");
+
+        assert_eq!(
+            redactor.redact("Office Desk, Desk"),
+            format!("{office}, {desk}")
+        );
+    }
+}
@@ -0,0 +1,23 @@
+//! One id for each time uplift runs, shared by our hooks, JSON output and logs so a single button
+//! press can be followed through everything it touched
+
+use std::env;
+use std::sync::OnceLock;
+
+use uuid::Uuid;
+
+/// Hooks get our id as `UPLIFT_RUN_ID`, so an uplift started from a hook keeps the same id
+pub const RUN_ID_ENV: &str = "UPLIFT_RUN_ID";
+
+static RUN_ID: OnceLock<Uuid> = OnceLock::new();
+
+/// Our id, taken from `UPLIFT_RUN_ID` if we were started by a hook, otherwise a new uuid v7 so ids
+/// sort by when they started
+pub fn get() -> Uuid {
+    *RUN_ID.get_or_init(|| {
+        env::var(RUN_ID_ENV)
+            .ok()
+            .and_then(|id| id.parse().ok())
+            .unwrap_or_else(Uuid::now_v7)
+    })
+}
@@ -0,0 +1,554 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use jiff::Zoned;
+use tokio::time::{self, Instant};
+
+use crate::calendar::ActiveHours;
+use crate::desk::{DeskControl, ForcePolicy, Nudge, Target, AVG_MID_HEIGHT};
+use crate::output::{self, progress, verbose};
+use crate::quiet_hours::{QuietHours, QuietMode};
+use crate::storage::{self, ScheduleState};
+
+/// Alternate between sitting and standing forever. We wait on tokio's clock and read the time of day
+/// from `clock`, with a [TokioClock] the whole schedule can be driven with `tokio::time::pause` and
+/// `advance`.
+#[derive(Clone, Debug)]
+pub struct Schedule {
+    /// How long to sit before standing up
+    pub sit: Duration,
+    /// How long to stand before sitting down
+    pub stand: Duration,
+    /// Skip moving while the OS is in Do Not Disturb / Focus mode
+    pub respect_focus: bool,
+    /// If someone moves the desk with the keypad, wait this long before our next move
+    pub manual_grace: Option<Duration>,
+    /// While standing, shift the desk up or down slightly this often to keep us moving
+    pub micro_adjust: Option<Duration>,
+    /// When we wait or skip our moves
+    pub quiet_hours: QuietHours,
+    /// Only move on these days and hours, moves that come due outside of them wait until they start
+    pub active_hours: Option<ActiveHours>,
+    /// Where we read the time of day for active hours, quiet hours and the move we save
+    pub clock: Arc<dyn Clock>,
+    /// Whether focus mode is on, [crate::focus::is_focus_active] outside of tests
+    pub focus: fn() -> Result<bool, anyhow::Error>,
+}
+
+/// Where a [Schedule] reads the time of day from
+pub trait Clock: fmt::Debug + Send + Sync {
+    fn now(&self) -> Zoned;
+}
+
+/// The system clock in the system time zone
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Zoned {
+        Zoned::now()
+    }
+}
+
+/// Starts at a time of day and then follows tokio's clock, so `tokio::time::pause` and `advance`
+/// move it too
+#[derive(Clone, Debug)]
+pub struct TokioClock {
+    start: Zoned,
+    started: Instant,
+}
+
+impl TokioClock {
+    pub fn new(start: Zoned) -> Self {
+        TokioClock {
+            start,
+            started: Instant::now(),
+        }
+    }
+}
+
+impl Clock for TokioClock {
+    fn now(&self) -> Zoned {
+        self.start.saturating_add(self.started.elapsed())
+    }
+}
+
+// how often we check the height for manual movement while waiting
+const MANUAL_POLL_INTERVAL: Duration = Duration::from_secs(1);
+// how far the desk can drift without us treating it as someone using the keypad, 0.5"
+const MANUAL_TOLERANCE: isize = 5;
+// a move that came due while we weren't running still happens if we restart within this long
+const RESUME_WINDOW: Duration = Duration::from_secs(5 * 60);
+// the most often we'll make micro adjustments
+const MIN_MICRO_ADJUST_INTERVAL: Duration = Duration::from_secs(60);
+// each micro adjustment is a couple of nudges, about 0.2"
+const MICRO_ADJUST_NUDGES: usize = 2;
+
+impl Schedule {
+    pub async fn run(&self, desk: &impl DeskControl) -> Result<(), anyhow::Error> {
+        let mut target = if desk.query_height().await? > AVG_MID_HEIGHT {
+            Target::Sit
+        } else {
+            Target::Stand
+        };
+        let mut resume_in = None;
+        if let Some((next, remaining)) = self.resumable(desk) {
+            progress!(
+                "Resuming our schedule, {next:?} in {}",
+                humantime::format_duration(remaining)
+            );
+            target = next;
+            resume_in = Some(remaining);
+        }
+
+        loop {
+            let wait = resume_in.take().unwrap_or(match target {
+                Target::Stand => self.sit,
+                _ => self.stand,
+            });
+            self.remember(desk, target, wait);
+            verbose!(
+                "Waiting {} before {target:?}",
+                humantime::format_duration(wait)
+            );
+            self.wait(desk, wait, target).await;
+
+            if let Some(until) = self
+                .active_hours
+                .as_ref()
+                .and_then(|active_hours| active_hours.until_active(self.clock.now().timestamp()))
+            {
+                progress!(
+                    "Outside of our active hours, waiting {} before {target:?}",
+                    humantime::format_duration(until)
+                );
+                time::sleep(until).await;
+            }
+
+            if self.respect_focus && self.focus_active() {
+                log::info!("Skipped {target:?} because focus mode is on");
+                progress!("Skipped {target:?}, focus mode is on");
+                continue;
+            }
+
+            if let Some((window, remaining)) = self.quiet_hours.active_at(&self.clock.now()) {
+                match self.quiet_hours.mode {
+                    QuietMode::Defer => {
+                        progress!(
+                            "Quiet hours ({window}), waiting {} before {target:?}",
+                            humantime::format_duration(remaining)
+                        );
+                        time::sleep(remaining).await;
+                    }
+                    QuietMode::Refuse => {
+                        log::info!("Skipped {target:?} because of quiet hours {window}");
+                        progress!("Skipped {target:?}, quiet hours ({window})");
+                        continue;
+                    }
+                }
+            }
+
+            let height = desk.force(target, ForcePolicy::default()).await?;
+            progress!("{target:?}: {}", output::height(height));
+
+            target = match target {
+                Target::Stand => Target::Sit,
+                _ => Target::Stand,
+            };
+        }
+    }
+
+    /// Sleep for `wait`, watching for heights we didn't ask for. Manual movement pushes our next
+    /// move back by `manual_grace` and stops our micro adjustments until the next move.
+    async fn wait(&self, desk: &impl DeskControl, wait: Duration, next: Target) {
+        let mut deadline = Instant::now() + wait;
+        let mut expected_height = desk.height();
+        let micro_adjust = self
+            .micro_adjust
+            // we're standing if we're waiting to sit
+            .filter(|_| next == Target::Sit)
+            .map(|interval| interval.max(MIN_MICRO_ADJUST_INTERVAL));
+        let mut next_adjustment = micro_adjust.map(|interval| Instant::now() + interval);
+        let mut direction = Nudge::Up;
+
+        while Instant::now() < deadline {
+            time::sleep_until(deadline.min(Instant::now() + MANUAL_POLL_INTERVAL)).await;
+
+            let height = desk.height();
+            if (height - expected_height).abs() > MANUAL_TOLERANCE {
+                log::info!("Manual movement detected: {expected_height} -> {height}");
+                progress!("Manual movement detected: {}", output::height(height));
+                expected_height = height;
+
+                if let Some(grace) = self.manual_grace {
+                    verbose!("Pausing for {}", humantime::format_duration(grace));
+                    deadline = deadline.max(Instant::now() + grace);
+                    self.remember(desk, next, deadline - Instant::now());
+                }
+                if next_adjustment.take().is_some() {
+                    verbose!("Stopped micro adjustments until our next move");
+                }
+            } else if let (Some(interval), Some(at)) = (micro_adjust, next_adjustment) {
+                if Instant::now() >= at {
+                    match micro_adjust_once(desk, direction).await {
+                        Ok(height) => expected_height = height,
+                        Err(e) => log::warn!("Failed to make a micro adjustment: {e:?}"),
+                    }
+                    direction = match direction {
+                        Nudge::Up => Nudge::Down,
+                        Nudge::Down => Nudge::Up,
+                    };
+                    next_adjustment = Some(Instant::now() + interval);
+                }
+            }
+        }
+    }
+
+    fn focus_active(&self) -> bool {
+        (self.focus)().unwrap_or_else(|e| {
+            log::debug!("Couldn't check focus mode: {e:?}");
+            false
+        })
+    }
+
+    /// The move we were waiting on before we were stopped and how long until it's due, unless it
+    /// came due too long ago
+    fn resumable(&self, desk: &impl DeskControl) -> Option<(Target, Duration)> {
+        let state = storage::schedule_state(desk.id()).unwrap_or_else(|e| {
+            log::warn!("Failed to load our schedule: {e:?}");
+            None
+        })?;
+
+        match state.at.duration_since(self.system_time()) {
+            Ok(remaining) => Some((state.next, remaining)),
+            Err(e) if e.duration() < RESUME_WINDOW => Some((state.next, Duration::ZERO)),
+            Err(_) => None,
+        }
+    }
+
+    fn remember(&self, desk: &impl DeskControl, next: Target, wait: Duration) {
+        let state = ScheduleState {
+            next,
+            at: self.system_time() + wait,
+        };
+        if let Err(e) = storage::save_schedule_state(desk.id(), &state) {
+            log::warn!("Failed to save our schedule: {e:?}");
+        }
+    }
+
+    fn system_time(&self) -> SystemTime {
+        self.clock.now().timestamp().into()
+    }
+}
+
+async fn micro_adjust_once(
+    desk: &impl DeskControl,
+    direction: Nudge,
+) -> Result<isize, anyhow::Error> {
+    let mut height = desk.height();
+    for _ in 0..MICRO_ADJUST_NUDGES {
+        height = desk.nudge(direction).await?;
+    }
+    verbose!("Micro adjusted {direction:?} to {}", output::height(height));
+
+    Ok(height)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::process;
+    use std::sync::atomic::{AtomicIsize, AtomicUsize, Ordering};
+    use std::sync::{Mutex, Once};
+
+    use crate::calendar::TimeWindow;
+    use crate::desk::{MoveProfile, Tolerance, AVG_SITTING_HEIGHT, AVG_STANDING_HEIGHT};
+
+    use super::*;
+
+    // a Monday
+    const MORNING: &str = "2026-03-02T11:00[America/New_York]";
+
+    /// Sits and stands as soon as it's asked to, remembering when it was asked
+    struct FakeDesk {
+        id: &'static str,
+        height: AtomicIsize,
+        started: Instant,
+        moves: Mutex<Vec<(Duration, Target)>>,
+    }
+
+    impl FakeDesk {
+        /// Each test uses its own `id` so their saved schedules don't collide
+        fn new(id: &'static str, height: isize) -> Self {
+            static DATA_DIR: Once = Once::new();
+            DATA_DIR.call_once(|| {
+                let dir = env::temp_dir().join(format!("uplift-schedule-{}", process::id()));
+                let _ = fs::remove_dir_all(&dir);
+                env::set_var("UPLIFT_DATA_DIR", dir);
+            });
+
+            FakeDesk {
+                id,
+                height: AtomicIsize::new(height),
+                started: Instant::now(),
+                moves: Mutex::new(vec![]),
+            }
+        }
+
+        fn moves(&self) -> Vec<(Duration, Target)> {
+            self.moves.lock().unwrap().clone()
+        }
+    }
+
+    impl DeskControl for FakeDesk {
+        fn id(&self) -> &str {
+            self.id
+        }
+
+        fn height(&self) -> isize {
+            self.height.load(Ordering::Relaxed)
+        }
+
+        async fn query_height(&self) -> Result<isize, anyhow::Error> {
+            Ok(self.height())
+        }
+
+        async fn nudge(&self, direction: Nudge) -> Result<isize, anyhow::Error> {
+            let change = match direction {
+                Nudge::Up => 1,
+                Nudge::Down => -1,
+            };
+            Ok(self.height.fetch_add(change, Ordering::Relaxed) + change)
+        }
+
+        async fn move_to_with(
+            &self,
+            target: isize,
+            _profile: MoveProfile,
+            _tolerance: Tolerance,
+        ) -> Result<isize, anyhow::Error> {
+            self.height.store(target, Ordering::Relaxed);
+            Ok(target)
+        }
+
+        async fn force(
+            &self,
+            target: Target,
+            _policy: ForcePolicy,
+        ) -> Result<isize, anyhow::Error> {
+            let height = match target {
+                Target::Sit => AVG_SITTING_HEIGHT,
+                Target::Stand => AVG_STANDING_HEIGHT,
+                Target::Toggle => unreachable!("The schedule always knows where it's going"),
+            };
+            self.height.store(height, Ordering::Relaxed);
+            self.moves
+                .lock()
+                .unwrap()
+                .push((self.started.elapsed(), target));
+            Ok(height)
+        }
+    }
+
+    fn schedule(start: &str) -> Schedule {
+        Schedule {
+            sit: minutes(30),
+            stand: minutes(10),
+            respect_focus: true,
+            manual_grace: None,
+            micro_adjust: None,
+            quiet_hours: QuietHours::default(),
+            active_hours: None,
+            clock: Arc::new(TokioClock::new(start.parse().unwrap())),
+            focus: || Ok(false),
+        }
+    }
+
+    fn minutes(minutes: u64) -> Duration {
+        Duration::from_secs(minutes * 60)
+    }
+
+    /// Run `schedule` for `length` of tokio's time, which passes instantly while it's paused
+    async fn run_for(schedule: &Schedule, desk: &FakeDesk, length: Duration) {
+        let result = time::timeout(length, schedule.run(desk)).await;
+        assert!(result.is_err(), "The schedule stopped: {result:?}");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn alternates_sitting_and_standing() {
+        let desk = FakeDesk::new("alternates", AVG_SITTING_HEIGHT);
+        run_for(&schedule(MORNING), &desk, minutes(85)).await;
+
+        assert_eq!(
+            desk.moves(),
+            [
+                (minutes(30), Target::Stand),
+                (minutes(40), Target::Sit),
+                (minutes(70), Target::Stand),
+                (minutes(80), Target::Sit),
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sits_first_when_standing() {
+        let desk = FakeDesk::new("sits-first", AVG_STANDING_HEIGHT);
+        run_for(&schedule(MORNING), &desk, minutes(45)).await;
+
+        assert_eq!(
+            desk.moves(),
+            [(minutes(10), Target::Sit), (minutes(40), Target::Stand)]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn manual_movement_pushes_our_move_back() {
+        let desk = FakeDesk::new("manual", AVG_SITTING_HEIGHT);
+        let schedule = Schedule {
+            manual_grace: Some(minutes(30)),
+            ..schedule(MORNING)
+        };
+        // between two of our height checks so we see it at 10 minutes
+        let keypad = async {
+            time::sleep(minutes(10) - Duration::from_millis(500)).await;
+            desk.height
+                .store(AVG_SITTING_HEIGHT + 20, Ordering::Relaxed);
+        };
+        tokio::join!(run_for(&schedule, &desk, minutes(45)), keypad);
+
+        assert_eq!(desk.moves(), [(minutes(40), Target::Stand)]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn resumes_a_saved_move() {
+        let desk = FakeDesk::new("resumes", AVG_SITTING_HEIGHT);
+        let schedule = schedule(MORNING);
+        let state = ScheduleState {
+            next: Target::Sit,
+            at: schedule.system_time() + minutes(5),
+        };
+        storage::save_schedule_state(desk.id, &state).unwrap();
+        run_for(&schedule, &desk, minutes(10)).await;
+
+        assert_eq!(desk.moves(), [(minutes(5), Target::Sit)]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn forgets_moves_that_came_due_long_ago() {
+        let desk = FakeDesk::new("forgets", AVG_SITTING_HEIGHT);
+        let schedule = schedule(MORNING);
+        let state = ScheduleState {
+            next: Target::Sit,
+            at: schedule.system_time() - RESUME_WINDOW - minutes(1),
+        };
+        storage::save_schedule_state(desk.id, &state).unwrap();
+        run_for(&schedule, &desk, minutes(35)).await;
+
+        assert_eq!(desk.moves(), [(minutes(30), Target::Stand)]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn skips_moves_during_focus() {
+        let desk = FakeDesk::new("focus", AVG_SITTING_HEIGHT);
+        let schedule = Schedule {
+            focus: || Ok(true),
+            ..schedule(MORNING)
+        };
+        run_for(&schedule, &desk, minutes(120)).await;
+
+        assert_eq!(desk.moves(), []);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retries_a_skipped_move_after_another_interval() {
+        static CHECKS: AtomicUsize = AtomicUsize::new(0);
+        let desk = FakeDesk::new("focus-ends", AVG_SITTING_HEIGHT);
+        let schedule = Schedule {
+            // focus is only on for our first move
+            focus: || Ok(CHECKS.fetch_add(1, Ordering::Relaxed) == 0),
+            ..schedule(MORNING)
+        };
+        run_for(&schedule, &desk, minutes(75)).await;
+
+        assert_eq!(
+            desk.moves(),
+            [(minutes(60), Target::Stand), (minutes(70), Target::Sit)]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn ignores_focus_unless_asked_to() {
+        let desk = FakeDesk::new("ignores-focus", AVG_SITTING_HEIGHT);
+        let schedule = Schedule {
+            respect_focus: false,
+            focus: || Ok(true),
+            ..schedule(MORNING)
+        };
+        run_for(&schedule, &desk, minutes(35)).await;
+
+        assert_eq!(desk.moves(), [(minutes(30), Target::Stand)]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn defers_moves_until_quiet_hours_end() {
+        let desk = FakeDesk::new("defers", AVG_SITTING_HEIGHT);
+        let schedule = Schedule {
+            quiet_hours: QuietHours {
+                windows: vec!["11:20-11:45".parse::<TimeWindow>().unwrap()],
+                mode: QuietMode::Defer,
+            },
+            ..schedule(MORNING)
+        };
+        run_for(&schedule, &desk, minutes(50)).await;
+
+        assert_eq!(
+            desk.moves(),
+            [(minutes(45), Target::Stand)],
+            "Our stand at 11:30 waits until 11:45"
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn refuses_moves_during_quiet_hours() {
+        let desk = FakeDesk::new("refuses", AVG_SITTING_HEIGHT);
+        let schedule = Schedule {
+            quiet_hours: QuietHours {
+                windows: vec!["11:20-11:45".parse::<TimeWindow>().unwrap()],
+                mode: QuietMode::Refuse,
+            },
+            ..schedule(MORNING)
+        };
+        run_for(&schedule, &desk, minutes(65)).await;
+
+        assert_eq!(
+            desk.moves(),
+            [(minutes(60), Target::Stand)],
+            "Our stand at 11:30 is skipped and tried again at 12:00"
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn waits_for_active_hours_over_the_weekend() {
+        let desk = FakeDesk::new("weekend", AVG_SITTING_HEIGHT);
+        let schedule = Schedule {
+            active_hours: Some(ActiveHours {
+                days: "weekdays".parse().unwrap(),
+                hours: Some("09:00-18:00".parse().unwrap()),
+                time_zone: jiff::tz::TimeZone::get("America/New_York").unwrap(),
+            }),
+            // a Friday
+            ..schedule("2026-03-06T17:45[America/New_York]")
+        };
+        let monday = Duration::from_secs((2 * 24 + 15) * 60 * 60 + 15 * 60);
+        // the clocks spring forward on Sunday so Monday comes an hour sooner
+        let monday = monday - minutes(60);
+        run_for(&schedule, &desk, monday + minutes(15)).await;
+
+        assert_eq!(
+            desk.moves(),
+            [(monday, Target::Stand), (monday + minutes(10), Target::Sit)]
+        );
+    }
+}
@@ -0,0 +1,381 @@
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File, TryLockError};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::SystemTime;
+
+use anyhow::{anyhow, Context};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::desk::{DecoderKind, Target};
+
+const DESKS_FILE: &str = "desks.json";
+const PRESETS_FILE: &str = "presets.json";
+const TEMPORARY_FILE: &str = "temporary.json";
+const CHARACTERISTICS_FILE: &str = "characteristics.json";
+const DECODERS_FILE: &str = "decoders.json";
+const HISTORY_FILE: &str = "history.json";
+const SCHEDULE_FILE: &str = "schedule.json";
+const SALT_FILE: &str = "salt.json";
+const PENDING_CALIBRATION_FILE: &str = "pending_calibration.json";
+// how many heights we remember for `uplift undo` on each desk
+const MAX_HISTORY: usize = 20;
+/// Store our state somewhere other than the data directory
+const DATA_DIR_ENV: &str = "UPLIFT_DATA_DIR";
+
+/// Every desk we've ever seen, keyed by its peripheral id
+pub type DeskInventory = BTreeMap<String, DeskSighting>;
+
+/// The last time we saw a desk while scanning
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DeskSighting {
+    pub id: String,
+    pub address: String,
+    pub name: Option<String>,
+    pub adapter: String,
+    pub rssi: Option<i16>,
+    pub last_seen: SystemTime,
+}
+
+/// The sit and stand heights saved on a desk, we can't read these from the desk so they're learned
+/// as we save and reach them
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+pub struct Presets {
+    pub sit: Option<isize>,
+    pub stand: Option<isize>,
+}
+
+/// What we've learned about a desk that's worth carrying to another machine
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Calibration {
+    pub presets: Presets,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub decoder: Option<DecoderKind>,
+}
+
+impl Calibration {
+    pub fn is_empty(&self) -> bool {
+        self.presets.sit.is_none() && self.presets.stand.is_none() && self.decoder.is_none()
+    }
+}
+
+/// A temporary move that should return the desk to `return_to` once `until` passes
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TemporaryPosition {
+    pub desk: String,
+    pub return_to: isize,
+    pub until: SystemTime,
+    /// The process that will move the desk back
+    pub pid: u32,
+}
+
+/// The next move `uplift schedule` is waiting on, so a restarted schedule keeps its timer
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct ScheduleState {
+    pub next: Target,
+    pub at: SystemTime,
+}
+
+/// A characteristic we found during service discovery
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct CachedCharacteristic {
+    pub uuid: Uuid,
+    pub service_uuid: Uuid,
+    /// The raw property flags
+    pub properties: u8,
+}
+
+/// The characteristics we need to control a desk, cached so we can try to skip service discovery
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct CachedCharacteristics {
+    pub data_in: CachedCharacteristic,
+    pub data_out: CachedCharacteristic,
+    pub name: CachedCharacteristic,
+}
+
+/// Our state (not user configuration) is stored in `<data dir>/uplift` or `UPLIFT_DATA_DIR`
+pub fn data_dir() -> Result<PathBuf, anyhow::Error> {
+    if let Some(dir) = env::var_os(DATA_DIR_ENV) {
+        return Ok(PathBuf::from(dir));
+    }
+
+    dirs::data_dir()
+        .map(|dir| dir.join("uplift"))
+        .ok_or_else(|| anyhow!("Couldn't find a data directory"))
+}
+
+/// Remember the desks we saw, replacing any older sightings of the same desk
+pub fn record_desks(
+    sightings: impl IntoIterator<Item = DeskSighting>,
+) -> Result<(), anyhow::Error> {
+    update(DESKS_FILE, |inventory: &mut DeskInventory| {
+        for sighting in sightings {
+            inventory.insert(sighting.id.clone(), sighting);
+        }
+    })
+}
+
+/// Every desk we've seen, most recently seen first
+pub fn desk_history() -> Result<Vec<DeskSighting>, anyhow::Error> {
+    let inventory: DeskInventory = load(DESKS_FILE)?;
+    let mut desks = inventory.into_values().collect::<Vec<_>>();
+    desks.sort_by_key(|desk| Reverse(desk.last_seen));
+
+    Ok(desks)
+}
+
+/// Move everything we know about a desk to its new id, macOS can change a desk's id between runs
+pub fn rename_desk(old_id: &str, new_id: &str) -> Result<(), anyhow::Error> {
+    rename_key::<Presets>(PRESETS_FILE, old_id, new_id)?;
+    rename_key::<CachedCharacteristics>(CHARACTERISTICS_FILE, old_id, new_id)?;
+    rename_key::<DecoderKind>(DECODERS_FILE, old_id, new_id)?;
+    rename_key::<Vec<isize>>(HISTORY_FILE, old_id, new_id)?;
+    rename_key::<ScheduleState>(SCHEDULE_FILE, old_id, new_id)?;
+
+    // we've already recorded the desk under its new id
+    update(DESKS_FILE, |inventory: &mut DeskInventory| {
+        inventory.remove(old_id);
+    })
+}
+
+/// The presets we've learned for a desk
+pub fn presets(id: &str) -> Result<Presets, anyhow::Error> {
+    let mut presets: BTreeMap<String, Presets> = load(PRESETS_FILE)?;
+
+    Ok(presets.remove(id).unwrap_or_default())
+}
+
+pub fn save_presets(id: &str, desk_presets: Presets) -> Result<(), anyhow::Error> {
+    update(PRESETS_FILE, |presets: &mut BTreeMap<String, Presets>| {
+        presets.insert(id.to_string(), desk_presets);
+    })
+}
+
+pub fn temporary_position() -> Result<Option<TemporaryPosition>, anyhow::Error> {
+    load(TEMPORARY_FILE)
+}
+
+pub fn save_temporary_position(temporary: Option<&TemporaryPosition>) -> Result<(), anyhow::Error> {
+    save(TEMPORARY_FILE, &temporary)
+}
+
+pub fn cached_characteristics(id: &str) -> Result<Option<CachedCharacteristics>, anyhow::Error> {
+    let mut cached: BTreeMap<String, CachedCharacteristics> = load(CHARACTERISTICS_FILE)?;
+
+    Ok(cached.remove(id))
+}
+
+/// Cache the characteristics for a desk or forget them with `None`
+pub fn save_cached_characteristics(
+    id: &str,
+    characteristics: Option<&CachedCharacteristics>,
+) -> Result<(), anyhow::Error> {
+    update(
+        CHARACTERISTICS_FILE,
+        |cached: &mut BTreeMap<String, CachedCharacteristics>| match characteristics {
+            Some(characteristics) => cached.insert(id.to_string(), *characteristics),
+            None => cached.remove(id),
+        },
+    )?;
+
+    Ok(())
+}
+
+/// The height decoder we last chose for a desk
+pub fn decoder(id: &str) -> Result<Option<DecoderKind>, anyhow::Error> {
+    let mut decoders: BTreeMap<String, DecoderKind> = load(DECODERS_FILE)?;
+
+    Ok(decoders.remove(id))
+}
+
+pub fn save_decoder(id: &str, decoder: DecoderKind) -> Result<(), anyhow::Error> {
+    update(
+        DECODERS_FILE,
+        |decoders: &mut BTreeMap<String, DecoderKind>| {
+            decoders.insert(id.to_string(), decoder);
+        },
+    )
+}
+
+/// The presets and decoder we've learned for a desk
+pub fn calibration(id: &str) -> Result<Calibration, anyhow::Error> {
+    Ok(Calibration {
+        presets: presets(id)?,
+        decoder: decoder(id)?,
+    })
+}
+
+pub fn save_calibration(id: &str, calibration: Calibration) -> Result<(), anyhow::Error> {
+    save_presets(id, calibration.presets)?;
+    if let Some(decoder) = calibration.decoder {
+        save_decoder(id, decoder)?;
+    }
+
+    Ok(())
+}
+
+/// Keep imported calibration for a desk name that isn't paired on this machine yet
+pub fn save_pending_calibration(name: &str, calibration: Calibration) -> Result<(), anyhow::Error> {
+    update(
+        PENDING_CALIBRATION_FILE,
+        |pending: &mut BTreeMap<String, Calibration>| {
+            pending.insert(name.to_string(), calibration);
+        },
+    )
+}
+
+/// Take the imported calibration waiting on `name`, once we know which desk it is
+pub fn take_pending_calibration(name: &str) -> Result<Option<Calibration>, anyhow::Error> {
+    update(
+        PENDING_CALIBRATION_FILE,
+        |pending: &mut BTreeMap<String, Calibration>| pending.remove(name),
+    )
+}
+
+/// The salt for our redacted pseudonyms, made the first time we need it so pseudonyms stay the same
+/// between runs
+pub fn redaction_salt() -> Result<Uuid, anyhow::Error> {
+    update(SALT_FILE, |salt: &mut Option<Uuid>| {
+        *salt.get_or_insert_with(Uuid::new_v4)
+    })
+}
+
+/// Remember the height a desk was at before we moved it, forgetting the oldest heights once we have
+/// too many
+pub fn push_history(id: &str, height: isize) -> Result<(), anyhow::Error> {
+    update(
+        HISTORY_FILE,
+        |history: &mut BTreeMap<String, Vec<isize>>| {
+            let heights = history.entry(id.to_string()).or_default();
+            heights.push(height);
+            if heights.len() > MAX_HISTORY {
+                heights.drain(..heights.len() - MAX_HISTORY);
+            }
+        },
+    )
+}
+
+pub fn schedule_state(id: &str) -> Result<Option<ScheduleState>, anyhow::Error> {
+    let mut states: BTreeMap<String, ScheduleState> = load(SCHEDULE_FILE)?;
+
+    Ok(states.remove(id))
+}
+
+pub fn save_schedule_state(id: &str, state: &ScheduleState) -> Result<(), anyhow::Error> {
+    update(
+        SCHEDULE_FILE,
+        |states: &mut BTreeMap<String, ScheduleState>| {
+            states.insert(id.to_string(), *state);
+        },
+    )
+}
+
+/// Take the most recent height from a desk's history
+pub fn pop_history(id: &str) -> Result<Option<isize>, anyhow::Error> {
+    update(
+        HISTORY_FILE,
+        |history: &mut BTreeMap<String, Vec<isize>>| history.get_mut(id).and_then(Vec::pop),
+    )
+}
+
+fn rename_key<T: DeserializeOwned + Serialize>(
+    file: &str,
+    old_key: &str,
+    new_key: &str,
+) -> Result<(), anyhow::Error> {
+    update(file, |values: &mut BTreeMap<String, T>| {
+        if let Some(value) = values.remove(old_key) {
+            values.insert(new_key.to_string(), value);
+        }
+    })
+}
+
+fn load<T: DeserializeOwned + Default>(file: &str) -> Result<T, anyhow::Error> {
+    let path = data_dir()?.join(file);
+
+    match fs::read(&path) {
+        Ok(contents) => serde_json::from_slice(&contents)
+            .with_context(|| format!("Failed to parse {}", path.display())),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(T::default()),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+fn save<T: Serialize>(file: &str, value: &T) -> Result<(), anyhow::Error> {
+    let contents = serde_json::to_vec_pretty(value)?;
+    write_atomic(&data_dir()?.join(file), &contents)
+}
+
+/// Load `file`, change it and save it while holding its lock. Every update re-reads the file so
+/// concurrent updates to different desks are merged instead of overwriting each other.
+fn update<T: DeserializeOwned + Serialize + Default, R>(
+    file: &str,
+    update: impl FnOnce(&mut T) -> R,
+) -> Result<R, anyhow::Error> {
+    let _lock = lock(&data_dir()?.join(file))?;
+    let mut value = load(file)?;
+    let result = update(&mut value);
+    save(file, &value)?;
+
+    Ok(result)
+}
+
+/// Take an exclusive lock for updating `path`, it's released when the returned file is dropped. If
+/// another uplift is updating `path` we warn and wait for it to finish.
+pub fn lock(path: &Path) -> Result<File, anyhow::Error> {
+    let mut lock_path = path.as_os_str().to_owned();
+    lock_path.push(".lock");
+    let lock_path = PathBuf::from(lock_path);
+    if let Some(dir) = lock_path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+
+    let file = File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)
+        .with_context(|| format!("Failed to open {}", lock_path.display()))?;
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            log::warn!(
+                "Another uplift is updating {}, waiting for it to finish",
+                path.display()
+            );
+            file.lock()
+                .with_context(|| format!("Failed to lock {}", lock_path.display()))?;
+        }
+        Err(TryLockError::Error(e)) => {
+            return Err(e).with_context(|| format!("Failed to lock {}", lock_path.display()))
+        }
+    }
+
+    Ok(file)
+}
+
+/// Write to a temporary file next to `path` and rename it into place, so readers only ever see the
+/// old or the new contents
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), anyhow::Error> {
+    let dir = path
+        .parent()
+        .ok_or_else(|| anyhow!("{} doesn't have a parent directory", path.display()))?;
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(format!(".{}.tmp", process::id()));
+    let temp_path = PathBuf::from(temp_path);
+
+    fs::write(&temp_path, contents)
+        .with_context(|| format!("Failed to write {}", temp_path.display()))?;
+    fs::rename(&temp_path, path).with_context(|| {
+        let _ = fs::remove_file(&temp_path);
+        format!("Failed to replace {}", path.display())
+    })
+}
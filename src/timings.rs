@@ -0,0 +1,75 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+
+lazy_static! {
+    static ref TIMINGS: Mutex<Vec<(&'static str, Duration)>> = Mutex::new(Vec::new());
+}
+
+/// Records how long a phase took once it's dropped
+pub struct Phase {
+    name: &'static str,
+    start: Instant,
+}
+
+/// Start timing a phase, the time is recorded when the returned [Phase] is dropped
+pub fn phase(name: &'static str) -> Phase {
+    Phase {
+        name,
+        start: Instant::now(),
+    }
+}
+
+/// Record a phase that started at `start` and finished now
+pub fn record(name: &'static str, start: Instant) {
+    let elapsed = start.elapsed();
+    log::trace!("{name} took {elapsed:?}");
+
+    if let Ok(mut timings) = TIMINGS.lock() {
+        timings.push((name, elapsed));
+    }
+}
+
+impl Drop for Phase {
+    fn drop(&mut self) {
+        record(self.name, self.start);
+    }
+}
+
+/// Take every phase we've recorded so far, so the next caller only sees newer phases
+pub fn take() -> Vec<(&'static str, Duration)> {
+    TIMINGS
+        .lock()
+        .map(|mut timings| timings.drain(..).collect())
+        .unwrap_or_default()
+}
+
+/// Print a breakdown of every phase we recorded, phases that ran multiple times are summed
+pub fn print_summary(total: Duration) {
+    let timings = match TIMINGS.lock() {
+        Ok(timings) => timings,
+        Err(_) => return,
+    };
+
+    let mut summary: Vec<(&'static str, Duration, usize)> = Vec::new();
+    for (name, elapsed) in timings.iter() {
+        match summary.iter_mut().find(|(n, _, _)| n == name) {
+            Some((_, sum, count)) => {
+                *sum += *elapsed;
+                *count += 1;
+            }
+            None => summary.push((name, *elapsed, 1)),
+        }
+    }
+
+    eprintln!("Timings:");
+    for (name, elapsed, count) in summary {
+        if count > 1 {
+            eprintln!("  {name:<24} {:>8.3}s ({count}x)", elapsed.as_secs_f32());
+        } else {
+            eprintln!("  {name:<24} {:>8.3}s", elapsed.as_secs_f32());
+        }
+    }
+    eprintln!("  {:<24} {:>8.3}s", "total", total.as_secs_f32());
+}
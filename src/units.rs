@@ -0,0 +1,187 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+const CM_PER_INCH: f64 = 2.54;
+
+/// A height in inches. When parsed, plain numbers are inches but other units can be used with a
+/// suffix: `30in`, `30"`, `76.2cm` or `762mm`. Either `.` or `,` can be the decimal separator and
+/// heights can't be negative.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Serialize)]
+#[serde(transparent)]
+pub struct Inches(pub f64);
+
+impl Inches {
+    fn new(inches: f64) -> Result<Inches, anyhow::Error> {
+        if inches.is_finite() && inches >= 0.0 {
+            Ok(Inches(inches))
+        } else {
+            Err(anyhow!(
+                "Invalid height `{inches}`, heights can't be negative"
+            ))
+        }
+    }
+
+    /// Our desk heights are stored in tenths of an inch
+    pub fn to_tenths(self) -> isize {
+        (self.0 * 10.0).round() as isize
+    }
+}
+
+impl FromStr for Inches {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let split = s
+            .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == ','))
+            .unwrap_or(s.len());
+        let (value, unit) = s.split_at(split);
+        if value.is_empty() && unit.starts_with('-') {
+            return Err(anyhow!("Invalid height `{s}`, heights can't be negative"));
+        }
+
+        let value = value
+            .replace(',', ".")
+            .parse::<f64>()
+            .with_context(|| format!("Invalid height `{s}`"))?;
+        let inches = match unit.trim() {
+            "" | "in" | "\"" => value,
+            // round conversions to 0.01" so they stay readable in the config
+            "cm" => (value / CM_PER_INCH * 100.0).round() / 100.0,
+            "mm" => (value / (CM_PER_INCH * 10.0) * 100.0).round() / 100.0,
+            unit => {
+                return Err(anyhow!(
+                    "Unknown unit `{unit}` in `{s}`, expected one of: in, \", cm, mm"
+                ))
+            }
+        };
+
+        Inches::new(inches)
+    }
+}
+
+impl<'de> Deserialize<'de> for Inches {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Number(f64),
+            Text(String),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Number(inches) => Inches::new(inches).map_err(serde::de::Error::custom),
+            Raw::Text(text) => text.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+impl fmt::Display for Inches {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// A duration like `90s` or `2m`, plain numbers are seconds
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Seconds(pub Duration);
+
+impl FromStr for Seconds {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        match s.parse::<u64>() {
+            Ok(seconds) => Ok(Seconds(Duration::from_secs(seconds))),
+            Err(_) => humantime::parse_duration(s)
+                .map(Seconds)
+                .with_context(|| format!("Invalid duration `{s}`")),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Seconds {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Number(u64),
+            Text(String),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Number(seconds) => Ok(Seconds(Duration::from_secs(seconds))),
+            Raw::Text(text) => text.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+impl Serialize for Seconds {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl fmt::Display for Seconds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", humantime::format_duration(self.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_heights_in_every_unit() {
+        for (text, inches) in [
+            ("30", 30.0),
+            (" 30.5 ", 30.5),
+            ("30,5", 30.5),
+            ("30in", 30.0),
+            ("30 in", 30.0),
+            ("30\"", 30.0),
+            ("76.2cm", 30.0),
+            ("76,2 cm", 30.0),
+            ("762mm", 30.0),
+            ("0", 0.0),
+        ] {
+            assert_eq!(text.parse::<Inches>().unwrap(), Inches(inches), "{text}");
+        }
+    }
+
+    #[test]
+    fn rejects_bad_heights() {
+        for text in [
+            "-30",
+            "-76cm",
+            "30-",
+            "3-0",
+            "",
+            "cm",
+            "30ft",
+            "30 inches",
+            "30cmm",
+            "1.2.3",
+        ] {
+            assert!(text.parse::<Inches>().is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn negative_numbers_arent_heights() {
+        #[derive(Debug, Deserialize)]
+        struct Position {
+            #[allow(dead_code)]
+            height: Inches,
+        }
+
+        assert!(toml::from_str::<Position>("height = 30").is_ok());
+        assert!(toml::from_str::<Position>("height = -30").is_err());
+        assert!(toml::from_str::<Position>("height = \"-30\"").is_err());
+    }
+}
@@ -0,0 +1,51 @@
+//! WSL2 doesn't have Bluetooth, so inside WSL we forward commands to `uplift.exe` on the Windows
+//! side which WSL can run directly
+
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::PathBuf;
+use std::process::ExitStatus;
+
+use anyhow::Context;
+use tokio::process::Command;
+
+const WINDOWS_UPLIFT: &str = "uplift.exe";
+/// Set to `0` to stop forwarding, ie: if you've passed a Bluetooth adapter through to WSL
+pub const FORWARD_ENV: &str = "UPLIFT_WSL_FORWARD";
+
+pub const NO_BLUETOOTH_HELP: &str =
+    "WSL doesn't have Bluetooth, install uplift on Windows so `uplift.exe` is on your PATH";
+
+pub fn is_wsl() -> bool {
+    env::var_os("WSL_DISTRO_NAME").is_some()
+        || fs::read_to_string("/proc/sys/kernel/osrelease")
+            .is_ok_and(|release| release.to_lowercase().contains("microsoft"))
+}
+
+/// Run this invocation with `uplift.exe` if we're in WSL and it's on our PATH, returns `None` if we
+/// should run it ourselves
+pub async fn forward_to_windows() -> Result<Option<ExitStatus>, anyhow::Error> {
+    if !is_wsl() || env::var_os(FORWARD_ENV).is_some_and(|forward| forward == "0") {
+        return Ok(None);
+    }
+    let Some(uplift) = windows_uplift() else {
+        log::debug!("Running in WSL but couldn't find {WINDOWS_UPLIFT}");
+        return Ok(None);
+    };
+
+    log::debug!("Forwarding to {}", uplift.display());
+    let status = Command::new(&uplift)
+        .args(env::args_os().skip(1).collect::<Vec<OsString>>())
+        .status()
+        .await
+        .with_context(|| format!("Failed to run {}", uplift.display()))?;
+
+    Ok(Some(status))
+}
+
+fn windows_uplift() -> Option<PathBuf> {
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(WINDOWS_UPLIFT))
+        .find(|path| path.is_file())
+}